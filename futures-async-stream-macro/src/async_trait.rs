@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use syn::{Expr, ExprAsync, ExprCall, ExprIf, ExprLet, Result, ReturnType, Stmt, Token};
+
+use crate::parse::FnSig;
+
+// `#[async_trait]` on a trait or an impl expands before `#[stream]` or
+// `#[try_stream]` on its methods, and rewrites
+//
+//      async fn method(&self) {
+//          // ...
+//      }
+//
+// into:
+//
+//      fn method<'life0, 'async_trait>(
+//          &'life0 self,
+//      ) -> Pin<Box<dyn Future<Output = ()> + Send + 'async_trait>>
+//      where
+//          'life0: 'async_trait,
+//          Self: Sync + 'async_trait,
+//      {
+//          Box::pin(async move {
+//              let __self = self;
+//              let () = {
+//                  // ...
+//              };
+//          })
+//      }
+//
+// This is the expansion of async-trait 0.1.48 and later (tested up to 0.1.92;
+// newer versions also insert the type hint removed below). Older versions
+// call a nested function instead of an async block and are rejected.
+//
+// This converts it back to an async fn so that it can be expanded as usual.
+// The lifetimes and bounds added by `#[async_trait]` are kept as they are, so
+// the returned stream can capture everything that the future could capture.
+pub(crate) fn undo(item: &mut FnSig) -> Result<()> {
+    let sig = &mut item.sig;
+    if sig.asyncness.is_some()
+        || !sig.generics.lifetimes().any(|def| def.lifetime.ident == "async_trait")
+    {
+        return Ok(());
+    }
+
+    // Trait methods without a default implementation have no body.
+    if item.semi.is_none() {
+        let body = match &mut *item.block.stmts {
+            [Stmt::Expr(Expr::Call(ExprCall { args, .. }), None)] if args.len() == 1 => {
+                match args.pop().unwrap().into_value() {
+                    Expr::Async(ExprAsync { block, .. }) => Some(block),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let Some(mut body) = body else {
+            bail!(
+                item.block,
+                "failed to parse the function rewritten by #[async_trait]; \
+                 #[stream] and #[try_stream] require async-trait 0.1.48 or later"
+            );
+        };
+        // Remove the type hint of the return value:
+        // `if let Some(__ret) = None::<()> { return __ret; }`
+        if let Some(Stmt::Expr(Expr::If(ExprIf { cond, .. }), None)) = body.stmts.first() {
+            if let Expr::Let(ExprLet { expr, .. }) = &**cond {
+                if is_none(expr) {
+                    body.stmts.remove(0);
+                }
+            }
+        }
+        item.block.stmts = body.stmts;
+    }
+
+    sig.asyncness = Some(<Token![async]>::default());
+    sig.output = ReturnType::Default;
+    Ok(())
+}
+
+fn is_none(expr: &Expr) -> bool {
+    match expr {
+        Expr::Path(path) => {
+            path.path.segments.last().is_some_and(|segment| segment.ident == "None")
+        }
+        _ => false,
+    }
+}
@@ -2,8 +2,9 @@
 
 use proc_macro2::Span;
 use syn::{
-    FnArg, GenericArgument, GenericParam, Generics, Lifetime, LifetimeParam, Receiver, Token,
-    TypeReference,
+    FnArg, GenericArgument, GenericParam, Generics, Lifetime, LifetimeParam,
+    ParenthesizedGenericArguments, Receiver, Token, TypeBareFn, TypeParamBound, TypeReference,
+    parse_quote,
     punctuated::Punctuated,
     visit_mut::{self, VisitMut},
 };
@@ -74,6 +75,38 @@ impl VisitMut for UnelideLifetimes<'_> {
         }
         visit_mut::visit_generic_argument_mut(self, arg);
     }
+
+    // `impl Trait + '_` and `dyn Trait + '_`
+    fn visit_type_param_bound_mut(&mut self, bound: &mut TypeParamBound) {
+        if let TypeParamBound::Lifetime(lifetime) = bound {
+            self.visit_lifetime(lifetime);
+        }
+        visit_mut::visit_type_param_bound_mut(self, bound);
+    }
+
+    // Elided lifetimes in `fn(&T) -> &U` and `Fn(&T) -> &U` are higher-ranked,
+    // so they must not be replaced with the lifetimes of the function.
+    fn visit_type_bare_fn_mut(&mut self, _: &mut TypeBareFn) {}
+
+    fn visit_parenthesized_generic_arguments_mut(&mut self, _: &mut ParenthesizedGenericArguments) {
+    }
+}
+
+/// Adds a new lifetime that is outlived by all lifetimes of the function and
+/// returns it.
+///
+/// This is used as the lifetime of boxed trait objects, as `dyn Trait` can
+/// only have a single lifetime bound.
+pub(crate) fn intersect_lifetimes(generics: &mut Generics, lifetimes: &[Lifetime]) -> Lifetime {
+    let lifetime_name = determine_lifetime_name(generics);
+    let lifetime = Lifetime::new(&lifetime_name, Span::call_site());
+    let idx = generics.lifetimes().count();
+    generics.params.insert(idx, LifetimeParam::new(lifetime.clone()).into());
+    let where_clause = generics.make_where_clause();
+    for l in lifetimes {
+        where_clause.predicates.push(parse_quote!(#l: #lifetime));
+    }
+    lifetime
 }
 
 /// Determine the prefix for all lifetime names. Ensure it doesn't overlap with
@@ -21,31 +21,34 @@ mod error;
 #[macro_use]
 mod utils;
 
+mod async_trait;
 mod elision;
 mod parse;
 mod stream;
+mod stream_trait;
 mod visitor;
 
-use proc_macro::{Delimiter, Group, TokenStream, TokenTree};
+use proc_macro::TokenStream;
 use quote::ToTokens as _;
 use syn::{Error, Expr, ExprForLoop, parse_quote};
 
-use self::utils::{expr_async, parse_as_empty};
+use self::utils::{default_crate_path, parse_crate_arg};
 
 /// Processes streams using a for loop.
 ///
 /// See the crate-level documentation for details.
 #[proc_macro_attribute]
 pub fn for_await(args: TokenStream, input: TokenStream) -> TokenStream {
-    if let Err(e) = parse_as_empty(&args.into()) {
-        return e.to_compile_error().into();
-    }
+    let krate = match parse_crate_arg(args.into()) {
+        Ok(krate) => krate.unwrap_or_else(default_crate_path),
+        Err(e) => return e.to_compile_error().into(),
+    };
 
     let mut expr: ExprForLoop = syn::parse_macro_input!(input);
     expr.attrs.insert(0, parse_quote!(#[for_await]));
 
     let mut expr = Expr::ForLoop(expr);
-    visitor::Visitor::default().visit_for_loop(&mut expr);
+    visitor::Visitor::new(visitor::Scope::Future, krate).visit_for_loop(&mut expr);
 
     expr.into_token_stream().into()
 }
@@ -63,11 +66,9 @@ pub fn stream(args: TokenStream, input: TokenStream) -> TokenStream {
 /// Creates streams via coroutines. This is equivalent to `#[stream]` on async blocks.
 #[proc_macro]
 pub fn stream_block(input: TokenStream) -> TokenStream {
-    let input = TokenStream::from(TokenTree::Group(Group::new(Delimiter::Brace, input)));
-    let block = syn::parse_macro_input!(input);
-    let mut expr = expr_async(block);
-
-    stream::parse_async(&mut expr, parse::Context::Stream).into()
+    stream::block_macro(input.into(), parse::Context::Stream, &default_crate_path())
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
 }
 
 /// Creates streams via coroutines.
@@ -80,12 +81,21 @@ pub fn try_stream(args: TokenStream, input: TokenStream) -> TokenStream {
         .into()
 }
 
+/// Processes `#[stream]` and `#[try_stream]` methods in a trait or an impl,
+/// and generates an object-safe version of the trait.
+///
+/// See the crate-level documentation for details.
+#[proc_macro_attribute]
+pub fn stream_trait(args: TokenStream, input: TokenStream) -> TokenStream {
+    stream_trait::attribute(args.into(), input.into())
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
 /// Creates streams via coroutines. This is equivalent to `#[try_stream]` on async blocks.
 #[proc_macro]
 pub fn try_stream_block(input: TokenStream) -> TokenStream {
-    let input = TokenStream::from(TokenTree::Group(Group::new(Delimiter::Brace, input)));
-    let block = syn::parse_macro_input!(input);
-    let mut expr = expr_async(block);
-
-    stream::parse_async(&mut expr, parse::Context::TryStream).into()
+    stream::block_macro(input.into(), parse::Context::TryStream, &default_crate_path())
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
 }
@@ -8,7 +8,7 @@ use syn::{
     token,
 };
 
-use crate::{utils::SliceExt as _, visitor::Scope};
+use crate::{async_trait, utils::SliceExt as _, visitor::Scope};
 
 #[derive(Clone, Copy)]
 pub(crate) enum Context {
@@ -35,9 +35,10 @@ impl From<Context> for Scope {
 }
 
 pub(crate) fn parse(input: TokenStream, cx: Context) -> Result<FnOrAsync> {
-    let input = syn::parse2(input)?;
-    match &input {
+    let mut input = syn::parse2(input)?;
+    match &mut input {
         FnOrAsync::Fn(sig) => {
+            async_trait::undo(sig)?;
             validate_signature(Some(sig), &sig.attrs, cx)?;
             Ok(input)
         }
@@ -68,6 +69,18 @@ fn peek_signature(input: ParseStream<'_>) -> bool {
 fn validate_signature(item: Option<&FnSig>, attrs: &[Attribute], cx: Context) -> Result<()> {
     if let Some(item) = item {
         if item.sig.asyncness.is_none() {
+            if let ReturnType::Type(..) = &item.sig.output {
+                // The function may have been rewritten by another attribute macro that was
+                // expanded before this attribute, e.g., `#[tracing::instrument]` placed above
+                // `#[stream]`.
+                bail!(
+                    item.sig.output,
+                    "async stream must be declared as async, but this function is not async and \
+                     has a return type; if another attribute macro removed `async`, \
+                     place #[{}] above that attribute",
+                    cx.as_str()
+                );
+            }
             bail!(item.sig.fn_token, "async stream must be declared as async");
         }
         if let Some(constness) = &item.sig.constness {
@@ -111,10 +124,9 @@ impl Parse for FnOrAsync {
             let vis: Visibility = input.parse()?;
             let method: TraitItemFn = input.parse()?;
 
-            let mut fn_sig: FnSig = method.into();
+            let mut fn_sig = FnSig::new(vis, method);
             attrs.append(&mut fn_sig.attrs);
             fn_sig.attrs = attrs;
-            fn_sig.vis = vis;
 
             Ok(Self::Fn(Box::new(fn_sig)))
         } else if input.peek(Token![async]) {
@@ -142,14 +154,16 @@ pub(crate) struct FnSig {
     pub(crate) semi: Option<Token![;]>,
 }
 
-impl From<TraitItemFn> for FnSig {
-    fn from(item: TraitItemFn) -> Self {
+impl FnSig {
+    // `TraitItemFn` is used to parse both functions with and without bodies, but
+    // it has no visibility, so the visibility is parsed separately.
+    fn new(vis: Visibility, item: TraitItemFn) -> Self {
         if let Some(block) = item.default {
-            Self { attrs: item.attrs, vis: Visibility::Inherited, sig: item.sig, block, semi: None }
+            Self { attrs: item.attrs, vis, sig: item.sig, block, semi: None }
         } else {
             Self {
                 attrs: item.attrs,
-                vis: Visibility::Inherited,
+                vis,
                 sig: item.sig,
                 block: Block { brace_token: token::Brace::default(), stmts: vec![] },
                 semi: item.semi_token,
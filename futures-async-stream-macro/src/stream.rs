@@ -1,22 +1,28 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use proc_macro2::TokenStream;
-use quote::{ToTokens, quote, quote_spanned};
+use proc_macro2::{Literal, TokenStream};
+use quote::{ToTokens, format_ident, quote, quote_spanned};
 use syn::{
-    Block, ExprAsync, FnArg, Pat, PatIdent, PatType, Result, Signature, Stmt, Token, Type,
-    parse::{Parse, ParseStream},
+    Attribute, Block, Expr, ExprAsync, ExprCall, ExprLit, ExprMethodCall, ExprPath, ExprTry,
+    Fields, FnArg, Generics, Ident, Item, ItemEnum, Lifetime, Lit, Pat, PatIdent, PatType, Path,
+    Result, Signature, Stmt, StmtMacro, Token, Type, Visibility, bracketed,
+    ext::IdentExt as _,
+    parenthesized,
+    parse::{Parse, ParseStream, Parser as _},
     parse_quote,
     punctuated::Punctuated,
     spanned::Spanned as _,
     token,
-    visit_mut::VisitMut as _,
+    visit_mut::{self, VisitMut},
 };
 
 use crate::{
     elision,
     parse::{self, Context, FnOrAsync, FnSig},
-    utils::parse_as_empty,
-    visitor::Visitor,
+    utils::{
+        default_crate_path, expr_async, parse_crate_arg, parse_crate_path, replace_expr, respan,
+    },
+    visitor::{Scope, Visitor},
 };
 
 mod kw {
@@ -25,14 +31,34 @@ mod kw {
     syn::custom_keyword!(error);
     syn::custom_keyword!(boxed);
     syn::custom_keyword!(boxed_local);
+    syn::custom_keyword!(captures);
+    syn::custom_keyword!(recursive);
+    syn::custom_keyword!(max_size);
+    syn::custom_keyword!(auto_box_over);
+    syn::custom_keyword!(movable);
+    syn::custom_keyword!(clone);
+    syn::custom_keyword!(size_hint);
+    syn::custom_keyword!(budget);
+    syn::custom_keyword!(prefetch);
+    syn::custom_keyword!(eager);
+    syn::custom_keyword!(init_error);
+    syn::custom_keyword!(async_init);
+    syn::custom_keyword!(continue_on_error);
+    syn::custom_keyword!(output);
+    syn::custom_keyword!(context);
+    syn::custom_keyword!(catch_panic);
+    syn::custom_keyword!(checkpoint);
+    syn::custom_keyword!(stoppable);
+    syn::custom_keyword!(must_consume);
+    syn::custom_keyword!(outputs);
 }
 
 pub(crate) fn attribute(args: TokenStream, input: TokenStream, cx: Context) -> Result<TokenStream> {
     match parse::parse(input, cx)? {
         FnOrAsync::Fn(sig) => parse_fn(args, sig, cx),
         FnOrAsync::Async(mut expr, semi) => {
-            parse_as_empty(&args)?;
-            let mut tokens = parse_async(&mut expr, cx);
+            let krate = parse_crate_arg(args)?.unwrap_or_else(default_crate_path);
+            let mut tokens = parse_async(&mut expr, cx, &krate);
             if let Some(semi) = semi {
                 semi.to_tokens(&mut tokens);
             }
@@ -42,43 +68,240 @@ pub(crate) fn attribute(args: TokenStream, input: TokenStream, cx: Context) -> R
     }
 }
 
-pub(crate) fn parse_async(expr: &mut ExprAsync, cx: Context) -> TokenStream {
-    Visitor::new(cx.into()).visit_expr_async_mut(expr);
-    make_gen_body(expr.capture.as_ref(), &expr.block, cx, None, false)
+pub(crate) fn parse_async(expr: &mut ExprAsync, cx: Context, krate: &Path) -> TokenStream {
+    let mut visitor = Visitor::new(cx.into(), krate.clone());
+    visitor.visit_expr_async_mut(expr);
+    make_gen_body(
+        expr.capture.as_ref(),
+        &expr.block,
+        cx,
+        None,
+        None,
+        None,
+        false,
+        CoroutineKind::Static,
+        None,
+        visitor.uses_size_hint(),
+        krate,
+    )
+}
+
+/// Expands `stream_block!` and `try_stream_block!`.
+///
+/// The input can start with `crate = <path>;` to specify the path to the
+/// `futures_async_stream` crate. Otherwise, `krate` is used.
+pub(crate) fn block_macro(input: TokenStream, cx: Context, krate: &Path) -> Result<TokenStream> {
+    let (custom_krate, stmts) = (|input: ParseStream<'_>| {
+        let krate = if input.peek(Token![crate]) && input.peek2(Token![=]) {
+            let krate = parse_crate_path(input, false)?;
+            let _: Token![;] = input.parse()?;
+            Some(krate)
+        } else {
+            None
+        };
+        Ok((krate, input.call(Block::parse_within)?))
+    })
+    .parse2(input)?;
+
+    let mut expr = expr_async(Block { brace_token: token::Brace::default(), stmts });
+    Ok(parse_async(&mut expr, cx, custom_krate.as_ref().unwrap_or(krate)))
 }
 
 #[derive(Clone, Copy)]
-enum ReturnTypeKind {
+pub(crate) enum ReturnTypeKind {
     // impl Stream<Item = ..> $(+ $lifetime)?
     Default,
     // Pin<Box<dyn Stream<Item = ..> (+ Send)? $(+ $lifetime)?>>
     Boxed { send: bool },
+    // impl Stream<Item = ..> (+ Send)?
+    //
+    // Used for methods in `#[stream_trait]`. Unlike `Default`, return-position
+    // `impl Trait` in traits captures all generic parameters in scope.
+    Trait { send: bool },
 }
 
 impl ReturnTypeKind {
-    fn parse_or_else<F>(&mut self, input: ParseStream<'_>, mut f: F) -> Result<()>
+    fn is_boxed(self) -> bool {
+        matches!(self, Self::Boxed { .. })
+    }
+}
+
+/// Arguments accepted by both `#[stream]` and `#[try_stream]`.
+struct CommonArgs {
+    boxed: ReturnTypeKind,
+    // captures = [<lifetime or ident>, ...]
+    captures: Option<Vec<CapturedParam>>,
+    // recursive
+    recursive: bool,
+    // max_size = <expr>
+    max_size: Option<Expr>,
+    // auto_box_over = <expr>
+    auto_box_over: Option<Expr>,
+    // movable
+    movable: bool,
+    // clone
+    clone: bool,
+    // size_hint = <expr>
+    size_hint: Option<Expr>,
+    // budget = <expr>
+    budget: Option<Expr>,
+    // prefetch = <expr>
+    prefetch: Option<Expr>,
+    // eager
+    eager: bool,
+    // init_error = <type>
+    init_error: Option<Type>,
+    // async_init
+    async_init: bool,
+    // stoppable
+    stoppable: Option<kw::stoppable>,
+    // must_consume
+    must_consume: bool,
+    // crate = <path>
+    krate: Option<Path>,
+}
+
+impl CommonArgs {
+    fn parse_or_else<F>(input: ParseStream<'_>, mut f: F) -> Result<Self>
     where
         F: FnMut(ParseStream<'_>) -> Result<()>,
     {
+        let mut boxed = ReturnTypeKind::Default;
+        let mut captures = None;
+        let mut recursive = false;
+        let mut max_size = None;
+        let mut auto_box_over = None;
+        let mut movable = false;
+        let mut clone = None;
+        let mut size_hint = None;
+        let mut budget = None;
+        let mut prefetch = None;
+        let mut eager = None;
+        let mut init_error = None;
+        let mut async_init = None;
+        let mut stoppable = None;
+        let mut must_consume = false;
+        let mut krate = None;
         while !input.is_empty() {
             if input.peek(kw::boxed) {
                 let i: kw::boxed = input.parse()?;
-                match self {
-                    Self::Default => *self = Self::Boxed { send: true },
-                    Self::Boxed { send: true } => bail!(i, "duplicate `boxed` argument"),
-                    Self::Boxed { send: false } => {
+                if auto_box_over.is_some() {
+                    bail!(
+                        i,
+                        "`auto_box_over` may not be used together with `boxed` or `boxed_local`"
+                    );
+                }
+                match boxed {
+                    ReturnTypeKind::Default => boxed = ReturnTypeKind::Boxed { send: true },
+                    ReturnTypeKind::Boxed { send: true } => bail!(i, "duplicate `boxed` argument"),
+                    ReturnTypeKind::Boxed { send: false } | ReturnTypeKind::Trait { .. } => {
                         bail!(i, "`boxed` and `boxed_local` may not be used at the same time");
                     }
                 }
             } else if input.peek(kw::boxed_local) {
                 let i: kw::boxed_local = input.parse()?;
-                match self {
-                    Self::Default => *self = Self::Boxed { send: false },
-                    Self::Boxed { send: false } => bail!(i, "duplicate `boxed_local` argument"),
-                    Self::Boxed { send: true } => {
+                if auto_box_over.is_some() {
+                    bail!(
+                        i,
+                        "`auto_box_over` may not be used together with `boxed` or `boxed_local`"
+                    );
+                }
+                match boxed {
+                    ReturnTypeKind::Default => boxed = ReturnTypeKind::Boxed { send: false },
+                    ReturnTypeKind::Boxed { send: false } => {
+                        bail!(i, "duplicate `boxed_local` argument");
+                    }
+                    ReturnTypeKind::Boxed { send: true } | ReturnTypeKind::Trait { .. } => {
                         bail!(i, "`boxed` and `boxed_local` may not be used at the same time");
                     }
                 }
+            } else if input.peek(kw::captures) {
+                // captures = [<lifetime or ident>, ...]
+                let i: kw::captures = input.parse()?;
+                captures = Some(parse_captures(input, i, captures.is_some())?);
+            } else if input.peek(kw::recursive) {
+                let i: kw::recursive = input.parse()?;
+                if recursive {
+                    bail!(i, "duplicate `recursive` argument");
+                }
+                recursive = true;
+            } else if input.peek(kw::max_size) {
+                // max_size = <expr>
+                let i: kw::max_size = input.parse()?;
+                max_size = Some(parse_expr_arg(input, &i, "<size>", max_size.is_some())?);
+            } else if input.peek(kw::auto_box_over) {
+                // auto_box_over = <expr>
+                let i: kw::auto_box_over = input.parse()?;
+                if boxed.is_boxed() {
+                    bail!(
+                        i,
+                        "`auto_box_over` may not be used together with `boxed` or `boxed_local`"
+                    );
+                }
+                auto_box_over = Some(parse_expr_arg(input, &i, "<size>", auto_box_over.is_some())?);
+            } else if input.peek(kw::movable) {
+                let i: kw::movable = input.parse()?;
+                if movable {
+                    bail!(i, "duplicate `movable` argument");
+                }
+                movable = true;
+            } else if input.peek(kw::clone) {
+                let i: kw::clone = input.parse()?;
+                if clone.is_some() {
+                    bail!(i, "duplicate `clone` argument");
+                }
+                clone = Some(i);
+            } else if input.peek(kw::size_hint) {
+                // size_hint = <expr>
+                let i: kw::size_hint = input.parse()?;
+                size_hint =
+                    Some(parse_expr_arg(input, &i, "(<lower>, <upper>)", size_hint.is_some())?);
+            } else if input.peek(kw::budget) {
+                // budget = <expr>
+                let i: kw::budget = input.parse()?;
+                let count = parse_expr_arg(input, &i, "<count>", budget.is_some())?;
+                // A stream with no budget would yield `Poll::Pending` forever.
+                if let Expr::Lit(ExprLit { lit: Lit::Int(lit), .. }) = &count {
+                    if lit.base10_parse::<u128>().is_ok_and(|count| count == 0) {
+                        bail!(count, "`budget` must be greater than zero");
+                    }
+                }
+                budget = Some(count);
+            } else if input.peek(kw::prefetch) {
+                // prefetch = <expr>
+                let i: kw::prefetch = input.parse()?;
+                prefetch = Some(parse_expr_arg(input, &i, "<count>", prefetch.is_some())?);
+            } else if input.peek(kw::eager) {
+                let i: kw::eager = input.parse()?;
+                if eager.is_some() {
+                    bail!(i, "duplicate `eager` argument");
+                }
+                eager = Some(i);
+            } else if input.peek(kw::init_error) {
+                // init_error = <type>
+                let i: kw::init_error = input.parse()?;
+                init_error = Some(parse_value(input, &i, init_error.is_some())?.0);
+            } else if input.peek(kw::async_init) {
+                let i: kw::async_init = input.parse()?;
+                if async_init.is_some() {
+                    bail!(i, "duplicate `async_init` argument");
+                }
+                async_init = Some(i);
+            } else if input.peek(kw::stoppable) {
+                let i: kw::stoppable = input.parse()?;
+                if stoppable.is_some() {
+                    bail!(i, "duplicate `stoppable` argument");
+                }
+                stoppable = Some(i);
+            } else if input.peek(kw::must_consume) {
+                let i: kw::must_consume = input.parse()?;
+                if must_consume {
+                    bail!(i, "duplicate `must_consume` argument");
+                }
+                must_consume = true;
+            } else if input.peek(Token![crate]) {
+                // crate = <path>
+                krate = Some(parse_crate_path(input, krate.is_some())?);
             } else {
                 f(input)?;
             }
@@ -89,12 +312,136 @@ impl ReturnTypeKind {
             let _: Token![,] = input.parse()?;
         }
 
-        Ok(())
+        if let Some(clone) = clone {
+            // Trait objects and `AutoBoxed` do not implement `Clone`.
+            if boxed.is_boxed() || auto_box_over.is_some() {
+                bail!(
+                    clone,
+                    "`clone` may not be used together with `boxed`, `boxed_local`, or `auto_box_over`"
+                );
+            }
+            if prefetch.is_some() {
+                bail!(clone, "`clone` may not be used together with `prefetch`");
+            }
+            // Only movable coroutines can implement `Clone`.
+            movable = true;
+        }
+        if let Some(eager) = eager {
+            if prefetch.is_none() {
+                bail!(eager, "`eager` requires `prefetch = <count>`");
+            }
+            // The stream starts running before it is returned.
+            if !movable {
+                bail!(eager, "`eager` requires `movable`");
+            }
+        }
+        if let Some(async_init) = async_init {
+            if init_error.is_none() {
+                bail!(async_init, "`async_init` requires `init_error = <type>`");
+            }
+        }
+        if let (Some(error), true) = (&init_error, recursive) {
+            bail!(error, "`init_error` may not be used together with `recursive`");
+        }
+        // Recursive calls are wrapped in a boxed stream, but a `stoppable`
+        // function returns a `(stream, StopHandle)` tuple, not a stream.
+        if let (Some(stoppable), true) = (stoppable, recursive) {
+            bail!(stoppable, "`stoppable` may not be used together with `recursive`");
+        }
+
+        Ok(Self {
+            boxed,
+            captures,
+            recursive,
+            max_size,
+            auto_box_over,
+            movable,
+            clone: clone.is_some(),
+            size_hint,
+            budget,
+            prefetch,
+            eager: eager.is_some(),
+            init_error,
+            async_init: async_init.is_some(),
+            stoppable,
+            must_consume,
+            krate,
+        })
     }
+}
 
-    fn is_boxed(self) -> bool {
-        matches!(self, Self::Boxed { .. })
+/// A generic parameter listed in the `captures` argument.
+enum CapturedParam {
+    Lifetime(Lifetime),
+    Ident(Ident),
+}
+
+impl Parse for CapturedParam {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        if input.peek(Lifetime) {
+            input.parse().map(Self::Lifetime)
+        } else {
+            input.parse().map(Self::Ident)
+        }
+    }
+}
+
+impl ToTokens for CapturedParam {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            Self::Lifetime(lifetime) => lifetime.to_tokens(tokens),
+            Self::Ident(ident) => ident.to_tokens(tokens),
+        }
+    }
+}
+
+// Parses `= [<param>, ...]` in `captures = [<param>, ...]`.
+fn parse_captures(
+    input: ParseStream<'_>,
+    name: kw::captures,
+    has_prev: bool,
+) -> Result<Vec<CapturedParam>> {
+    if input.is_empty() || !input.peek(Token![=]) {
+        bail!(name, "expected `captures = [..]`, found `captures`");
+    }
+    let eq_token: Token![=] = input.parse()?;
+    if !input.peek(token::Bracket) {
+        let span = quote!(#name #eq_token);
+        bail!(span, "expected `captures = [..]`, found `captures =`");
+    }
+    let content;
+    let bracket_token = bracketed!(content in input);
+    let params = content.parse_terminated(CapturedParam::parse, Token![,])?;
+    if has_prev {
+        let mut span = quote!(#name);
+        bracket_token.surround(&mut span, |tokens| params.to_tokens(tokens));
+        bail!(span, "duplicate `captures` argument");
+    }
+    Ok(params.into_iter().collect())
+}
+
+// Parses `= <expr>` in `<name> = <expr>`. `expected` is the placeholder of the
+// value used in error messages.
+fn parse_expr_arg(
+    input: ParseStream<'_>,
+    name: &impl ToTokens,
+    expected: &str,
+    has_prev: bool,
+) -> Result<Expr> {
+    if input.is_empty() || !input.peek(Token![=]) {
+        bail!(name, "expected `{0} = {1}`, found `{0}`", name.to_token_stream(), expected);
+    }
+    let eq_token: Token![=] = input.parse()?;
+    if input.is_empty() || input.peek(Token![,]) {
+        let span = quote!(#name #eq_token);
+        bail!(span, "expected `{0} = {1}`, found `{0} =`", name.to_token_stream(), expected);
+    }
+    let value: Expr = input.parse()?;
+    if has_prev {
+        let span = quote!(#name #eq_token #value);
+        bail!(span, "duplicate `{}` argument", name.to_token_stream());
     }
+    Ok(value)
 }
 
 // Parses `= <value>` in `<name> = <value>` and returns value and span of name-value pair.
@@ -119,22 +466,126 @@ fn parse_value(
     Ok((value, span))
 }
 
+/// The `outputs(<name> = <type>, ...)` argument of `#[stream]`.
+pub(crate) struct OutputsArg {
+    token: kw::outputs,
+    pub(crate) outputs: Vec<(Ident, Type)>,
+}
+
+/// The maximum number of outputs, which is the largest tuple that implements
+/// `Channels`.
+const MAX_OUTPUTS: usize = 8;
+
+// Parses `(<name> = <type>, ...)` in `outputs(<name> = <type>, ...)`.
+fn parse_outputs(input: ParseStream<'_>, token: kw::outputs, has_prev: bool) -> Result<OutputsArg> {
+    if !input.peek(token::Paren) {
+        bail!(token, "expected `outputs(<name> = <type>, ...)`, found `outputs`");
+    }
+    let content;
+    let paren_token = parenthesized!(content in input);
+    let pairs = content.parse_terminated(
+        |input| {
+            let name: Ident = input.parse()?;
+            let (ty, _) = parse_value(input, &name, false)?;
+            Ok((name, ty))
+        },
+        Token![,],
+    )?;
+    let (names, tys): (Vec<_>, Vec<_>) = pairs.iter().cloned().unzip();
+    let mut span = quote!(#token);
+    paren_token.surround(&mut span, |tokens| quote!(#(#names = #tys),*).to_tokens(tokens));
+    if has_prev {
+        bail!(span, "duplicate `outputs` argument");
+    }
+    if pairs.is_empty() {
+        bail!(span, "`outputs` requires at least one output");
+    }
+    if pairs.len() > MAX_OUTPUTS {
+        bail!(span, "`outputs` supports at most {} outputs", MAX_OUTPUTS);
+    }
+    let mut outputs: Vec<(Ident, Type)> = vec![];
+    for (name, ty) in pairs {
+        if outputs.iter().any(|(prev, _)| *prev == name) {
+            bail!(name, "duplicate output `{}`", name);
+        }
+        outputs.push((name, ty));
+    }
+    Ok(OutputsArg { token, outputs })
+}
+
+// Parses `= <enum>` in `item = <enum>`.
+//
+// Each variant of the enum must have a single unnamed field, and the types of
+// the fields must be distinct, so that `yield` can convert them with `From`.
+fn parse_item_enum(input: ParseStream<'_>, name: kw::item, has_prev: bool) -> Result<ItemEnum> {
+    let eq_token: Token![=] = input.parse()?;
+    let item: ItemEnum = input.parse()?;
+    if has_prev {
+        let span = quote!(#name #eq_token #item);
+        bail!(span, "duplicate `item` argument");
+    }
+    let mut tys: Vec<&Type> = vec![];
+    for variant in &item.variants {
+        let ty = match &variant.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => &fields.unnamed[0].ty,
+            _ => bail!(
+                variant,
+                "variants of `item` enum must have a single unnamed field, e.g., `{}(T)`",
+                variant.ident
+            ),
+        };
+        if let Some(discriminant) = &variant.discriminant {
+            bail!(discriminant.1, "variants of `item` enum may not have discriminants");
+        }
+        let ty_string = ty.to_token_stream().to_string();
+        if tys.iter().any(|prev| prev.to_token_stream().to_string() == ty_string) {
+            bail!(ty, "duplicate type `{}` in `item` enum", ty_string);
+        }
+        tys.push(ty);
+    }
+    Ok(item)
+}
+
 struct StreamArg {
+    // The `item` type, or `()` if `outputs` is passed.
     item_ty: Type,
-    boxed: ReturnTypeKind,
+    // item = <enum>
+    item_enum: Option<ItemEnum>,
+    outputs: Option<OutputsArg>,
+    common: CommonArgs,
 }
 
 impl Parse for StreamArg {
     fn parse(input: ParseStream<'_>) -> Result<Self> {
         let mut item_ty = None;
-        let mut boxed = ReturnTypeKind::Default;
-        boxed.parse_or_else(input, |input| {
+        let mut item_enum = None;
+        let mut outputs = None;
+        let common = CommonArgs::parse_or_else(input, |input| {
             if input.peek(kw::item) {
-                // item = <Type>
                 let i: kw::item = input.parse()?;
-                item_ty = Some(parse_value(input, &i, item_ty.is_some())?.0);
+                let fork = input.fork();
+                let is_enum = fork.parse::<Token![=]>().is_ok()
+                    && fork.call(Attribute::parse_outer).is_ok()
+                    && fork.parse::<Visibility>().is_ok()
+                    && fork.peek(Token![enum]);
+                if is_enum {
+                    // item = <enum>
+                    let item = parse_item_enum(input, i, item_ty.is_some())?;
+                    let name = &item.ident;
+                    let (_, ty_generics, _) = item.generics.split_for_impl();
+                    item_ty = Some(parse_quote!(#name #ty_generics));
+                    item_enum = Some(item);
+                } else {
+                    // item = <Type>
+                    item_ty = Some(parse_value(input, &i, item_ty.is_some())?.0);
+                }
+                Ok(())
+            } else if input.peek(kw::outputs) {
+                // outputs(<name> = <type>, ...)
+                let i: kw::outputs = input.parse()?;
+                outputs = Some(parse_outputs(input, i, outputs.is_some())?);
                 Ok(())
-            } else if item_ty.is_none() {
+            } else if item_ty.is_none() && outputs.is_none() {
                 input.parse::<kw::item>().map(|_| unreachable!())
             } else {
                 let token = input.parse::<TokenStream>()?;
@@ -142,26 +593,48 @@ impl Parse for StreamArg {
             }
         })?;
 
-        if let Some(item_ty) = item_ty {
-            Ok(Self { item_ty, boxed })
-        } else {
-            input.parse::<kw::item>().map(|_| unreachable!())
+        match (item_ty, outputs) {
+            (Some(_), Some(outputs)) => {
+                bail!(outputs.token, "`outputs` may not be used together with `item`")
+            }
+            (Some(item_ty), None) => Ok(Self { item_ty, item_enum, outputs: None, common }),
+            (None, Some(outputs)) => Ok(Self {
+                item_ty: parse_quote!(()),
+                item_enum: None,
+                outputs: Some(outputs),
+                common,
+            }),
+            (None, None) => input.parse::<kw::item>().map(|_| unreachable!()),
         }
     }
 }
 
 struct TryStreamArg {
+    // `ok` type, or `output` type if `output` is true.
     ok: Type,
-    error: Type,
-    boxed: ReturnTypeKind,
+    error: Option<Type>,
+    output: bool,
+    // continue_on_error
+    continue_on_error: bool,
+    // context
+    context: bool,
+    // catch_panic
+    catch_panic: bool,
+    // checkpoint = <type>
+    checkpoint: Option<Type>,
+    common: CommonArgs,
 }
 
 impl Parse for TryStreamArg {
     fn parse(input: ParseStream<'_>) -> Result<Self> {
         let mut ok = None;
         let mut error = None;
-        let mut boxed = ReturnTypeKind::Default;
-        boxed.parse_or_else(input, |input| {
+        let mut output = None;
+        let mut continue_on_error = false;
+        let mut context = false;
+        let mut catch_panic = false;
+        let mut checkpoint = None;
+        let common = CommonArgs::parse_or_else(input, |input| {
             if input.peek(kw::ok) {
                 // ok = <Type>
                 let i: kw::ok = input.parse()?;
@@ -172,6 +645,40 @@ impl Parse for TryStreamArg {
                 let i: kw::error = input.parse()?;
                 error = Some(parse_value(input, &i, error.is_some())?.0);
                 Ok(())
+            } else if input.peek(kw::output) {
+                // output = <Type>
+                let i: kw::output = input.parse()?;
+                output = Some(parse_value(input, &i, output.is_some())?.0);
+                Ok(())
+            } else if input.peek(kw::continue_on_error) {
+                let i: kw::continue_on_error = input.parse()?;
+                if continue_on_error {
+                    bail!(i, "duplicate `continue_on_error` argument");
+                }
+                continue_on_error = true;
+                Ok(())
+            } else if input.peek(kw::context) {
+                let i: kw::context = input.parse()?;
+                if context {
+                    bail!(i, "duplicate `context` argument");
+                }
+                context = true;
+                Ok(())
+            } else if input.peek(kw::catch_panic) {
+                let i: kw::catch_panic = input.parse()?;
+                if catch_panic {
+                    bail!(i, "duplicate `catch_panic` argument");
+                }
+                catch_panic = true;
+                Ok(())
+            } else if input.peek(kw::checkpoint) {
+                // checkpoint = <type>
+                let i: kw::checkpoint = input.parse()?;
+                checkpoint = Some(parse_value(input, &i, checkpoint.is_some())?.0);
+                Ok(())
+            } else if output.is_some() {
+                let token = input.parse::<TokenStream>()?;
+                bail!(token, "unexpected argument: {}", token)
             } else if ok.is_none() {
                 input.parse::<kw::ok>().map(|_| unreachable!())
             } else if error.is_none() {
@@ -182,111 +689,740 @@ impl Parse for TryStreamArg {
             }
         })?;
 
+        if let Some(output) = output {
+            if let Some(ty) = ok.as_ref().or(error.as_ref()) {
+                bail!(ty, "`output` may not be used together with `ok` or `error`");
+            }
+            if continue_on_error {
+                bail!(output, "`continue_on_error` may not be used together with `output`");
+            }
+            if context {
+                bail!(output, "`context` may not be used together with `output`");
+            }
+            if catch_panic {
+                bail!(output, "`catch_panic` may not be used together with `output`");
+            }
+            if checkpoint.is_some() {
+                bail!(output, "`checkpoint` may not be used together with `output`");
+            }
+            return Ok(Self {
+                ok: output,
+                error: None,
+                output: true,
+                continue_on_error,
+                context,
+                catch_panic,
+                checkpoint,
+                common,
+            });
+        }
+        if let (Some(checkpoint), true) = (&checkpoint, catch_panic) {
+            bail!(checkpoint, "`checkpoint` may not be used together with `catch_panic`");
+        }
         match (ok, error) {
-            (Some(ok), Some(error)) => Ok(Self { ok, error, boxed }),
+            (Some(ok), Some(error)) => Ok(Self {
+                ok,
+                error: Some(error),
+                output: false,
+                continue_on_error,
+                context,
+                catch_panic,
+                checkpoint,
+                common,
+            }),
             (Some(_), None) => input.parse::<kw::error>().map(|_| unreachable!()),
             (None, _) => input.parse::<kw::ok>().map(|_| unreachable!()),
         }
     }
 }
 
-fn parse_fn(args: TokenStream, sig: Box<FnSig>, cx: Context) -> Result<TokenStream> {
-    Ok(match cx {
-        Context::Stream => {
-            let StreamArg { item_ty, boxed } = syn::parse2(args)?;
-            let trait_ = quote! {
-                ::futures_async_stream::__private::stream::Stream<Item = #item_ty>
-            };
-            parse_fn_inner(sig, cx, None, boxed.is_boxed(), |lifetimes| match boxed {
-                ReturnTypeKind::Default => {
-                    // Raw `impl` breaks syntax highlighting in some editors.
-                    let impl_token = token::Impl::default();
-                    quote! {
-                        #impl_token #trait_ + #lifetimes
-                    }
+/// The arguments of `#[stream]` or `#[try_stream]` on functions.
+pub(crate) struct FnArgs {
+    // The `Item` type of `#[stream]`, or the `Ok` or `output` type of `#[try_stream]`.
+    pub(crate) item_ty: Type,
+    // The `Err` type of `#[try_stream]`.
+    pub(crate) error: Option<Type>,
+    // Whether `item_ty` is the `output` type of `#[try_stream]`.
+    output: bool,
+    continue_on_error: bool,
+    context: bool,
+    catch_panic: bool,
+    checkpoint: Option<Type>,
+    pub(crate) boxed: ReturnTypeKind,
+    captures: Option<Vec<CapturedParam>>,
+    recursive: bool,
+    max_size: Option<Expr>,
+    auto_box_over: Option<Expr>,
+    movable: bool,
+    clone: bool,
+    size_hint: Option<Expr>,
+    budget: Option<Expr>,
+    prefetch: Option<Expr>,
+    eager: bool,
+    init_error: Option<Type>,
+    async_init: bool,
+    stoppable: Option<kw::stoppable>,
+    must_consume: bool,
+    outputs: Option<OutputsArg>,
+    // The enum generated for `item = <enum>`. `item_ty` is the enum type.
+    item_enum: Option<ItemEnum>,
+    pub(crate) krate: Path,
+}
+
+impl FnArgs {
+    /// Parses the arguments. If the `crate` argument is not passed, `krate` or
+    /// the default path is used.
+    pub(crate) fn parse(args: TokenStream, cx: Context, krate: Option<&Path>) -> Result<Self> {
+        let (
+            item_ty,
+            error,
+            output,
+            continue_on_error,
+            context,
+            catch_panic,
+            checkpoint,
+            outputs,
+            item_enum,
+            common,
+        ) = match cx {
+            Context::Stream => {
+                let StreamArg { item_ty, item_enum, outputs, common } = syn::parse2(args)?;
+                (item_ty, None, false, false, false, false, None, outputs, item_enum, common)
+            }
+            Context::TryStream => {
+                let TryStreamArg {
+                    ok,
+                    error,
+                    output,
+                    continue_on_error,
+                    context,
+                    catch_panic,
+                    checkpoint,
+                    common,
+                } = syn::parse2(args)?;
+                (
+                    ok,
+                    error,
+                    output,
+                    continue_on_error,
+                    context,
+                    catch_panic,
+                    checkpoint,
+                    None,
+                    None,
+                    common,
+                )
+            }
+        };
+        let CommonArgs {
+            boxed,
+            captures,
+            recursive,
+            max_size,
+            auto_box_over,
+            movable,
+            clone,
+            size_hint,
+            budget,
+            prefetch,
+            eager,
+            init_error,
+            async_init,
+            stoppable,
+            must_consume,
+            krate: custom_krate,
+        } = common;
+        // The checkpoint is read from the stream returned by the function, so
+        // it cannot be wrapped in another stream.
+        if let Some(checkpoint) = &checkpoint {
+            if prefetch.is_some() {
+                bail!(checkpoint, "`checkpoint` may not be used together with `prefetch`");
+            }
+            if auto_box_over.is_some() {
+                bail!(checkpoint, "`checkpoint` may not be used together with `auto_box_over`");
+            }
+            if must_consume {
+                bail!(checkpoint, "`checkpoint` may not be used together with `must_consume`");
+            }
+        }
+        // The output streams share the coroutine, so the stream cannot be
+        // boxed or wrapped in another stream.
+        if let Some(outputs) = &outputs {
+            let conflicts = [
+                (matches!(boxed, ReturnTypeKind::Boxed { send: true }), "boxed"),
+                (matches!(boxed, ReturnTypeKind::Boxed { send: false }), "boxed_local"),
+                (recursive, "recursive"),
+                (max_size.is_some(), "max_size"),
+                (auto_box_over.is_some(), "auto_box_over"),
+                (clone, "clone"),
+                (size_hint.is_some(), "size_hint"),
+                (prefetch.is_some(), "prefetch"),
+                (stoppable.is_some(), "stoppable"),
+                (must_consume, "must_consume"),
+            ];
+            if let Some((_, name)) = conflicts.iter().find(|(conflict, _)| *conflict) {
+                bail!(outputs.token, "`outputs` may not be used together with `{}`", name);
+            }
+        }
+        let krate = custom_krate.or_else(|| krate.cloned()).unwrap_or_else(default_crate_path);
+        Ok(Self {
+            item_ty,
+            error,
+            output,
+            continue_on_error,
+            context,
+            catch_panic,
+            checkpoint,
+            boxed,
+            captures,
+            recursive,
+            max_size,
+            auto_box_over,
+            movable,
+            clone,
+            size_hint,
+            budget,
+            prefetch,
+            eager,
+            init_error,
+            async_init,
+            stoppable,
+            must_consume,
+            outputs,
+            item_enum,
+            krate,
+        })
+    }
+
+    /// Returns the return type of the function `ident`.
+    ///
+    /// With the `outputs` argument, this is the struct generated for the
+    /// outputs (see `outputs_struct`), whose fields are the output streams.
+    ///
+    /// With the `stoppable` argument, the stream type is paired with
+    /// `StopHandle`.
+    ///
+    /// With the `init_error` argument, this is `Result` of the stream type, or
+    /// a future that outputs it if `async_init` is also passed.
+    pub(crate) fn return_ty(&self, ident: &Ident, bounds: &TokenStream) -> TokenStream {
+        let krate = &self.krate;
+        let item_ty = &self.item_ty;
+        let item = match &self.error {
+            Some(error) => quote!(#krate::__private::Result<#item_ty, #error>),
+            None if self.output => {
+                quote!(<#item_ty as #krate::__private::try_stream::TryOutput>::Item)
+            }
+            None => quote!(#item_ty),
+        };
+        let trait_ = match &self.checkpoint {
+            Some(checkpoint) => quote! {
+                #krate::__private::checkpoint::CheckpointStream<
+                    Item = #item,
+                    Checkpoint = #checkpoint
+                >
+            },
+            None if self.must_consume => quote! {
+                #krate::__private::MustConsume<Item = #item>
+            },
+            None => quote!(#krate::__private::stream::Stream<Item = #item>),
+        };
+        // Raw `impl` breaks syntax highlighting in some editors.
+        let impl_token = token::Impl::default();
+        let clone = if self.clone { Some(quote!(+ #krate::__private::Clone)) } else { None };
+        let mut stream_ty = match self.boxed {
+            ReturnTypeKind::Default if self.outputs.is_some() => {
+                let name = outputs_struct_ident(ident);
+                let streams = self.outputs.iter().flat_map(|arg| &arg.outputs).map(|(_, ty)| {
+                    quote!(#impl_token #krate::__private::stream::Stream<Item = #ty> + #bounds)
+                });
+                quote!(#name<#(#streams),*>)
+            }
+            ReturnTypeKind::Default => quote! {
+                #impl_token #trait_ #clone + #bounds
+            },
+            ReturnTypeKind::Boxed { send } => {
+                let send = if send { Some(quote!(+ #krate::__private::Send)) } else { None };
+                quote! {
+                    #krate::__private::Pin<#krate::__private::Box<
+                        dyn #trait_ #send + #bounds
+                    >>
                 }
-                ReturnTypeKind::Boxed { send } => {
-                    let send = if send {
-                        Some(quote!(+ ::futures_async_stream::__private::Send))
-                    } else {
-                        None
-                    };
-                    quote! {
-                        ::futures_async_stream::__private::Pin<Box<
-                            dyn #trait_ #send + #lifetimes
-                        >>
-                    }
+            }
+            ReturnTypeKind::Trait { send } => {
+                let send = if send { Some(quote!(+ #krate::__private::Send)) } else { None };
+                quote! {
+                    #impl_token #trait_ #send #clone
                 }
-            })
+            }
+        };
+        if self.stoppable.is_some() {
+            stream_ty = quote!((#stream_ty, #krate::__private::StopHandle));
         }
-        Context::TryStream => {
-            let TryStreamArg { ok, error, boxed } = syn::parse2(args)?;
-            parse_fn_inner(sig, cx, Some(&error), boxed.is_boxed(), |lifetimes| {
-                let trait_ = quote! {
-                    ::futures_async_stream::__private::stream::Stream<
-                        Item = ::futures_async_stream::__private::Result<#ok, #error>
-                    >
-                };
-                match boxed {
-                    ReturnTypeKind::Default => {
-                        // Raw `impl` breaks syntax highlighting in some editors.
-                        let impl_token = token::Impl::default();
-                        quote! {
-                            #impl_token #trait_ + #lifetimes
-                        }
-                    }
-                    ReturnTypeKind::Boxed { send } => {
-                        let send = if send {
-                            Some(quote!(+ ::futures_async_stream::__private::Send))
-                        } else {
-                            None
-                        };
-                        quote! {
-                            ::futures_async_stream::__private::Pin<Box<
-                                dyn #trait_ #send + #lifetimes
-                            >>
-                        }
-                    }
-                }
-            })
+        match &self.init_error {
+            None => stream_ty,
+            Some(error) if self.async_init => quote! {
+                #impl_token #krate::__private::future::Future<
+                    Output = #krate::__private::Result<#stream_ty, #error>
+                > + #bounds
+            },
+            Some(error) => quote! {
+                #krate::__private::Result<#stream_ty, #error>
+            },
         }
-    })
+    }
 }
 
-fn parse_fn_inner(
-    sig: Box<FnSig>,
-    cx: Context,
-    error: Option<&Type>,
-    boxed: bool,
-    return_ty: impl FnOnce(TokenStream) -> TokenStream,
-) -> TokenStream {
-    let FnSig { attrs, vis, sig, mut block, semi } = *sig;
+fn parse_fn(args: TokenStream, sig: Box<FnSig>, cx: Context) -> Result<TokenStream> {
+    let args = FnArgs::parse(args, cx, None)?;
+    parse_fn_inner(sig, cx, &args)
+}
+
+pub(crate) fn parse_fn_inner(sig: Box<FnSig>, cx: Context, args: &FnArgs) -> Result<TokenStream> {
+    let FnSig { mut attrs, vis, sig, mut block, semi } = *sig;
     let Signature { unsafety, abi, fn_token, ident, mut generics, inputs, .. } = sig;
 
+    // The struct of `outputs` and the enum of `item = <enum>` are defined next
+    // to the function, which is not possible in impl and trait blocks.
+    if let Some(FnArg::Receiver(_)) = inputs.first() {
+        if let Some(outputs) = &args.outputs {
+            bail!(outputs.token, "`outputs` argument is not supported on associated functions");
+        }
+        if let Some(item) = &args.item_enum {
+            bail!(item, "`item` enum is not supported on associated functions");
+        }
+    }
+
+    // The stream returned by a recursive call contains itself, so it must be
+    // boxed. Boxed streams are already type-erased.
+    if args.recursive && !args.boxed.is_boxed() {
+        BoxRecursiveCalls { ident: &ident, krate: &args.krate }.visit_block_mut(&mut block);
+    }
+
+    let mut prologue = take_prologue(&mut block, args)?;
+
+    // This runs before the visitor, so the errors yielded by `?` in loops with
+    // `continue_on_error` also have the location.
+    if args.context {
+        let error = args.error.as_ref().unwrap();
+        AddLocation { krate: &args.krate, error, function: &ident }.visit_block_mut(&mut block);
+    }
+
     // Visit `#[for_await]`, `.await`, and `yield`.
-    Visitor::new(cx.into()).visit_block_mut(&mut block);
+    let mut visitor = Visitor::new(cx.into(), args.krate.clone())
+        .movable(args.movable)
+        .budget(args.budget.is_some())
+        .output(args.output)
+        .checkpoint(args.checkpoint.clone())
+        .stoppable(args.stoppable.is_some())
+        .continue_on_error(args.continue_on_error)
+        .outputs(args.outputs.as_ref().map(|outputs| outputs.outputs.clone()))
+        .item_enum(args.item_enum.as_ref().map(|_| args.item_ty.clone()));
+    visitor.visit_block_mut(&mut block);
 
+    let krate = &args.krate;
     let (mut arguments, mut statements) = expand_async_body(inputs);
+    if let Some(prologue) = &mut prologue {
+        // The prologue is not a part of the stream. `.await` can only be used
+        // in it with `async_init`.
+        let scope = if args.async_init { Scope::Future } else { Scope::Closure };
+        Visitor::new(scope, krate.clone()).visit_block_mut(prologue);
+        // The prologue sees the arguments in the same way as the body of the stream.
+        let mut stmts = statements.clone();
+        for stmt in &mut stmts {
+            if let Stmt::Local(local) = stmt {
+                // The prologue may not use all the arguments.
+                local.attrs.push(parse_quote!(#[allow(unused_variables)]));
+            }
+        }
+        stmts.append(&mut prologue.stmts);
+        prologue.stmts = stmts;
+    }
+    if let Some(count) = &args.budget {
+        let budget = def_site_ident!("__budget");
+        statements.push(parse_quote! {
+            let mut #budget = #krate::__private::stream::Budget::new(#count);
+        });
+    }
     statements.append(&mut block.stmts);
     block.stmts = statements;
 
-    let body_inner = make_gen_body(Some(&<Token![move]>::default()), &block, cx, error, boxed);
+    let coroutine = if args.clone {
+        CoroutineKind::Clone
+    } else if args.movable {
+        CoroutineKind::Movable
+    } else {
+        CoroutineKind::Static
+    };
+    let mut body_inner = make_gen_body(
+        Some(&<Token![move]>::default()),
+        &block,
+        cx,
+        args.error.as_ref(),
+        args.output.then_some(&args.item_ty),
+        args.checkpoint.as_ref(),
+        args.outputs.is_some(),
+        coroutine,
+        args.size_hint.as_ref(),
+        visitor.uses_size_hint(),
+        krate,
+    );
+    if args.stoppable.is_some() {
+        // `request_stop` wakes the task that polled the stream last. The handle
+        // is cloned before the coroutine moves `__stop`.
+        let stop = def_site_ident!("__stop");
+        body_inner = quote! {
+            #krate::__private::stoppable(#krate::__private::Clone::clone(&#stop), #body_inner)
+        };
+    }
+    if let Some(outputs) = &args.outputs {
+        let name = outputs_struct_ident(&ident);
+        let handle = def_site_ident!("__outputs");
+        let fields = outputs.outputs.iter().enumerate().map(|(i, (field, _))| {
+            let i = Literal::usize_unsuffixed(i);
+            quote!(#field: #handle.output::<#i>())
+        });
+        body_inner = quote! {{
+            let #handle = #body_inner;
+            #name { #(#fields),* }
+        }};
+    }
+    if args.catch_panic {
+        let catch_panic = if args.clone { quote!(catch_panic_clone) } else { quote!(catch_panic) };
+        body_inner = quote!(#krate::__private::try_stream::#catch_panic(#body_inner));
+    }
+    if let Some(count) = &args.prefetch {
+        let prefetch = if args.eager { quote!(prefetch_eager) } else { quote!(prefetch) };
+        body_inner = quote!(#krate::__private::#prefetch::<_, { #count }>(#body_inner));
+    }
+    if let Some(auto_box_over) = &args.auto_box_over {
+        body_inner = quote!(#krate::__private::auto_box::<_, { #auto_box_over }>(#body_inner));
+    }
+    if let Some(max_size) = &args.max_size {
+        body_inner = quote!(#krate::__private::assert_size::<_, { #max_size }>(#body_inner));
+    }
+    if args.must_consume {
+        // `cfg` is evaluated in the caller's crate, so the check is removed in
+        // release builds of it.
+        let (checked, unchecked) = if args.clone {
+            (quote!(must_consume_clone), quote!(must_consume_unchecked_clone))
+        } else {
+            (quote!(must_consume), quote!(must_consume_unchecked))
+        };
+        let function = ident.to_string();
+        let stream = def_site_ident!("__stream");
+        body_inner = quote! {{
+            #[cfg(debug_assertions)]
+            let #stream = #krate::__private::#checked(#body_inner, #function);
+            #[cfg(not(debug_assertions))]
+            let #stream = #krate::__private::#unchecked(#body_inner);
+            #stream
+        }};
+        // The location of the caller is reported as the location where the
+        // stream was created. It is not available in the future returned by
+        // an `async_init` function.
+        if !args.async_init && abi.is_none() {
+            attrs.push(parse_quote!(#[cfg_attr(debug_assertions, track_caller)]));
+        }
+    }
+    if args.boxed.is_boxed() {
+        body_inner = quote!(#krate::__private::Box::pin(#body_inner));
+    }
+    if args.stoppable.is_some() {
+        // `yield_or_stop!` in the body of the stream refers to `__stop`.
+        let stop = def_site_ident!("__stop");
+        let handle = def_site_ident!("__stop_handle");
+        body_inner = quote! {{
+            let #stop = #krate::__private::StopHandle::default();
+            let #handle = #krate::__private::Clone::clone(&#stop);
+            (#body_inner, #handle)
+        }};
+    }
+    if let Some(prologue) = prologue {
+        let error = &args.init_error;
+        let stmts = prologue.stmts;
+        body_inner = quote! {
+            #(#stmts)*
+            #krate::__private::Ok::<_, #error>(#body_inner)
+        };
+        if args.async_init {
+            body_inner = quote!(async move { #body_inner });
+            // The async block is generated, so the lint is not actionable for users.
+            attrs.push(parse_quote!(#[allow(clippy::manual_async_fn)]));
+        }
+    }
     let mut body = TokenStream::new();
     block.brace_token.surround(&mut body, |tokens| {
         body_inner.to_tokens(tokens);
     });
 
     elision::unelide_lifetimes(&mut generics, &mut arguments);
+    let return_ty = args.return_ty(&ident, &return_bounds(&mut generics, args)?);
     let where_clause = &generics.where_clause;
-    let lifetimes = generics.lifetimes().map(|def| &def.lifetime);
-    let return_ty = return_ty(quote!(#(#lifetimes +)*));
 
     let body = semi.map_or(body, ToTokens::into_token_stream);
-    quote! {
+    let outputs_struct = args.outputs.as_ref().map(|outputs| outputs_struct(&vis, &ident, outputs));
+    let item_enum = args.item_enum.as_ref().map(|item| item_enum(&vis, &ident, item, krate));
+    Ok(quote! {
+        #outputs_struct
+        #item_enum
         #(#attrs)*
         #vis #unsafety #abi #fn_token #ident #generics (#(#arguments),*) -> #return_ty
         #where_clause
         #body
+    })
+}
+
+// Returns the name of the struct generated for the `outputs` argument of the
+// function `ident`, e.g., `FooBarOutputs` for `foo_bar`.
+fn outputs_struct_ident(ident: &Ident) -> Ident {
+    format_ident!("{}Outputs", to_upper_camel_case(&ident.unraw().to_string()), span = ident.span())
+}
+
+fn to_upper_camel_case(s: &str) -> String {
+    s.split('_')
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            chars.next().map(|c| c.to_ascii_uppercase()).into_iter().chain(chars)
+        })
+        .collect()
+}
+
+// Generates the struct returned by a function with the `outputs` argument.
+//
+// The struct has a field for each output, and the type of each field is a
+// type parameter, because the output streams are `impl Stream`.
+fn outputs_struct(vis: &Visibility, ident: &Ident, outputs: &OutputsArg) -> TokenStream {
+    let name = outputs_struct_ident(ident);
+    let doc = format!(
+        "The outputs of [`{ident}`].\n\n\
+         The items yielded to an output are buffered until it is polled, and \
+         the buffers are unbounded. Consume all the outputs concurrently, or \
+         drop the ones that are not needed."
+    );
+    let (fields, params): (Vec<_>, Vec<_>) = outputs
+        .outputs
+        .iter()
+        .map(|(field, _)| {
+            let doc = format!("The `{field}` output of [`{ident}`].");
+            let param = format_ident!("{}", to_upper_camel_case(&field.unraw().to_string()));
+            (quote!(#[doc = #doc] #vis #field: #param), param)
+        })
+        .unzip();
+    quote! {
+        #[doc = #doc]
+        #[must_use = "streams do nothing unless polled"]
+        #vis struct #name<#(#params),*> {
+            #(#fields),*
+        }
+    }
+}
+
+// Generates the enum of `item = <enum>` and the `From` impls for the types of
+// its variants.
+//
+// If the visibility or the documentation of the enum is omitted, those of the
+// function are used.
+fn item_enum(vis: &Visibility, ident: &Ident, item: &ItemEnum, krate: &Path) -> TokenStream {
+    let mut item = item.clone();
+    if let Visibility::Inherited = item.vis {
+        item.vis = vis.clone();
+    }
+    if !item.attrs.iter().any(|attr| attr.path().is_ident("doc")) {
+        let doc = format!("The items of [`{ident}`].");
+        item.attrs.push(parse_quote!(#[doc = #doc]));
+    }
+    let name = &item.ident;
+    let (impl_generics, ty_generics, where_clause) = item.generics.split_for_impl();
+    let impls = item.variants.iter().map(|variant| {
+        let variant_ident = &variant.ident;
+        let ty = &variant.fields.iter().next().unwrap().ty;
+        // The type of the variant may not use all the lifetimes of the enum.
+        quote! {
+            #[allow(single_use_lifetimes)]
+            impl #impl_generics #krate::__private::From<#ty> for #name #ty_generics #where_clause {
+                #[inline]
+                fn from(value: #ty) -> Self {
+                    Self::#variant_ident(value)
+                }
+            }
+        }
+    });
+    quote! {
+        #item
+        #(#impls)*
+    }
+}
+
+// Removes `init! { .. }` at the start of the body and returns its statements.
+// `init!` anywhere else in the top level of the body is rejected.
+//
+// Returns `None` if the `init_error` argument is not passed.
+fn take_prologue(block: &mut Block, args: &FnArgs) -> Result<Option<Block>> {
+    let mut prologue = Block { brace_token: block.brace_token, stmts: vec![] };
+    if let Some(Stmt::Macro(StmtMacro { mac, .. })) = block.stmts.first() {
+        if mac.path.is_ident("init") {
+            if args.init_error.is_none() {
+                bail!(mac, "`init!` requires `init_error = <type>` argument");
+            }
+            prologue.stmts = mac.parse_body_with(Block::parse_within)?;
+            block.stmts.remove(0);
+        }
+    }
+    for stmt in &block.stmts {
+        if let Stmt::Macro(StmtMacro { mac, .. }) = stmt {
+            if mac.path.is_ident("init") {
+                bail!(mac, "`init!` must be the first statement");
+            }
+        }
+    }
+    Ok(args.init_error.is_some().then_some(prologue))
+}
+
+// Returns the bounds that tie the returned stream to the generic parameters it captures.
+//
+// Without the `captures` argument, all lifetimes (including unelided ones) are captured.
+// With it, `impl Stream` gets the precise capturing bound (`use<..>`) and boxed streams
+// are bounded only by the listed lifetimes.
+fn return_bounds(generics: &mut Generics, args: &FnArgs) -> Result<TokenStream> {
+    if let ReturnTypeKind::Trait { .. } = args.boxed {
+        if let Some(captures) = &args.captures {
+            bail!(
+                quote!(#(#captures),*),
+                "`captures` argument is not supported in #[stream_trait]"
+            );
+        }
+        if let Some(error) = &args.init_error {
+            bail!(error, "`init_error` argument is not supported in #[stream_trait]");
+        }
+        if let Some(stoppable) = &args.stoppable {
+            bail!(stoppable, "`stoppable` argument is not supported in #[stream_trait]");
+        }
+        if let Some(outputs) = &args.outputs {
+            bail!(outputs.token, "`outputs` argument is not supported in #[stream_trait]");
+        }
+        if let Some(item) = &args.item_enum {
+            bail!(item, "`item` enum is not supported in #[stream_trait]");
+        }
+        // Return-position `impl Trait` in traits captures all generic parameters.
+        return Ok(TokenStream::new());
+    }
+
+    let lifetimes: Vec<_> = match &args.captures {
+        // Like async fn, `impl Trait` in the return type implicitly captures all
+        // lifetimes, including those hidden in paths, without requiring the stream
+        // to outlive them. This relies on the edition 2024 capture rules, which
+        // apply because the tokens are generated by this crate.
+        None if !args.boxed.is_boxed() => return Ok(TokenStream::new()),
+        None => generics.lifetimes().map(|def| def.lifetime.clone()).collect(),
+        // The listed parameters may also come from the enclosing `impl` block,
+        // so it is left to rustc to check that they are in scope.
+        Some(captures) => {
+            let lifetimes = captures.iter().filter_map(|param| match param {
+                CapturedParam::Lifetime(l) => Some(l.clone()),
+                CapturedParam::Ident(_) => None,
+            });
+            if !args.boxed.is_boxed() {
+                // Lifetimes must be listed before other generic parameters in `use<..>`.
+                let others =
+                    captures.iter().filter(|param| matches!(param, CapturedParam::Ident(_)));
+                return Ok(quote!(use<#(#lifetimes,)* #(#others),*> +));
+            }
+            lifetimes.collect()
+        }
+    };
+
+    // A trait object can only have a single lifetime bound.
+    Ok(match &*lifetimes {
+        [] => TokenStream::new(),
+        [lifetime] => quote!(#lifetime +),
+        _ => {
+            let lifetime = elision::intersect_lifetimes(generics, &lifetimes);
+            quote!(#lifetime +)
+        }
+    })
+}
+
+// Replaces calls to the function itself (`f(..)`, `Self::f(..)`, and `self.f(..)`)
+// with `Box::pin(f(..))`.
+struct BoxRecursiveCalls<'a> {
+    ident: &'a Ident,
+    krate: &'a Path,
+}
+
+impl BoxRecursiveCalls<'_> {
+    fn is_recursive_call(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Call(ExprCall { func, .. }) => match &**func {
+                Expr::Path(ExprPath { qself: None, path, .. }) => match path.segments.len() {
+                    1 => path.is_ident(self.ident),
+                    2 => path.segments[0].ident == "Self" && path.segments[1].ident == *self.ident,
+                    _ => false,
+                },
+                _ => false,
+            },
+            Expr::MethodCall(ExprMethodCall { receiver, method, .. }) => {
+                method == self.ident
+                    && matches!(&**receiver, Expr::Path(ExprPath { path, .. }) if path.is_ident("self"))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl VisitMut for BoxRecursiveCalls<'_> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        visit_mut::visit_expr_mut(self, expr);
+        if self.is_recursive_call(expr) {
+            let krate = self.krate;
+            replace_expr(expr, |expr| parse_quote!(#krate::__private::Box::pin(#expr)));
+        }
+    }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {
+        // Do not recurse into nested items.
+    }
+}
+
+// Replaces `<expr>?` with:
+//
+// with_location::<_, _, <error>>(<expr>, Location::new("<function>", file!(), line!(), column!()))?
+struct AddLocation<'a> {
+    krate: &'a Path,
+    error: &'a Type,
+    function: &'a Ident,
+}
+
+impl VisitMut for AddLocation<'_> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        // `?` in closures and async blocks returns from them.
+        if let Expr::Closure(_) | Expr::Async(_) = expr {
+            return;
+        }
+
+        visit_mut::visit_expr_mut(self, expr);
+        if let Expr::Try(ExprTry { expr: e, question_token, .. }) = expr {
+            let error = self.error;
+            let function = self.function.to_string();
+            // `file!()`, `line!()`, and `column!()` report the location of the
+            // outermost macro call, so all tokens of their paths must have the
+            // span of `?` instead of the span of the attribute.
+            let krate = respan(self.krate.to_token_stream(), question_token.span);
+            let location = quote_spanned! { question_token.span =>
+                #krate::__private::Location::new(
+                    #function,
+                    #krate::__private::file!(),
+                    #krate::__private::line!(),
+                    #krate::__private::column!(),
+                )
+            };
+            **e = parse_quote! {
+                #krate::__private::with_location::<_, _, #error>(#e, #location)
+            };
+        }
+    }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {
+        // Do not recurse into nested items.
     }
 }
 
@@ -350,35 +1486,95 @@ fn expand_async_body(inputs: Punctuated<FnArg, Token![,]>) -> (Vec<FnArg>, Vec<S
     (arguments, statements)
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum CoroutineKind {
+    // `static move |..| { .. }`
+    Static,
+    // `move |..| { .. }`
+    Movable,
+    // `move |..| { .. }`, and the returned stream implements `Clone`.
+    Clone,
+}
+
 fn make_gen_body(
     capture: Option<&Token![move]>,
     block: &Block,
     cx: Context,
     error: Option<&Type>,
-    boxed: bool,
+    output: Option<&Type>,
+    checkpoint: Option<&Type>,
+    outputs: bool,
+    coroutine: CoroutineKind,
+    size_hint: Option<&Expr>,
+    uses_size_hint: bool,
+    krate: &Path,
 ) -> TokenStream {
-    let (gen_function, ret_value, ret_ty) = match cx {
-        Context::Stream => (
-            quote!(::futures_async_stream::__private::stream::from_coroutine),
-            TokenStream::new(),
-            quote!(()),
+    let (module, ret_value, ret_ty) = match cx {
+        Context::Stream if outputs => {
+            (quote!(#krate::__private::outputs), TokenStream::new(), quote!(()))
+        }
+        Context::Stream => (quote!(#krate::__private::stream), TokenStream::new(), quote!(())),
+        Context::TryStream if output.is_some() => (
+            quote!(#krate::__private::try_stream),
+            quote!(#krate::__private::try_stream::complete::<#output>()),
+            quote!(<#output as #krate::__private::try_stream::TryOutput>::Return),
         ),
         Context::TryStream => {
             let error = error.map_or_else(|| quote!(_), ToTokens::to_token_stream);
+            let module = if checkpoint.is_some() {
+                quote!(#krate::__private::checkpoint)
+            } else {
+                quote!(#krate::__private::try_stream)
+            };
             (
-                quote!(::futures_async_stream::__private::try_stream::from_coroutine),
-                quote!(::futures_async_stream::__private::Ok(())),
-                quote!(::futures_async_stream::__private::Result<(), #error>),
+                module,
+                quote!(#krate::__private::Ok(())),
+                quote!(#krate::__private::Result<(), #error>),
             )
         }
     };
+    // Only the streams that use the size hint store it, so the other streams
+    // are not larger than necessary.
+    let size_hint = match size_hint {
+        Some(size_hint) => Some(size_hint.to_token_stream()),
+        None if uses_size_hint => Some(quote!((0, #krate::__private::None))),
+        None => None,
+    };
+    let gen_function = match (coroutine, output) {
+        (CoroutineKind::Clone, None) => "from_clone_coroutine",
+        (_, None) => "from_coroutine",
+        (CoroutineKind::Clone, Some(_)) => "from_clone_output_coroutine",
+        (_, Some(_)) => "from_output_coroutine",
+    };
+    let gen_function = if size_hint.is_some() {
+        format_ident!("{gen_function}_with_size_hint")
+    } else {
+        format_ident!("{gen_function}")
+    };
+    let gen_function = match (output, checkpoint) {
+        (Some(output), _) => quote!(#module::#gen_function::<_, #output>),
+        (None, Some(checkpoint)) => quote!(#module::#gen_function::<_, _, _, #checkpoint>),
+        (None, None) => quote!(#module::#gen_function),
+    };
+    let static_token =
+        if coroutine == CoroutineKind::Static { Some(Token![static](block.span())) } else { None };
+    let resume_ext = match (checkpoint, &size_hint) {
+        (Some(_), Some(_)) => quote! {
+            <#krate::__private::future::WithCheckpoint<#krate::__private::future::WithSizeHint>>
+        },
+        (Some(_), None) => quote!(<#krate::__private::future::WithCheckpoint>),
+        (None, Some(_)) => quote!(<#krate::__private::future::WithSizeHint>),
+        (None, None) => TokenStream::new(),
+    };
+    let size_hint = size_hint.map(|size_hint| quote!(#size_hint,));
 
     let task_context = def_site_ident!("__task_context");
-    let body = quote_spanned! { block.span() =>
+    quote_spanned! { block.span() =>
         #gen_function(
+            #size_hint
             #[coroutine]
-            static #capture |
-                mut #task_context: ::futures_async_stream::__private::future::ResumeTy,
+            #static_token #capture |
+                mut #task_context: #krate::__private::future::ResumeTy #resume_ext,
             | -> #ret_ty {
                 let (): () = #block;
 
@@ -388,12 +1584,10 @@ fn make_gen_body(
                 {
                     return #ret_value;
                     loop {
-                        #task_context = yield ::futures_async_stream::__private::Poll::Pending;
+                        #task_context = yield #krate::__private::Poll::Pending;
                     }
                 }
             }
         )
-    };
-
-    if boxed { quote!(Box::pin(#body)) } else { body }
+    }
 }
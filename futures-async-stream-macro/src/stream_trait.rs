@@ -0,0 +1,283 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use proc_macro2::{Span, TokenStream, TokenTree};
+use quote::{ToTokens, format_ident, quote};
+use syn::{
+    Attribute, FnArg, GenericParam, Generics, Ident, ImplItem, ImplItemFn, Item, ItemImpl,
+    ItemTrait, Lifetime, Meta, Pat, PatIdent, PatType, Path, Receiver, Result, Signature,
+    TraitItem, parse_quote,
+};
+
+use crate::{
+    elision,
+    parse::{self, Context, FnOrAsync},
+    stream::{self, FnArgs, ReturnTypeKind},
+    utils::{SliceExt as _, default_crate_path, parse_crate_arg},
+};
+
+pub(crate) fn attribute(args: TokenStream, input: TokenStream) -> Result<TokenStream> {
+    let krate = parse_crate_arg(args)?;
+    match syn::parse2(input)? {
+        Item::Trait(item) => expand_trait(item, krate.as_ref()),
+        Item::Impl(item) => expand_impl(item, krate.as_ref()),
+        _ => bail!(
+            // Highlight the attribute itself, like `derive` and `proc_macro` do.
+            TokenStream::new(),
+            "#[stream_trait] attribute may only be used on traits or impls"
+        ),
+    }
+}
+
+// Expands
+//
+//      #[stream_trait]
+//      pub trait Foo {
+//          #[stream(item = i32)]
+//          async fn method(&self);
+//      }
+//
+// into:
+//
+//      pub trait Foo {
+//          fn method<'_async0>(&'_async0 self) -> impl Stream<Item = i32> + Send;
+//      }
+//
+//      pub trait DynFoo {
+//          fn method<'_async0>(&'_async0 self)
+//              -> Pin<Box<dyn Stream<Item = i32> + Send + '_async0>>;
+//      }
+//
+//      impl<__Self: Foo + ?Sized> DynFoo for __Self {
+//          fn method<'_async0>(&'_async0 self)
+//              -> Pin<Box<dyn Stream<Item = i32> + Send + '_async0>>
+//          {
+//              Box::pin(<__Self as Foo>::method(self))
+//          }
+//      }
+fn expand_trait(mut item: ItemTrait, krate: Option<&Path>) -> Result<TokenStream> {
+    let trait_ident = &item.ident;
+    let (_, ty_generics, _) = item.generics.split_for_impl();
+    let trait_path = quote!(#trait_ident #ty_generics);
+    let self_ty = def_site_ident!("__Self");
+
+    let mut dyn_methods = vec![];
+    let mut dyn_impls = vec![];
+    for trait_item in &mut item.items {
+        let TraitItem::Fn(method) = trait_item else { continue };
+        let Some((cx, args)) = take_stream_args(&mut method.attrs)? else { continue };
+        let mut args = FnArgs::parse(args, cx, krate)?;
+
+        let FnOrAsync::Fn(sig) = parse::parse(method.to_token_stream(), cx)? else {
+            unreachable!()
+        };
+        let send = !matches!(args.boxed, ReturnTypeKind::Boxed { send: false });
+
+        args.boxed = ReturnTypeKind::Boxed { send };
+        let (dyn_method, dyn_impl) =
+            dyn_method(&sig.attrs, &sig.sig, &args, &trait_path, &item.generics, &self_ty)?;
+        dyn_methods.push(dyn_method);
+        dyn_impls.push(dyn_impl);
+
+        args.boxed = ReturnTypeKind::Trait { send };
+        *trait_item = TraitItem::Verbatim(stream::parse_fn_inner(sig, cx, &args)?);
+    }
+
+    let vis = &item.vis;
+    let dyn_ident = format_ident!("Dyn{}", trait_ident);
+    let doc = format!(
+        "An object-safe version of [`{trait_ident}`].\n\n\
+         This trait is implemented for all types that implement [`{trait_ident}`]. \
+         The stream methods of this trait return boxed streams.",
+    );
+    let generics = &item.generics;
+    let where_clause = &generics.where_clause;
+    let mut impl_generics = generics.clone();
+    for param in &mut impl_generics.params {
+        match param {
+            GenericParam::Type(param) => param.default = None,
+            GenericParam::Const(param) => param.default = None,
+            GenericParam::Lifetime(_) => {}
+        }
+    }
+    let krate = krate.cloned().unwrap_or_else(default_crate_path);
+    impl_generics.params.push(parse_quote!(#self_ty: #trait_path + ?#krate::__private::Sized));
+
+    Ok(quote! {
+        #item
+
+        #[doc = #doc]
+        #vis trait #dyn_ident #generics #where_clause {
+            #(#dyn_methods)*
+        }
+
+        impl #impl_generics #dyn_ident #ty_generics for #self_ty #where_clause {
+            #(#dyn_impls)*
+        }
+    })
+}
+
+fn expand_impl(mut item: ItemImpl, krate: Option<&Path>) -> Result<TokenStream> {
+    for impl_item in &mut item.items {
+        let ImplItem::Fn(method) = impl_item else { continue };
+        let Some((cx, args)) = take_stream_args(&mut method.attrs)? else { continue };
+        let mut args = FnArgs::parse(args, cx, krate)?;
+
+        let ImplItemFn { attrs, vis, defaultness, sig, block } = method;
+        let method = quote!(#(#attrs)* #vis #defaultness #sig #block);
+        let FnOrAsync::Fn(sig) = parse::parse(method, cx)? else { unreachable!() };
+        let send = !matches!(args.boxed, ReturnTypeKind::Boxed { send: false });
+
+        args.boxed = ReturnTypeKind::Trait { send };
+        *impl_item = ImplItem::Verbatim(stream::parse_fn_inner(sig, cx, &args)?);
+    }
+
+    Ok(item.into_token_stream())
+}
+
+// Removes `#[stream]` or `#[try_stream]` attribute and returns its kind and arguments.
+fn take_stream_args(attrs: &mut Vec<Attribute>) -> Result<Option<(Context, TokenStream)>> {
+    let (i, cx) = if let Some(i) = attrs.position_unique("stream")? {
+        (i, Context::Stream)
+    } else if let Some(i) = attrs.position_unique("try_stream")? {
+        (i, Context::TryStream)
+    } else {
+        return Ok(None);
+    };
+
+    let args = match attrs.remove(i).meta {
+        Meta::Path(_) => TokenStream::new(),
+        Meta::List(list) => list.tokens,
+        Meta::NameValue(nv) => bail!(nv.eq_token, "expected list"),
+    };
+    Ok(Some((cx, args)))
+}
+
+// Returns the declaration of the boxed method in the companion trait and its
+// implementation that forwards to the original method.
+fn dyn_method(
+    attrs: &[Attribute],
+    sig: &Signature,
+    args: &FnArgs,
+    trait_path: &TokenStream,
+    trait_generics: &Generics,
+    self_ty: &Ident,
+) -> Result<(TokenStream, TokenStream)> {
+    // `Self` in the companion trait refers to the type that implements the
+    // companion trait, so it cannot be used to access associated items of the
+    // original trait.
+    let self_in_types = sig
+        .inputs
+        .iter()
+        .filter_map(|arg| match arg {
+            FnArg::Typed(arg) => Some(arg.ty.to_token_stream()),
+            FnArg::Receiver(_) => None,
+        })
+        .chain(Some(args.item_ty.to_token_stream()))
+        .chain(args.error.as_ref().map(ToTokens::to_token_stream))
+        .find_map(find_self);
+    if let Some(self_token) = self_in_types {
+        bail!(
+            self_token,
+            "`Self` may not be used in the argument or item types of stream methods in #[stream_trait]"
+        );
+    }
+
+    let Signature { unsafety, abi, fn_token, ident, generics, inputs, .. } = sig;
+    let krate = &args.krate;
+    let mut generics = generics.clone();
+    let mut has_receiver = false;
+    let mut call_args = vec![];
+    let mut arguments: Vec<_> = inputs
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| match arg {
+            FnArg::Receiver(receiver) => {
+                has_receiver = true;
+                call_args.push(receiver.self_token.to_token_stream());
+                FnArg::Receiver(Receiver { attrs: vec![], ..receiver.clone() })
+            }
+            FnArg::Typed(PatType { ty, colon_token, .. }) => {
+                // Patterns are not allowed in functions without bodies.
+                let ident = def_site_ident!("__arg{}", i);
+                call_args.push(ident.to_token_stream());
+                FnArg::Typed(PatType {
+                    attrs: vec![],
+                    pat: Box::new(Pat::Ident(PatIdent {
+                        attrs: vec![],
+                        by_ref: None,
+                        mutability: None,
+                        ident,
+                        subpat: None,
+                    })),
+                    colon_token: *colon_token,
+                    ty: ty.clone(),
+                })
+            }
+        })
+        .collect();
+
+    // Generic methods and methods without a receiver cannot be called on trait
+    // objects, so exclude them from the vtable to keep the trait object-safe.
+    let generic_params: Vec<_> = generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Type(param) => Some(&param.ident),
+            GenericParam::Const(param) => Some(&param.ident),
+            GenericParam::Lifetime(_) => None,
+        })
+        .cloned()
+        .collect();
+    if !has_receiver || !generic_params.is_empty() {
+        generics.make_where_clause().predicates.push(parse_quote!(Self: #krate::__private::Sized));
+    }
+    let turbofish =
+        if generic_params.is_empty() { None } else { Some(quote!(::<#(#generic_params),*>)) };
+
+    elision::unelide_lifetimes(&mut generics, &mut arguments);
+    let lifetimes: Vec<_> = generics.lifetimes().map(|def| def.lifetime.clone()).collect();
+    let lifetime = match &*lifetimes {
+        [] => Lifetime::new("'static", Span::call_site()),
+        [lifetime] => lifetime.clone(),
+        _ => elision::intersect_lifetimes(&mut generics, &lifetimes),
+    };
+    // The stream returned by the original method captures all generic parameters,
+    // so they must outlive the trait object.
+    let type_params: Vec<_> = trait_generics
+        .type_params()
+        .chain(generics.type_params())
+        // Use call-site spans so that lints do not treat these bounds as written by the user.
+        .map(|param| Ident::new(&param.ident.to_string(), Span::call_site()))
+        .collect();
+    let where_clause = generics.make_where_clause();
+    where_clause.predicates.push(parse_quote!(Self: #lifetime));
+    for param in type_params {
+        where_clause.predicates.push(parse_quote!(#param: #lifetime));
+    }
+    let return_ty = args.return_ty(ident, &quote!(#lifetime +));
+    let where_clause = &generics.where_clause;
+
+    let signature = quote! {
+        #(#attrs)*
+        #unsafety #abi #fn_token #ident #generics (#(#arguments),*) -> #return_ty
+        #where_clause
+    };
+    let call = quote!(<#self_ty as #trait_path>::#ident #turbofish(#(#call_args),*));
+    let call = if unsafety.is_some() { quote!(unsafe { #call }) } else { call };
+    Ok((
+        quote!(#signature;),
+        quote! {
+            #signature {
+                #krate::__private::Box::pin(#call)
+            }
+        },
+    ))
+}
+
+fn find_self(tokens: TokenStream) -> Option<TokenTree> {
+    tokens.into_iter().find_map(|tt| match tt {
+        TokenTree::Ident(ref ident) if ident == "Self" => Some(tt),
+        TokenTree::Group(group) => find_self(group.stream()),
+        _ => None,
+    })
+}
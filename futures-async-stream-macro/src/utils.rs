@@ -2,9 +2,12 @@
 
 use std::mem;
 
-use proc_macro2::TokenStream;
+use proc_macro2::{Group, Span, TokenStream, TokenTree};
 use syn::{
-    Attribute, Block, Error, Expr, ExprAsync, ExprTuple, Result, Token, punctuated::Punctuated,
+    Attribute, Block, Error, Expr, ExprAsync, ExprTuple, Path, Result, Token,
+    parse::{ParseStream, Parser as _},
+    parse_quote,
+    punctuated::Punctuated,
     token,
 };
 
@@ -38,6 +41,23 @@ pub(crate) fn unit() -> Expr {
     })
 }
 
+// Sets the span of all tokens in `tokens` to `span`.
+pub(crate) fn respan(tokens: TokenStream, span: Span) -> TokenStream {
+    tokens
+        .into_iter()
+        .map(|mut token| {
+            if let TokenTree::Group(group) = &mut token {
+                let mut new = Group::new(group.delimiter(), respan(group.stream(), span));
+                new.set_span(span);
+                *group = new;
+            } else {
+                token.set_span(span);
+            }
+            token
+        })
+        .collect()
+}
+
 pub(crate) fn replace_expr<F>(this: &mut Expr, f: F)
 where
     F: FnOnce(Expr) -> Expr,
@@ -45,6 +65,50 @@ where
     *this = f(mem::replace(this, Expr::Verbatim(TokenStream::new())));
 }
 
+/// Returns the default path to the `futures_async_stream` crate.
+pub(crate) fn default_crate_path() -> Path {
+    parse_quote!(::futures_async_stream)
+}
+
+/// Parses `crate = <path>`.
+pub(crate) fn parse_crate_path(input: ParseStream<'_>, has_prev: bool) -> Result<Path> {
+    let crate_token: Token![crate] = input.parse()?;
+    if !input.peek(Token![=]) {
+        bail!(crate_token, "expected `crate = <path>`, found `crate`");
+    }
+    let eq_token: Token![=] = input.parse()?;
+    if input.is_empty() || input.peek(Token![,]) || input.peek(Token![;]) {
+        let span = quote::quote!(#crate_token #eq_token);
+        bail!(span, "expected `crate = <path>`, found `crate =`");
+    }
+    let path = input.call(Path::parse_mod_style)?;
+    if has_prev {
+        let span = quote::quote!(#crate_token #eq_token #path);
+        bail!(span, "duplicate `crate` argument");
+    }
+    Ok(path)
+}
+
+/// Parses the arguments of the attributes that only accept `crate = <path>`.
+pub(crate) fn parse_crate_arg(tokens: TokenStream) -> Result<Option<Path>> {
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+    (|input: ParseStream<'_>| {
+        if !input.peek(Token![crate]) {
+            let token = input.parse::<TokenStream>()?;
+            bail!(token, "unexpected token: `{}`", token);
+        }
+        let path = parse_crate_path(input, false)?;
+        if !input.is_empty() {
+            let _: Token![,] = input.parse()?;
+            parse_as_empty(&input.parse()?)?;
+        }
+        Ok(Some(path))
+    })
+    .parse2(tokens)
+}
+
 /// Checks if `tokens` is an empty `TokenStream`.
 ///
 /// This is almost equivalent to `syn::parse2::<Nothing>()`, but produces
@@ -58,18 +122,24 @@ pub(crate) fn parse_as_empty(tokens: &TokenStream) -> Result<()> {
 
 pub(crate) trait SliceExt {
     fn position_exact(&self, ident: &str) -> Result<Option<usize>>;
+    fn position_unique(&self, ident: &str) -> Result<Option<usize>>;
     fn find(&self, ident: &str) -> Option<&Attribute>;
 }
 
 impl SliceExt for [Attribute] {
     fn position_exact(&self, ident: &str) -> Result<Option<usize>> {
+        let pos = self.position_unique(ident)?;
+        if let Some(i) = pos {
+            self[i].meta.require_path_only()?;
+        }
+        Ok(pos)
+    }
+
+    fn position_unique(&self, ident: &str) -> Result<Option<usize>> {
         self.iter()
             .try_fold((0, None), |(i, mut prev), attr| {
-                if attr.path().is_ident(ident) {
-                    if prev.replace(i).is_some() {
-                        bail!(attr, "duplicate #[{}] attribute", ident);
-                    }
-                    attr.meta.require_path_only()?;
+                if attr.path().is_ident(ident) && prev.replace(i).is_some() {
+                    bail!(attr, "duplicate #[{}] attribute", ident);
                 }
                 Ok((i + 1, prev))
             })
@@ -1,18 +1,39 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use quote::quote;
+use std::{cell::Cell, mem, rc::Rc};
+
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::{ToTokens as _, quote, quote_spanned};
 use syn::{
-    Expr, ExprAwait, ExprCall, ExprForLoop, ExprYield, Item, Token, parse_quote,
-    parse_quote_spanned,
+    Block, Expr, ExprAsync, ExprAwait, ExprClosure, ExprForLoop, ExprLoop, ExprMacro,
+    ExprMethodCall, ExprPath, ExprTry, ExprWhile, ExprYield, Item, Meta, Pat, PatType, Path, Stmt,
+    StmtMacro, Token, Type,
+    parse::ParseStream,
+    parse_quote, parse_quote_spanned,
+    punctuated::Punctuated,
     spanned::Spanned as _,
     visit_mut::{self, VisitMut},
 };
 
 use crate::{
-    parse, stream, stream_block, try_stream_block,
-    utils::{SliceExt as _, expr_compile_error, replace_expr, unit},
+    parse, stream,
+    utils::{SliceExt as _, expr_compile_error, parse_crate_arg, replace_expr, unit},
 };
 
+/// The macros that can be used in the body of `#[stream]` and `#[try_stream]`.
+const STREAM_MACROS: &[&str] = &[
+    "size_hint",
+    "poll",
+    "with_context",
+    "yield_now",
+    "yield_err",
+    "checkpoint",
+    "yield_or_stop",
+    "scope",
+    "scope_local",
+    "yield_to",
+];
+
 /// The scope in which `#[for_await]`, `.await`, or `yield` was called.
 ///
 /// The type of coroutine depends on which scope is called.
@@ -43,14 +64,177 @@ impl Scope {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone)]
 pub(crate) struct Visitor {
     scope: Scope,
+    /// The path to the `futures_async_stream` crate.
+    krate: Path,
+    /// Whether the coroutine of `#[stream]` or `#[try_stream]` (this) is movable.
+    ///
+    /// Movable coroutines cannot hold borrows across `yield`, so futures and
+    /// streams are pinned with `Pin::new` every time they are polled.
+    movable: bool,
+    /// Whether `#[stream]` or `#[try_stream]` (this) has the `budget` argument.
+    ///
+    /// If so, `.await` and `#[for_await]` yield `Poll::Pending` after the
+    /// number of consecutive ready polls reaches the budget.
+    budget: bool,
+    /// Whether `#[try_stream]` (this) has the `output` argument.
+    ///
+    /// If so, `yield` yields values as is, and `yield_err!` cannot be used.
+    output: bool,
+    /// The `checkpoint` type of `#[try_stream]` (this).
+    checkpoint: Option<Type>,
+    /// Whether `#[stream]` or `#[try_stream]` (this) has the `stoppable` argument.
+    stoppable: bool,
+    /// Whether `#[try_stream]` (this) has the `continue_on_error` argument.
+    ///
+    /// If so, `?` in the body of loops yields the error and continues with the
+    /// next iteration.
+    continue_on_error: bool,
+    /// Whether the expression being visited is in the body of a loop.
+    in_loop: bool,
+    /// The `outputs` of `#[stream]` (this).
+    ///
+    /// If so, items are yielded with `yield_to!` instead of `yield`.
+    outputs: Option<Vec<(Ident, Type)>>,
+    /// The enum generated for `item = <enum>` of `#[stream]` (this).
+    ///
+    /// If so, `yield` converts the values into it with `From`.
+    item_enum: Option<Type>,
+    /// Whether `size_hint!` is used in `#[stream]` or `#[try_stream]` (this).
+    ///
+    /// If so, the stream stores its size hint. This is shared with the clones
+    /// of this visitor.
+    size_hint: Rc<Cell<bool>>,
 }
 
 impl Visitor {
-    pub(crate) fn new(scope: Scope) -> Self {
-        Self { scope }
+    pub(crate) fn new(scope: Scope, krate: Path) -> Self {
+        Self {
+            scope,
+            krate,
+            movable: false,
+            budget: false,
+            output: false,
+            checkpoint: None,
+            stoppable: false,
+            continue_on_error: false,
+            in_loop: false,
+            outputs: None,
+            item_enum: None,
+            size_hint: Rc::default(),
+        }
+    }
+
+    pub(crate) fn movable(mut self, movable: bool) -> Self {
+        self.movable = movable;
+        self
+    }
+
+    pub(crate) fn budget(mut self, budget: bool) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    pub(crate) fn output(mut self, output: bool) -> Self {
+        self.output = output;
+        self
+    }
+
+    pub(crate) fn checkpoint(mut self, checkpoint: Option<Type>) -> Self {
+        self.checkpoint = checkpoint;
+        self
+    }
+
+    pub(crate) fn stoppable(mut self, stoppable: bool) -> Self {
+        self.stoppable = stoppable;
+        self
+    }
+
+    pub(crate) fn continue_on_error(mut self, continue_on_error: bool) -> Self {
+        self.continue_on_error = continue_on_error;
+        self
+    }
+
+    pub(crate) fn outputs(mut self, outputs: Option<Vec<(Ident, Type)>>) -> Self {
+        self.outputs = outputs;
+        self
+    }
+
+    pub(crate) fn item_enum(mut self, item_enum: Option<Type>) -> Self {
+        self.item_enum = item_enum;
+        self
+    }
+
+    /// Returns `true` if `size_hint!` has been visited.
+    pub(crate) fn uses_size_hint(&self) -> bool {
+        self.size_hint.get()
+    }
+
+    /// Returns the expression that yields `Poll::Pending`.
+    ///
+    /// If `wake` is `true`, the task is woken first, so that the executor
+    /// polls the stream again.
+    fn yield_pending(&self, wake: bool) -> TokenStream {
+        let task_context = def_site_ident!("__task_context");
+        let krate = &self.krate;
+        let reset = self.has_budget().then(|| {
+            let budget = def_site_ident!("__budget");
+            quote!(#budget.reset();)
+        });
+        let wake = wake.then(|| {
+            // For interoperability with `forbid(unsafe_code)`, `unsafe` token should be call-site span.
+            let unsafety = <Token![unsafe]>::default();
+            quote! {
+                #unsafety {
+                    #krate::__private::future::get_context(#task_context)
+                }.waker().wake_by_ref();
+            }
+        });
+        quote! {{
+            #reset
+            #wake
+            #task_context = yield #krate::__private::Poll::Pending;
+        }}
+    }
+
+    /// Returns the statement that consumes the budget after a ready poll, and
+    /// yields `Poll::Pending` if it is exhausted.
+    fn consume_budget(&self) -> Option<TokenStream> {
+        self.has_budget().then(|| {
+            let budget = def_site_ident!("__budget");
+            let yield_pending = self.yield_pending(true);
+            quote! {
+                if #budget.ready() #yield_pending
+            }
+        })
+    }
+
+    fn has_budget(&self) -> bool {
+        self.budget && self.scope.is_stream()
+    }
+
+    /// Returns the statement that pins `<pinned>` and the expression that
+    /// returns `Pin<&mut _>` of it.
+    ///
+    /// `span` is used to report that `<pinned>` is not `Unpin` in movable coroutines.
+    fn pin(&self, pinned: &Ident, span: Span) -> (TokenStream, TokenStream) {
+        let krate = &self.krate;
+        if self.movable && self.scope.is_stream() {
+            (TokenStream::new(), quote_spanned!(span=> #krate::__private::Pin::new(&mut #pinned)))
+        } else {
+            // For interoperability with `forbid(unsafe_code)`, `unsafe` token should be call-site span.
+            let unsafety = <Token![unsafe]>::default();
+            (
+                quote! {
+                    let mut #pinned = #unsafety {
+                        #krate::__private::Pin::new_unchecked(&mut #pinned)
+                    };
+                },
+                quote!(#krate::__private::Pin::as_mut(&mut #pinned)),
+            )
+        }
     }
 
     /// Visits `#[for_await] for <pat> in <expr> { .. }`.
@@ -87,6 +271,8 @@ impl Visitor {
             }
 
             let pinned = def_site_ident!("__pinned");
+            let krate = &self.krate;
+            let (pin_stmt, pinned_expr) = self.pin(&pinned, e.span());
 
             // It needs to adjust the type yielded by the macro because coroutines used internally by
             // async fn yield `()` type, but coroutines used internally by `stream` yield
@@ -94,34 +280,38 @@ impl Visitor {
             let match_next = match self.scope {
                 Scope::Future => {
                     quote! {
-                        match ::futures_async_stream::__private::stream::next(&mut #pinned).await {
-                            ::futures_async_stream::__private::Some(e) => e,
-                            ::futures_async_stream::__private::None => break,
+                        match #krate::__private::stream::next(&mut #pinned).await {
+                            #krate::__private::Some(e) => e,
+                            #krate::__private::None => break,
                         }
                     }
                 }
                 Scope::Stream | Scope::TryStream => {
                     let task_context = def_site_ident!("__task_context");
                     let poll_result = def_site_ident!("__poll_result");
+                    let consume_budget = self.consume_budget();
+                    let yield_pending = self.yield_pending(false);
                     quote! {{
                         let #poll_result = unsafe {
-                            ::futures_async_stream::__private::stream::Stream::poll_next(
-                                ::futures_async_stream::__private::Pin::as_mut(&mut #pinned),
-                                ::futures_async_stream::__private::future::get_context(
+                            #krate::__private::stream::Stream::poll_next(
+                                #pinned_expr,
+                                #krate::__private::future::get_context(
                                     #task_context,
                                 ),
                             )
                         };
                         match #poll_result {
-                            ::futures_async_stream::__private::Poll::Ready(
-                                ::futures_async_stream::__private::Some(e),
-                            ) => e,
-                            ::futures_async_stream::__private::Poll::Ready(
-                                ::futures_async_stream::__private::None,
+                            #krate::__private::Poll::Ready(
+                                #krate::__private::Some(e),
+                            ) => {
+                                #consume_budget
+                                e
+                            }
+                            #krate::__private::Poll::Ready(
+                                #krate::__private::None,
                             ) => break,
-                            ::futures_async_stream::__private::Poll::Pending => {
-                                #task_context =
-                                    yield ::futures_async_stream::__private::Poll::Pending;
+                            #krate::__private::Poll::Pending => {
+                                #yield_pending
                                 continue;
                             }
                         }
@@ -141,9 +331,7 @@ impl Visitor {
             body.stmts.insert(0, parse_quote!(let #pat = #match_next;));
             *expr = parse_quote! {{
                 let mut #pinned = #e;
-                let mut #pinned = unsafe {
-                    ::futures_async_stream::__private::Pin::new_unchecked(&mut #pinned)
-                };
+                #pin_stmt
                 #label loop #body
             }}
         }
@@ -155,17 +343,516 @@ impl Visitor {
             return;
         }
 
-        // Desugar `yield <e>` into `__task_context = yield Poll::Ready(<e>)`.
+        // Desugar `yield <e>` into `__task_context = yield Poll::Ready(<e>)`
+        // (`Poll::Ready(Ok(<e>))` in `#[try_stream]` without the `output` argument,
+        // and `Poll::Ready(<enum as From<_>>::from(<e>))` with `item = <enum>`).
         if let Expr::Yield(ExprYield { yield_token, expr: e, .. }) = expr {
-            e.get_or_insert_with(|| Box::new(unit()));
+            if self.outputs.is_some() {
+                *expr = expr_compile_error(&format_err!(
+                    yield_token,
+                    "`yield` may not be used together with the `outputs` argument; \
+                     use `yield_to!(<output>: <expr>)` instead"
+                ));
+                return;
+            }
+            let e = e.take().map_or_else(unit, |e| *e);
+            let krate = &self.krate;
+            let item = if self.scope == Scope::TryStream && !self.output {
+                quote!(#krate::__private::Ok(#e))
+            } else if let Some(item_enum) = &self.item_enum {
+                quote!(<#item_enum as #krate::__private::From<_>>::from(#e))
+            } else {
+                e.into_token_stream()
+            };
+            *expr = self.yield_item(*yield_token, &item);
+        }
+    }
 
-            let task_context = def_site_ident!("__task_context");
+    /// Visits `yield_to!(<output>: <expr>)`.
+    fn visit_yield_to(&self, expr: &mut Expr) {
+        if !self.scope.is_stream() {
+            return;
+        }
+
+        // Desugar `yield_to!(<output>: <e>)` into `__task_context = yield Poll::Ready(<item>)`,
+        // where `<item>` is a tuple of `Option`s, one for each output, and only the
+        // `Option` of `<output>` is `Some(<e>)`.
+        if let Expr::Macro(ExprMacro { mac, .. }) = expr {
+            if !mac.path.is_ident("yield_to") {
+                return;
+            }
+            let Some(outputs) = &self.outputs else {
+                *expr = expr_compile_error(&format_err!(
+                    mac,
+                    "`yield_to!` requires `outputs(..)` argument"
+                ));
+                return;
+            };
+            let (name, mut e) = match mac.parse_body_with(|input: ParseStream<'_>| {
+                let name: Ident = input.parse()?;
+                let _: Token![:] = input.parse()?;
+                Ok((name, input.parse::<Expr>()?))
+            }) {
+                Ok(args) => args,
+                Err(e) => {
+                    *expr = expr_compile_error(&e);
+                    return;
+                }
+            };
+            if !outputs.iter().any(|(output, _)| *output == name) {
+                *expr = expr_compile_error(&format_err!(name, "unknown output `{}`", name));
+                return;
+            }
+            // The body of the macro is not visited yet, e.g., `.await` in it.
+            self.clone().visit_expr_mut(&mut e);
+            let krate = &self.krate;
+            let fields = outputs.iter().map(|(output, ty)| {
+                if *output == name {
+                    quote!(#krate::__private::Some::<#ty>(#e))
+                } else {
+                    quote!(#krate::__private::None::<#ty>)
+                }
+            });
+            *expr = self.yield_item(Token![yield](mac.span()), &quote!((#(#fields,)*)));
+        }
+    }
+
+    /// Returns the expression that yields `Poll::Ready(<item>)`.
+    ///
+    /// With the `budget` argument, the budget is reset after `<item>` is
+    /// evaluated, because yielding an item returns control to the executor.
+    fn yield_item(&self, yield_token: Token![yield], item: &TokenStream) -> Expr {
+        let task_context = def_site_ident!("__task_context");
+        let krate = &self.krate;
+        if self.has_budget() {
+            let budget = def_site_ident!("__budget");
+            let item_ident = def_site_ident!("__item");
+            parse_quote! {{
+                let #item_ident = #item;
+                #budget.reset();
+                #task_context = #yield_token #krate::__private::Poll::Ready(#item_ident)
+            }}
+        } else {
+            parse_quote! {
+                #task_context = #yield_token #krate::__private::Poll::Ready(#item)
+            }
+        }
+    }
+
+    /// Visits `yield_err!(<e>)`.
+    fn visit_yield_err(&self, expr: &mut Expr) {
+        if !self.scope.is_stream() {
+            return;
+        }
+
+        // Desugar `yield_err!(<e>)` into
+        // `__task_context = yield Poll::Ready(Err(From::from(<e>)))`.
+        if let Expr::Macro(ExprMacro { mac, .. }) = expr {
+            if !mac.path.is_ident("yield_err") {
+                return;
+            }
+            if self.scope != Scope::TryStream {
+                *expr = expr_compile_error(&format_err!(
+                    mac,
+                    "`yield_err!` may only be used in #[try_stream] functions and async blocks"
+                ));
+                return;
+            }
+            if self.output {
+                *expr = expr_compile_error(&format_err!(
+                    mac,
+                    "`yield_err!` may not be used together with the `output` argument"
+                ));
+                return;
+            }
+            let e: Expr = match mac.parse_body() {
+                Ok(e) => e,
+                Err(e) => {
+                    *expr = expr_compile_error(&e);
+                    return;
+                }
+            };
+            let krate = &self.krate;
+            let item = quote_spanned! { e.span() =>
+                #krate::__private::Err(#krate::__private::From::from(#e))
+            };
+            *expr = self.yield_item(Token![yield](mac.span()), &item);
+        }
+    }
+
+    /// Visits `<expr>?` in the body of loops with the `continue_on_error` argument.
+    fn visit_try(&self, expr: &mut Expr) {
+        if self.scope != Scope::TryStream || !self.continue_on_error || !self.in_loop {
+            return;
+        }
+
+        // Desugar `<e>?` into
+        //
+        // match <e> {
+        //     Ok(ok) => ok,
+        //     Err(err) => {
+        //         __task_context = yield Poll::Ready(Err(From::from(err)));
+        //         continue;
+        //     }
+        // }
+        if let Expr::Try(ExprTry { expr: e, question_token, .. }) = expr {
+            let krate = &self.krate;
+            let ok = def_site_ident!("__ok");
+            let err = def_site_ident!("__err");
+            let item = quote_spanned! { question_token.span() =>
+                #krate::__private::Err(#krate::__private::From::from(#err))
+            };
+            let yield_err = self.yield_item(Token![yield](question_token.span()), &item);
             *expr = parse_quote! {
-                #task_context = #yield_token ::futures_async_stream::__private::Poll::Ready(#e)
+                match #e {
+                    #krate::__private::Ok(#ok) => #ok,
+                    #krate::__private::Err(#err) => {
+                        #yield_err;
+                        continue;
+                    }
+                }
             };
         }
     }
 
+    /// Visits the body of a loop.
+    fn visit_loop_body(&mut self, body: &mut Block) {
+        let tmp = mem::replace(&mut self.in_loop, true);
+        self.visit_block_mut(body);
+        self.in_loop = tmp;
+    }
+
+    /// Visits `yield_or_stop!(<e>)`.
+    fn visit_yield_or_stop(&self, expr: &mut Expr) {
+        if !self.scope.is_stream() {
+            return;
+        }
+
+        // Desugar `yield_or_stop!(<e>)` into:
+        //
+        // if __stop.is_stop_requested() {
+        //     StopSignal::Stop
+        // } else {
+        //     yield <e>;
+        //     __stop.signal()
+        // }
+        if let Expr::Macro(ExprMacro { mac, .. }) = expr {
+            if !mac.path.is_ident("yield_or_stop") {
+                return;
+            }
+            if !self.stoppable {
+                *expr = expr_compile_error(&format_err!(
+                    mac,
+                    "`yield_or_stop!` requires `stoppable` argument"
+                ));
+                return;
+            }
+            let e: Expr = match mac.parse_body() {
+                Ok(e) => e,
+                Err(e) => {
+                    *expr = expr_compile_error(&e);
+                    return;
+                }
+            };
+            let mut yield_expr = Expr::Yield(ExprYield {
+                attrs: vec![],
+                yield_token: Token![yield](mac.span()),
+                expr: Some(Box::new(e)),
+            });
+            self.visit_yield(&mut yield_expr);
+            let stop = def_site_ident!("__stop");
+            let krate = &self.krate;
+            *expr = parse_quote! {
+                if #stop.is_stop_requested() {
+                    #krate::__private::StopSignal::Stop
+                } else {
+                    #yield_expr;
+                    #stop.signal()
+                }
+            };
+        }
+    }
+
+    /// Visits `checkpoint!(<checkpoint>)`.
+    fn visit_checkpoint(&self, expr: &mut Expr) {
+        if !self.scope.is_stream() {
+            return;
+        }
+
+        // Desugar `checkpoint!(<checkpoint>)` into:
+        //
+        // {
+        //     let __checkpoint: <type> = <checkpoint>;
+        //     unsafe { set_checkpoint::<<type>, _>(__task_context, __checkpoint) }
+        // }
+        if let Expr::Macro(ExprMacro { mac, .. }) = expr {
+            if !mac.path.is_ident("checkpoint") {
+                return;
+            }
+            let Some(ty) = &self.checkpoint else {
+                *expr = expr_compile_error(&format_err!(
+                    mac,
+                    "`checkpoint!` requires `checkpoint = <type>` argument"
+                ));
+                return;
+            };
+            let e: Expr = match mac.parse_body() {
+                Ok(e) => e,
+                Err(e) => {
+                    *expr = expr_compile_error(&e);
+                    return;
+                }
+            };
+            let checkpoint = def_site_ident!("__checkpoint");
+            let task_context = def_site_ident!("__task_context");
+            let krate = &self.krate;
+            // For interoperability with `forbid(unsafe_code)`, `unsafe` token should be call-site span.
+            let unsafety = <Token![unsafe]>::default();
+            *expr = parse_quote! {{
+                let #checkpoint: #ty = #e;
+                #unsafety {
+                    #krate::__private::future::set_checkpoint::<#ty, _>(#task_context, #checkpoint)
+                }
+            }};
+        }
+    }
+
+    /// Visits `scope!(|<pat>| <body>)` and `scope_local!(|<pat>| <body>)`.
+    ///
+    /// This must be called before visiting the inner expressions, so that the
+    /// async blocks spawned in the scope are expanded as streams.
+    fn visit_scope(&self, expr: &mut Expr) {
+        if !self.scope.is_stream() {
+            return;
+        }
+
+        // Desugar `scope!(|<pat>| <body>)` into:
+        //
+        // {
+        //     let mut __scope = Scope::default();
+        //     {
+        //         let <pat> = &mut __scope;
+        //         <body>
+        //     };
+        //     #[for_await]
+        //     for __item in __scope {
+        //         yield __item;
+        //     }
+        // }
+        //
+        // and `<pat>.spawn(async <block>)` in `<body>` into
+        // `<pat>.spawn(#[stream] async <block>)`.
+        //
+        // `<body>` runs to completion before `__scope` is polled, so the
+        // spawned streams only start running after the closure returns, and
+        // `.await` in `<body>` cannot wait for items of a spawned stream.
+        if let Expr::Macro(ExprMacro { mac, .. }) = expr {
+            let (name, ty) = if mac.path.is_ident("scope") {
+                ("scope", quote!(Scope))
+            } else if mac.path.is_ident("scope_local") {
+                ("scope_local", quote!(LocalScope))
+            } else {
+                return;
+            };
+            if self.scope != Scope::Stream {
+                *expr = expr_compile_error(&format_err!(
+                    mac,
+                    "`{}!` may only be used in #[stream]",
+                    name
+                ));
+                return;
+            }
+            let closure: ExprClosure = match mac.parse_body() {
+                Ok(closure) => closure,
+                Err(e) => {
+                    *expr = expr_compile_error(&e);
+                    return;
+                }
+            };
+            let ident = match closure.inputs.first() {
+                Some(Pat::Ident(pat)) if closure.inputs.len() == 1 => pat.ident.clone(),
+                Some(Pat::Type(PatType { pat, .. })) if closure.inputs.len() == 1 => match &**pat {
+                    Pat::Ident(pat) => pat.ident.clone(),
+                    _ => {
+                        *expr =
+                            expr_compile_error(&format_err!(pat, "expected an identifier pattern"));
+                        return;
+                    }
+                },
+                _ => {
+                    *expr = expr_compile_error(&format_err!(
+                        closure.inputs,
+                        "expected a closure with one argument, e.g. `|s| { .. }`"
+                    ));
+                    return;
+                }
+            };
+            let pat = &closure.inputs[0];
+            let mut body = *closure.body;
+            SpawnStreams { scope: &ident }.visit_expr_mut(&mut body);
+            let scope = def_site_ident!("__scope");
+            let item = def_site_ident!("__item");
+            let krate = &self.krate;
+            *expr = parse_quote! {{
+                let mut #scope = #krate::__private::#ty::default();
+                {
+                    let #pat = &mut #scope;
+                    #body
+                };
+                #[for_await]
+                for #item in #scope {
+                    yield #item;
+                }
+            }};
+        }
+    }
+
+    /// Visits `size_hint!(<lower>, <upper>)`.
+    fn visit_size_hint(&self, expr: &mut Expr) {
+        if !self.scope.is_stream() {
+            return;
+        }
+
+        // Desugar `size_hint!(<lower>, <upper>)` into:
+        //
+        // {
+        //     let (__lower, __upper) = (<lower>, <upper>);
+        //     unsafe { set_size_hint(__task_context, __lower, __upper) }
+        // }
+        if let Expr::Macro(ExprMacro { mac, .. }) = expr {
+            if !mac.path.is_ident("size_hint") {
+                return;
+            }
+            // The output streams share the coroutine, and their sizes are unknown.
+            if self.outputs.is_some() {
+                *expr = expr_compile_error(&format_err!(
+                    mac,
+                    "`size_hint!` may not be used together with the `outputs` argument"
+                ));
+                return;
+            }
+            let args = match mac.parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated) {
+                Ok(args) if args.len() == 2 => args,
+                Ok(_) => {
+                    *expr = expr_compile_error(&format_err!(
+                        mac,
+                        "expected `size_hint!(<lower>, <upper>)`"
+                    ));
+                    return;
+                }
+                Err(e) => {
+                    *expr = expr_compile_error(&e);
+                    return;
+                }
+            };
+            self.size_hint.set(true);
+            let (lower, upper) = (&args[0], &args[1]);
+            let lower_ident = def_site_ident!("__lower");
+            let upper_ident = def_site_ident!("__upper");
+            let task_context = def_site_ident!("__task_context");
+            let krate = &self.krate;
+            // For interoperability with `forbid(unsafe_code)`, `unsafe` token should be call-site span.
+            let unsafety = <Token![unsafe]>::default();
+            *expr = parse_quote! {{
+                let (#lower_ident, #upper_ident) = (#lower, #upper);
+                #unsafety {
+                    #krate::__private::future::set_size_hint(
+                        #task_context,
+                        #lower_ident,
+                        #upper_ident,
+                    )
+                }
+            }};
+        }
+    }
+
+    /// Visits `poll!(<f>)` and `with_context!(<f>)`.
+    fn visit_context_macro(&self, expr: &mut Expr) {
+        if !self.scope.is_stream() {
+            return;
+        }
+
+        // Desugar `poll!(<f>)` into:
+        //
+        // {
+        //     let mut __poll = poll_fn(<f>);
+        //     loop {
+        //         if let Poll::Ready(result) = __poll(unsafe { get_context(__task_context) }) {
+        //             break result;
+        //         }
+        //         __task_context = yield Poll::Pending;
+        //     }
+        // }
+        //
+        // and `with_context!(<f>)` into:
+        //
+        // with_context(<f>, unsafe { get_context(__task_context) })
+        //
+        // `poll_fn` and `with_context` require `<f>` to accept `&mut Context<'_>`
+        // of any lifetime, so the context cannot escape from `<f>`.
+        if let Expr::Macro(ExprMacro { mac, .. }) = expr {
+            let is_poll = mac.path.is_ident("poll");
+            if !is_poll && !mac.path.is_ident("with_context") {
+                return;
+            }
+            let f: Expr = match mac.parse_body() {
+                Ok(f) => f,
+                Err(e) => {
+                    *expr = expr_compile_error(&e);
+                    return;
+                }
+            };
+            let task_context = def_site_ident!("__task_context");
+            let krate = &self.krate;
+            // For interoperability with `forbid(unsafe_code)`, `unsafe` token should be call-site span.
+            let unsafety = <Token![unsafe]>::default();
+            let cx = quote! {
+                #unsafety { #krate::__private::future::get_context(#task_context) }
+            };
+            *expr = if is_poll {
+                let poll = def_site_ident!("__poll");
+                let yield_pending = self.yield_pending(false);
+                parse_quote_spanned! { f.span() => {
+                    let mut #poll = #krate::__private::future::poll_fn(#f);
+                    loop {
+                        if let #krate::__private::Poll::Ready(result) = #poll(#cx) {
+                            break result;
+                        }
+                        #yield_pending
+                    }
+                }}
+            } else {
+                parse_quote_spanned! { f.span() =>
+                    #krate::__private::future::with_context(#f, #cx)
+                }
+            };
+        }
+    }
+
+    /// Visits `yield_now!()`.
+    fn visit_yield_now(&self, expr: &mut Expr) {
+        if !self.scope.is_stream() {
+            return;
+        }
+
+        // Desugar `yield_now!()` into:
+        //
+        // {
+        //     unsafe { get_context(__task_context) }.waker().wake_by_ref();
+        //     __task_context = yield Poll::Pending;
+        // }
+        if let Expr::Macro(ExprMacro { mac, .. }) = expr {
+            if !mac.path.is_ident("yield_now") {
+                return;
+            }
+            if !mac.tokens.is_empty() {
+                *expr = expr_compile_error(&format_err!(mac, "expected `yield_now!()`"));
+                return;
+            }
+            *expr = syn::parse2(self.yield_pending(true)).unwrap();
+        }
+    }
+
     /// Visits `stream_block!` macro.
     fn visit_macro(&self, expr: &mut Expr) {
         if self.scope != Scope::Other {
@@ -174,15 +861,22 @@ impl Visitor {
 
         replace_expr(expr, |expr| {
             if let Expr::Macro(mut expr) = expr {
-                let mut e: ExprCall = if expr.mac.path.is_ident("stream_block") {
-                    syn::parse(stream_block(expr.mac.tokens.into())).unwrap()
+                let cx = if expr.mac.path.is_ident("stream_block") {
+                    parse::Context::Stream
                 } else if expr.mac.path.is_ident("try_stream_block") {
-                    syn::parse(try_stream_block(expr.mac.tokens.into())).unwrap()
+                    parse::Context::TryStream
                 } else {
                     return Expr::Macro(expr);
                 };
-                e.attrs.append(&mut expr.attrs);
-                Expr::Call(e)
+                let mut e: Expr = syn::parse2(
+                    stream::block_macro(expr.mac.tokens, cx, &self.krate)
+                        .unwrap_or_else(syn::Error::into_compile_error),
+                )
+                .unwrap();
+                if let Expr::Call(e) = &mut e {
+                    e.attrs.append(&mut expr.attrs);
+                }
+                e
             } else {
                 unreachable!()
             }
@@ -196,7 +890,7 @@ impl Visitor {
         }
 
         if let Expr::Async(e) = expr {
-            match (e.attrs.position_exact("stream"), e.attrs.position_exact("try_stream")) {
+            match (e.attrs.position_unique("stream"), e.attrs.position_unique("try_stream")) {
                 (Err(e), _) | (_, Err(e)) => {
                     *expr = expr_compile_error(&e);
                 }
@@ -207,18 +901,33 @@ impl Visitor {
                     ));
                 }
                 (Ok(Some(i)), _) => {
-                    e.attrs.remove(i);
-                    *expr = syn::parse2(stream::parse_async(e, parse::Context::Stream)).unwrap();
+                    let meta = e.attrs.remove(i).meta;
+                    *expr = self.expand_async(e, meta, parse::Context::Stream);
                 }
                 (_, Ok(Some(i))) => {
-                    e.attrs.remove(i);
-                    *expr = syn::parse2(stream::parse_async(e, parse::Context::TryStream)).unwrap();
+                    let meta = e.attrs.remove(i).meta;
+                    *expr = self.expand_async(e, meta, parse::Context::TryStream);
                 }
                 (Ok(None), Ok(None)) => unreachable!(),
             }
         }
     }
 
+    fn expand_async(&self, expr: &mut ExprAsync, meta: Meta, cx: parse::Context) -> Expr {
+        // The nested async stream blocks use the same crate path as the outer
+        // scope, unless it is overridden.
+        let krate = match meta {
+            Meta::Path(_) => Ok(None),
+            Meta::List(list) => parse_crate_arg(list.tokens),
+            meta @ Meta::NameValue(_) => meta.require_list().map(|_| None),
+        };
+        let krate = match krate {
+            Ok(krate) => krate,
+            Err(e) => return expr_compile_error(&e),
+        };
+        syn::parse2(stream::parse_async(expr, cx, krate.as_ref().unwrap_or(&self.krate))).unwrap()
+    }
+
     /// Visits `<base>.await`.
     ///
     /// It needs to adjust the type yielded by the macro because coroutines used internally by
@@ -243,25 +952,32 @@ impl Visitor {
         //         __task_context = yield Poll::Pending;
         //     }
         // }
+        //
+        // In movable coroutines, `Pin::new(&mut __pinned)` is used instead (see `pin`).
+        // With the `budget` argument, a ready poll consumes the budget (see `consume_budget`).
         if let Expr::Await(ExprAwait { base, await_token, .. }) = expr {
             let task_context = def_site_ident!("__task_context");
+            let krate = &self.krate;
+            let pinned = Ident::new("__pinned", await_token.span());
+            let (pin_stmt, pinned_expr) = self.pin(&pinned, base.span());
+            let consume_budget = self.consume_budget();
+            let yield_pending = self.yield_pending(false);
             // For interoperability with `forbid(unsafe_code)`, `unsafe` token should be call-site span.
             let unsafety = <Token![unsafe]>::default();
             *expr = parse_quote_spanned! { await_token.span() => {
-                let mut __pinned = #base;
-                let mut __pinned = #unsafety {
-                    ::futures_async_stream::__private::Pin::new_unchecked(&mut __pinned)
-                };
+                let mut #pinned = #base;
+                #pin_stmt
                 loop {
-                    if let ::futures_async_stream::__private::Poll::Ready(result) = #unsafety {
-                        ::futures_async_stream::__private::future::Future::poll(
-                            ::futures_async_stream::__private::Pin::as_mut(&mut __pinned),
-                            ::futures_async_stream::__private::future::get_context(#task_context),
+                    if let #krate::__private::Poll::Ready(result) = #unsafety {
+                        #krate::__private::future::Future::poll(
+                            #pinned_expr,
+                            #krate::__private::future::get_context(#task_context),
                         )
                     } {
+                        #consume_budget
                         break result;
                     }
-                    #task_context = yield ::futures_async_stream::__private::Poll::Pending;
+                    #yield_pending
                 }
             }};
         }
@@ -295,6 +1011,9 @@ impl VisitMut for Visitor {
             _ => {}
         }
 
+        if let Expr::Macro(_) = expr {
+            self.visit_scope(expr);
+        }
         if self.scope != Scope::Other {
             visit_mut::visit_expr_mut(self, expr);
         }
@@ -302,7 +1021,17 @@ impl VisitMut for Visitor {
             Expr::Async(_) => self.visit_async(expr),
             Expr::Await(_) => self.visit_await(expr),
             Expr::ForLoop(_) => self.visit_for_loop(expr),
-            Expr::Macro(_) => self.visit_macro(expr),
+            Expr::Macro(_) => {
+                self.visit_size_hint(expr);
+                self.visit_context_macro(expr);
+                self.visit_yield_now(expr);
+                self.visit_yield_err(expr);
+                self.visit_checkpoint(expr);
+                self.visit_yield_or_stop(expr);
+                self.visit_yield_to(expr);
+                self.visit_macro(expr);
+            }
+            Expr::Try(_) => self.visit_try(expr),
             Expr::Yield(_) => self.visit_yield(expr),
             _ => {}
         }
@@ -311,6 +1040,66 @@ impl VisitMut for Visitor {
         self.scope = tmp;
     }
 
+    // `?` in the iterator expression or the condition belongs to the outer loop.
+    fn visit_expr_for_loop_mut(&mut self, expr: &mut ExprForLoop) {
+        self.visit_pat_mut(&mut expr.pat);
+        self.visit_expr_mut(&mut expr.expr);
+        self.visit_loop_body(&mut expr.body);
+    }
+
+    fn visit_expr_while_mut(&mut self, expr: &mut ExprWhile) {
+        self.visit_expr_mut(&mut expr.cond);
+        self.visit_loop_body(&mut expr.body);
+    }
+
+    fn visit_expr_loop_mut(&mut self, expr: &mut ExprLoop) {
+        self.visit_loop_body(&mut expr.body);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        // Macros in statement position are parsed as `Stmt::Macro`.
+        if let Stmt::Macro(StmtMacro { mac, .. }) = stmt {
+            if self.scope.is_stream() && STREAM_MACROS.iter().any(|name| mac.path.is_ident(name)) {
+                let Stmt::Macro(StmtMacro { attrs, mac, semi_token }) =
+                    mem::replace(stmt, Stmt::Expr(unit(), None))
+                else {
+                    unreachable!()
+                };
+                *stmt = Stmt::Expr(Expr::Macro(ExprMacro { attrs, mac }), semi_token);
+            }
+        }
+        visit_mut::visit_stmt_mut(self, stmt);
+    }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {
+        // Do not recurse into nested items.
+    }
+}
+
+/// Marks the async blocks spawned in `scope!` as `#[stream]`.
+struct SpawnStreams<'a> {
+    /// The argument of the closure passed to `scope!`.
+    scope: &'a Ident,
+}
+
+impl VisitMut for SpawnStreams<'_> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        visit_mut::visit_expr_mut(self, expr);
+        if let Expr::MethodCall(ExprMethodCall { receiver, method, args, .. }) = expr {
+            let is_scope = matches!(&**receiver, Expr::Path(ExprPath { path, .. }) if path.is_ident(self.scope));
+            if !is_scope || method != "spawn" || args.len() != 1 {
+                return;
+            }
+            if let Some(Expr::Async(e)) = args.first_mut() {
+                if !e.attrs.iter().any(|attr| {
+                    attr.path().is_ident("stream") || attr.path().is_ident("try_stream")
+                }) {
+                    e.attrs.push(parse_quote!(#[stream]));
+                }
+            }
+        }
+    }
+
     fn visit_item_mut(&mut self, _: &mut Item) {
         // Do not recurse into nested items.
     }
@@ -21,4 +21,14 @@ const _: fn() = || {
     assert_unpin::<crate::future::ResumeTy>();
     assert_unwind_safe::<crate::future::ResumeTy>();
     assert_ref_unwind_safe::<crate::future::ResumeTy>();
+    assert_send::<crate::stream::Budget>();
+    assert_sync::<crate::stream::Budget>();
+    assert_unpin::<crate::stream::Budget>();
+    assert_unwind_safe::<crate::stream::Budget>();
+    assert_ref_unwind_safe::<crate::stream::Budget>();
+    assert_send::<crate::context::Location>();
+    assert_sync::<crate::context::Location>();
+    assert_unpin::<crate::context::Location>();
+    assert_unwind_safe::<crate::context::Location>();
+    assert_ref_unwind_safe::<crate::context::Location>();
 };
@@ -26,6 +26,8 @@ fn write_size<T>(out: &mut String) {
 fn track_size() {
     let mut out = String::new();
     write_size::<crate::future::ResumeTy>(&mut out);
+    write_size::<crate::stream::Budget>(&mut out);
+    write_size::<crate::context::Location>(&mut out);
     test_helper::git::assert_diff(
         Path::new(env!("CARGO_MANIFEST_DIR")).join("src/gen/tests/track_size.txt"),
         out,
@@ -20,7 +20,7 @@ futures-async-stream = "0.2"
 futures = "0.3"
 ```
 
-*Compiler support: requires rustc nightly-2024-04-25+*
+*Compiler support: requires rustc nightly-2025-01-01+*
 
 ## `#[for_await]`
 
@@ -81,6 +81,12 @@ To early exit from a `#[stream]` function or block, use `return`.
 `item = some::Path` and the values output from the stream must be yielded
 via the `yield` expression.
 
+Lifetimes in arguments are elided in the same way as async fn, and the
+returned stream captures all of them, including lifetime parameters hidden in
+paths (e.g., `Formatter` instead of `Formatter<'_>`). Boxed streams can only
+be bound by lifetimes that are written, so use `Formatter<'_>` in functions
+with the `boxed` or `boxed_local` argument.
+
 `#[stream]` can also be used on async blocks:
 
 ```
@@ -102,6 +108,404 @@ fn foo() -> impl Stream<Item = i32> {
 Note that `#[stream]` on async block does not require the `item` argument,
 but it may require additional type annotations.
 
+The returned streams are fused: once a stream has returned `None`, it returns
+`None` forever without resuming the completed coroutine. A stream that
+panicked is also terminated, so polling it again after catching the panic
+returns `None`. Async blocks with `#[stream]` and `#[try_stream]` return
+`impl FusedStream`.
+
+## Size hints
+
+By default, `Stream::size_hint` of the returned stream is `(0, None)`. Pass
+`size_hint = (<lower>, <upper>)` to set the initial size hint. The
+expression can use the arguments of the function. Use
+`size_hint!(<lower>, <upper>)` in the body to update it once the number of
+the remaining items is known:
+
+```
+#![feature(coroutines)]
+
+use futures_async_stream::stream;
+
+#[stream(item = u32, size_hint = (n, Some(n)))]
+async fn numbers(n: usize) {
+    for i in 0..n as u32 {
+        yield i;
+    }
+}
+
+#[stream(item = String)]
+async fn lines(page: Vec<String>) {
+    // e.g., the first line of the page announces the number of the lines.
+    let mut page = page.into_iter();
+    let total: usize = page.next().unwrap().parse().unwrap();
+    size_hint!(total, Some(total));
+    for line in page.take(total) {
+        yield line;
+    }
+}
+```
+
+Both describe the number of the items that have not been yielded yet; the size
+hint is decreased every time an item is yielded. `size_hint!` can be used in
+the body of `#[stream]` and `#[try_stream]` functions and async blocks, but
+not in closures or async blocks nested in them.
+
+## Accessing the task context
+
+The body of `#[stream]` and `#[try_stream]` cannot access the `Context` passed
+to `poll_next` directly. Use `poll!(|cx| ..)` to run a poll function until it
+returns `Poll::Ready` and get its value, yielding `Poll::Pending` to the
+caller in the meantime. Use `with_context!(|cx| ..)` to run a function with the
+context once:
+
+```
+#![feature(coroutines)]
+
+use std::{
+    pin::Pin,
+    task::{Context, Waker},
+};
+
+use futures::stream::Stream;
+use futures_async_stream::stream;
+
+#[stream(item = i32)]
+async fn forward<S: Stream<Item = i32> + Unpin>(mut s: S) {
+    while let Some(x) = poll!(|cx: &mut Context<'_>| Pin::new(&mut s).poll_next(cx)) {
+        yield x;
+    }
+}
+
+#[stream(item = Waker)]
+async fn wakers() {
+    yield with_context!(|cx| cx.waker().clone());
+}
+```
+
+The functions receive `&mut Context<'_>` that is only valid during the call,
+so the context cannot escape from them. Like `size_hint!`, these macros can be
+used in the body of `#[stream]` and `#[try_stream]` functions and async
+blocks, but not in closures or async blocks nested in them.
+
+## Cooperative scheduling
+
+The body of an async stream runs until it yields an item or a future or
+stream it awaits returns `Poll::Pending`. If the futures and streams are
+always ready, a single call to `poll_next` can run for a long time and starve
+other tasks on the executor. Use `yield_now!()` to wake the task and yield
+`Poll::Pending` to the caller:
+
+```
+#![feature(coroutines)]
+
+use futures::stream::Stream;
+use futures_async_stream::{for_await, stream};
+
+#[stream(item = u64)]
+async fn sum(s: impl Stream<Item = u64>) {
+    let mut sum = 0;
+    let mut count = 0;
+    #[for_await]
+    for x in s {
+        sum += x;
+        count += 1;
+        if count % 1024 == 0 {
+            yield_now!();
+        }
+    }
+    yield sum;
+}
+```
+
+With the `budget = <count>` argument, the stream does this automatically after
+`<count>` consecutive `.await`s and `#[for_await]` iterations that did not
+return `Poll::Pending`. The count must be greater than zero, and is reset
+every time the stream yields an item or `Poll::Pending`:
+
+```
+#![feature(coroutines)]
+
+use futures::stream::Stream;
+use futures_async_stream::{for_await, stream};
+
+#[stream(item = u64, budget = 1024)]
+async fn sum(s: impl Stream<Item = u64>) {
+    let mut sum = 0;
+    #[for_await]
+    for x in s {
+        sum += x;
+    }
+    yield sum;
+}
+```
+
+Both only use the waker of the task, so they work with any executor and in
+`no_std`.
+
+## Prefetching
+
+The body of an async stream only runs while the consumer polls the stream, so
+the time spent producing items (e.g., fetching pages from the network) and
+the time spent processing them add up. With the `prefetch = <count>`
+argument, every time the consumer polls the stream, the body keeps running
+until it returns `Poll::Pending`, it completes, or up to `<count>` items are
+buffered ahead of the consumer:
+
+```
+#![feature(coroutines)]
+
+use futures::stream::Stream;
+use futures_async_stream::{for_await, stream};
+
+#[stream(item = Vec<u8>, prefetch = 4)]
+async fn pages(client: impl Stream<Item = Vec<u8>>) {
+    #[for_await]
+    for page in client {
+        yield page;
+    }
+}
+```
+
+The buffer is stored inline in the stream, so this works in `no_std`. The
+body does not run in the background: it only runs while the consumer polls
+the stream. With the `eager` argument, the body also starts running at
+creation until it returns `Poll::Pending` or the buffer is full. There is no
+task to wake at that point, so the first poll of the stream always resumes
+the body again with the consumer's waker. This requires the `movable`
+argument, because the stream is moved after it starts running:
+
+```
+#![feature(coroutines)]
+
+use futures_async_stream::stream;
+
+#[stream(item = i32, prefetch = 2, movable, eager)]
+async fn numbers() {
+    for i in 0..10 {
+        yield i;
+    }
+}
+```
+
+## Validating arguments
+
+The body of an async stream only runs when the stream is polled, so errors in
+the arguments can only be reported as items of the stream. With the
+`init_error = <type>` argument, the function returns
+`Result<impl Stream, <type>>` instead, and `init! { .. }` at the start of the
+body runs when the function is called. Returning `Err` or using `?` in
+`init!` returns the error from the function, and variables defined in it can
+be used in the rest of the body:
+
+```
+#![feature(coroutines)]
+
+use futures_async_stream::stream;
+
+#[derive(Debug)]
+struct InvalidStep;
+
+#[stream(item = u32, init_error = InvalidStep)]
+async fn range(end: u32, step: u32) {
+    init! {
+        if step == 0 {
+            return Err(InvalidStep);
+        }
+        let count = end.div_ceil(step);
+    }
+    for i in 0..count {
+        yield i * step;
+    }
+}
+
+assert!(range(10, 0).is_err());
+```
+
+With the `async_init` argument, the function returns
+`impl Future<Output = Result<impl Stream, <type>>>`, and `.await` can be used
+in `init!` (e.g., to open a connection before streaming from it).
+
+## Stopping gracefully
+
+Dropping a stream drops its body without running the rest of it. With the
+`stoppable` argument (requires the `alloc` feature), the function returns a
+[`StopHandle`](https://docs.rs/futures-async-stream/latest/futures_async_stream/struct.StopHandle.html)
+alongside the stream. After `request_stop` is called on the handle,
+`yield_or_stop!(<expr>)` in the body evaluates to `StopSignal::Stop` instead
+of `StopSignal::Continue`, so the body can run async cleanup (e.g., send a
+goodbye message) before it ends. The consumer must keep polling the stream
+until it ends for the cleanup to run:
+
+```
+#![feature(coroutines)]
+
+use futures_async_stream::stream;
+
+#[stream(item = String, stoppable)]
+async fn frames() {
+    let mut i = 0;
+    loop {
+        if yield_or_stop!(format!("frame {i}")).is_stop() {
+            yield async { "goodbye".to_owned() }.await;
+            return;
+        }
+        i += 1;
+    }
+}
+
+let (stream, handle) = frames();
+// ...
+handle.request_stop();
+```
+
+If the stop is requested before `yield_or_stop!` is evaluated, the item is
+not yielded. `request_stop` wakes the task that polled the stream last, so a
+consumer that is waiting for the next item polls the stream again.
+
+## Running streams concurrently
+
+`scope!(|s| { .. })` in the body of `#[stream]` (requires the `alloc` feature)
+runs the child streams spawned with `s.spawn(..)` concurrently and yields
+their items in the order in which they are ready. Async blocks passed to
+`s.spawn` can use `yield` like the body of `#[stream]`. The child streams are
+polled by the enclosing stream, so no runtime is needed, and `scope!` completes
+once every child stream ends:
+
+```
+#![feature(coroutines)]
+
+use futures::stream::Stream;
+use futures_async_stream::stream;
+
+#[stream(item = String)]
+async fn fan_in(producers: Vec<impl Stream<Item = String> + Send + 'static>) {
+    scope!(|s| {
+        for (i, producer) in producers.into_iter().enumerate() {
+            s.spawn(async move {
+                #[for_await]
+                for line in producer {
+                    yield format!("{i}: {line}");
+                }
+            });
+        }
+    });
+}
+```
+
+The children are polled in round-robin order, so a child that is always ready
+does not starve the others. Each child stream is boxed when it is spawned,
+which is one allocation per child.
+
+The child streams of `scope!` must implement `Send`. Use `scope_local!`
+for child streams that do not.
+
+## Detecting unconsumed streams
+
+With the `must_consume` argument, dropping the returned stream before it ends
+panics in debug builds, with the name of the function and the location where
+the stream was created. Call
+[`MustConsume::cancel`](https://docs.rs/futures-async-stream/latest/futures_async_stream/trait.MustConsume.html#tymethod.cancel)
+to allow the stream to be dropped early. In release builds, and without the
+`std` feature, the check is removed:
+
+```
+#![feature(coroutines)]
+
+use futures::stream::StreamExt;
+use futures_async_stream::{MustConsume, stream};
+
+#[stream(item = i32, must_consume)]
+async fn numbers(n: i32) {
+    for i in 0..n {
+        yield i;
+    }
+}
+
+async fn first() -> Option<i32> {
+    let mut stream = Box::pin(numbers(10));
+    let first = stream.next().await;
+    // Without this, dropping the stream panics in debug builds.
+    stream.cancel();
+    first
+}
+```
+
+The returned stream implements `MustConsume` instead of `Stream`, which is a
+supertrait of it. `must_consume` may not be used together with `checkpoint`.
+
+## Multiple outputs
+
+With the `outputs(<name> = <type>, ...)` argument of `#[stream]` (requires the
+`std` feature), the function returns a struct with a stream for each output
+instead of a single stream. The struct is named after the function, e.g.
+`ParseOutputs` for `parse`. Use `yield_to!(<name>: <expr>)` instead of `yield`
+in the body to yield an item to an output (`yield <name>: <expr>` is not valid
+Rust syntax, so it cannot be used in the body of an attribute macro):
+
+```
+#![feature(coroutines)]
+
+use futures_async_stream::stream;
+
+enum Event {
+    Skipped(String),
+    Done,
+}
+
+#[stream(outputs(rows = Vec<i32>, events = Event))]
+async fn parse(lines: Vec<String>) {
+    for line in lines {
+        match line.split(',').map(str::parse).collect() {
+            Ok(row) => yield_to!(rows: row),
+            Err(_) => yield_to!(events: Event::Skipped(line)),
+        }
+    }
+    yield_to!(events: Event::Done);
+}
+
+let ParseOutputs { rows, events } = parse(vec!["1,2".to_owned()]);
+```
+
+The output streams share the body and can be consumed independently. Polling
+any of them resumes the body, and the items yielded to the other outputs are
+buffered until they are consumed, or discarded if the other outputs have been
+dropped.
+
+**Note:** The buffers are unbounded. If one output is polled and another is
+neither consumed nor dropped, the items of the latter accumulate in memory
+for as long as the body keeps running. Consume all the outputs concurrently
+(e.g., with `join`), or drop the ones that are not needed.
+
+The struct is defined next to the function, so `outputs` cannot be used on
+associated functions.
+
+## Items of multiple types
+
+`#[stream]` accepts an enum definition in place of the item type. Each variant
+must have a single unnamed field of a distinct type. The enum is defined next
+to the function with its visibility, along with `From` impls for its variants,
+so values of any of these types can be yielded directly:
+
+```
+#![feature(coroutines)]
+
+use futures_async_stream::stream;
+
+#[stream(item = #[derive(Debug)] enum Token<'a> { Word(&'a str), Number(u32) })]
+async fn tokenize<'a>(s: &'a str) {
+    for token in s.split_whitespace() {
+        match token.parse::<u32>() {
+            Ok(n) => yield n,
+            Err(_) => yield token,
+        }
+    }
+}
+```
+
+Yielding a value of the enum itself is also allowed. Because the enum is
+defined next to the function, it cannot be used on associated functions.
+
 ## Using async stream functions in traits
 
 You can use async stream functions in traits by passing `boxed` or
@@ -135,6 +539,12 @@ function that returns `Pin<Box<dyn Stream<Item = item> + Send + 'lifetime>>`.
 If you passed `boxed_local` instead of `boxed`, async stream function
 returns a non-thread-safe stream (`Pin<Box<dyn Stream<Item = item> + 'lifetime>>`).
 
+`boxed` and `boxed_local` require the `alloc` feature, which is enabled by
+the default `std` feature. In `no_std` crates, use
+`default-features = false, features = ["alloc"]` instead. Note that crates
+that previously used `default-features = false` must now enable the `alloc`
+feature to keep using `boxed` and `boxed_local`.
+
 ```
 #![feature(coroutines)]
 
@@ -161,96 +571,531 @@ impl Foo for Bar {
 }
 ```
 
-## `#[try_stream]`
+### Generating static-dispatch and object-safe traits together
 
-`?` operator can be used with the `#[try_stream]`. The `Item` of the
-returned stream is `Result` with `Ok` being the value yielded and `Err` the
-error type returned by `?` operator or `return Err(...)`.
+`#[stream_trait]` on a trait processes all `#[stream]` and `#[try_stream]`
+methods in it. Each method is converted to a method that returns
+`impl Stream` (without boxing), and a companion trait named `Dyn{Trait}`
+with the boxed versions of the methods is generated. The companion trait is
+implemented for all types that implement the original trait, so it can be
+used as a trait object.
 
 ```
 #![feature(coroutines)]
 
-use futures::stream::Stream;
-use futures_async_stream::try_stream;
+use futures_async_stream::stream_trait;
 
-#[try_stream(ok = i32, error = Box<dyn std::error::Error>)]
-async fn foo(stream: impl Stream<Item = String>) {
-    #[for_await]
-    for x in stream {
-        yield x.parse()?;
+#[stream_trait]
+pub trait Foo {
+    #[stream(item = u32)]
+    async fn method(&self);
+}
+
+struct Bar(u32);
+
+// Implementations also need `#[stream_trait]`.
+#[stream_trait]
+impl Foo for Bar {
+    #[stream(item = u32)]
+    async fn method(&self) {
+        for i in 0..self.0 {
+            yield i;
+        }
     }
 }
+
+// `DynFoo::method` returns `Pin<Box<dyn Stream<Item = u32> + Send + '_>>`.
+let foos: Vec<Box<dyn DynFoo>> = vec![Box::new(Bar(1)), Box::new(Bar(2))];
 ```
 
-`#[try_stream]` can be used wherever `#[stream]` can be used.
+The returned streams are `Send` unless `boxed_local` is passed to the
+method. The methods of the companion trait that have no receiver or have
+type parameters require `Self: Sized`, and cannot be called on trait
+objects. The argument and item types of the methods cannot refer to `Self`.
 
-To early exit from a `#[try_stream]` function or block, use `return Ok(())`.
+## Recursive async stream functions
 
-<!--
-## List of features that may be added in the future as an extension of this feature:
+An async stream function that calls itself cannot be compiled as is because
+the returned stream would contain itself. Pass `recursive` to box the
+recursive calls in the function body:
 
-- `async_sink` (https://github.com/rust-lang-nursery/futures-rs/pull/1548#issuecomment-486205382)
-- Support `.await` in macro (https://github.com/rust-lang-nursery/futures-rs/pull/1548#discussion_r285341883)
-- Parallel version of `for_await` (https://github.com/rustasync/runtime/pull/25)
--->
+```
+#![feature(coroutines)]
 
-## How to write the equivalent code without this API?
+use futures_async_stream::stream;
 
-### `#[for_await]`
+struct Tree {
+    value: i32,
+    children: Vec<Tree>,
+}
 
-You can write this by combining `while let` loop, `.await`, `pin!` macro,
-and `StreamExt::next()` method:
+#[stream(item = i32, recursive)]
+async fn walk(tree: &Tree) {
+    yield tree.value;
+    for child in &tree.children {
+        #[for_await]
+        for x in walk(child) {
+            yield x;
+        }
+    }
+}
+```
+
+Calls of the form `f(..)`, `Self::f(..)`, and `self.f(..)` are treated as
+recursive calls. The function still returns `impl Stream`, and the
+thread-safety of the stream is inferred as usual. `recursive` requires the
+`alloc` feature.
+
+## Limiting the size of async streams
+
+The stream returned by an async stream function stores all local variables
+that are held across `yield` or `.await`, so it can become large without
+notice. Pass `max_size = <bytes>` to fail compilation if the stream is larger
+than the given size:
 
 ```
-use std::pin::pin;
+#![feature(coroutines)]
 
-use futures::stream::{Stream, StreamExt};
+use futures_async_stream::stream;
 
-async fn collect(stream: impl Stream<Item = i32>) -> Vec<i32> {
-    let mut vec = vec![];
-    let mut stream = pin!(stream);
-    while let Some(value) = stream.next().await {
-        vec.push(value);
+#[stream(item = u8, max_size = 64)]
+async fn bytes(data: Vec<u8>) {
+    for b in data {
+        yield b;
     }
-    vec
 }
 ```
 
-### `#[stream]`
+This is checked after monomorphization, so `cargo check` does not report it;
+use `cargo build` or `cargo test` instead. To see the size of the stream at
+each suspension point, compile with `-Z print-type-sizes`.
 
-You can write this by manually implementing the combinator:
+Pass `auto_box_over = <bytes>` to store the stream on the heap only if it is
+larger than the given size. Otherwise, the stream is stored inline, without
+allocation:
 
 ```
-use std::{
-    pin::Pin,
-    task::{ready, Context, Poll},
-};
+#![feature(coroutines)]
 
-use futures::stream::Stream;
-use pin_project::pin_project;
+use futures_async_stream::stream;
 
-fn foo<S>(stream: S) -> impl Stream<Item = i32>
-where
-    S: Stream<Item = String>,
-{
-    Foo { stream }
+#[stream(item = u8, auto_box_over = 256)]
+async fn large() {
+    let buf = [0; 4096];
+    for b in buf {
+        yield b;
+    }
 }
+```
 
-#[pin_project]
-struct Foo<S> {
-    #[pin]
-    stream: S,
-}
+`auto_box_over` requires the `alloc` feature and cannot be used together with
+`boxed` or `boxed_local`. When both are specified, `max_size` checks the size
+after `auto_box_over` is applied.
 
-impl<S> Stream for Foo<S>
-where
-    S: Stream<Item = String>,
-{
-    type Item = i32;
+## Movable async streams
 
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        if let Some(x) = ready!(self.project().stream.poll_next(cx)) {
-            Poll::Ready(Some(x.parse().unwrap()))
+By default, the stream returned by an async stream function does not implement
+`Unpin`, because the body may hold borrows of its own local variables across
+`yield` or `.await`. If the body does not need this, pass `movable` to make the
+stream `Unpin`, so it can be polled without pinning:
+
+```
+#![feature(coroutines)]
+
+use futures::stream::StreamExt;
+use futures_async_stream::stream;
+
+#[stream(item = i32, movable)]
+async fn range(n: i32) {
+    for i in 0..n {
+        yield i;
+    }
+}
+
+async fn first() -> Option<i32> {
+    range(3).next().await
+}
+```
+
+In `movable` streams, futures and streams used with `.await` and `#[for_await]`
+must be `Unpin`; use `Box::pin` for those that are not. Bodies that hold a
+borrow across `yield` or `.await` are rejected with "borrow may still be in use
+when coroutine yields", pointing at the borrow; the accompanying "add `static`"
+suggestion refers to the generated coroutine and means removing `movable`.
+
+Pass `clone` (which implies `movable`) to make the stream implement `Clone`.
+This allows forking a stream in the middle of iteration. All captured
+arguments and all local variables held across `yield` or `.await` must
+implement `Clone`, and the crate that defines the function must enable the
+`coroutine_clone` feature:
+
+```
+#![feature(coroutines, coroutine_clone)]
+
+use futures::stream::StreamExt;
+use futures_async_stream::stream;
+
+#[stream(item = String, clone)]
+async fn prefixes(words: Vec<&'static str>) {
+    let mut buf = String::new();
+    for word in words {
+        buf.push_str(word);
+        yield buf.clone();
+    }
+}
+
+async fn fork() {
+    let mut s = prefixes(vec!["a", "b"]);
+    assert_eq!(s.next().await.as_deref(), Some("a"));
+    let rest = s.clone();
+    assert_eq!(s.collect::<Vec<_>>().await, rest.collect::<Vec<_>>().await);
+}
+```
+
+`clone` cannot be used together with `boxed`, `boxed_local`, or
+`auto_box_over`.
+
+## Controlling captured lifetimes
+
+By default, the stream returned by an async stream function is bound by all
+lifetimes of the function, including elided ones. You can list the generic
+parameters that the stream actually captures via the `captures` argument:
+
+```
+#![feature(coroutines)]
+
+use futures_async_stream::stream;
+
+// The returned stream borrows `x`, but not `_y`.
+#[stream(item = &'a str, captures = ['a])]
+async fn foo<'a>(x: &'a str, _y: &str) {
+    yield x;
+}
+```
+
+For non-boxed streams this generates a [precise capturing](https://doc.rust-lang.org/std/keyword.use.html#precise-capturing)
+bound (`+ use<..>`), so every type parameter in scope must also be
+listed. For boxed streams, only the listed lifetimes are used as bounds of
+the trait object. Generic parameters of the enclosing `impl` block can also
+be listed; whether they are in scope is checked by the compiler.
+
+## Re-exporting the macros
+
+The expansions of the macros refer to `::futures_async_stream`. If you
+re-export the macros from another crate, pass the path to the re-exported
+crate via the `crate` argument:
+
+```
+#![feature(coroutines, proc_macro_hygiene, stmt_expr_attributes)]
+
+mod facade {
+    pub use futures_async_stream::*;
+}
+
+use facade::{for_await, stream, stream_block};
+
+#[stream(crate = facade, item = i32)]
+async fn foo() {
+    yield 1;
+}
+
+async fn bar() {
+    // `stream_block!` and `try_stream_block!` take `crate = <path>;` at the beginning.
+    let s = stream_block! {
+        crate = facade;
+        yield 1;
+    };
+    #[for_await(crate = facade)]
+    for x in s {}
+}
+```
+
+`#[for_await]`, `#[stream]`, and `#[try_stream]` that are nested in async
+stream functions or blocks inherit the path of the outer one.
+
+## Using with other attribute macros
+
+Attribute macros are expanded from the outermost (topmost) one. Place
+`#[stream]` and `#[try_stream]` above other attributes on the function; the
+attributes below them (including attribute macros such as
+`#[tracing::instrument]`) are forwarded to the generated function, which
+returns the stream.
+
+```
+#![feature(coroutines)]
+
+use futures_async_stream::stream;
+
+#[stream(item = i32)]
+#[inline]
+#[must_use]
+async fn foo(x: i32) {
+    yield x;
+}
+```
+
+If another attribute macro that rewrites async fn is expanded first, the
+function is no longer async, and `#[stream]` reports an error asking to
+reorder the attributes.
+
+`#[async_trait]` on traits and impls is always expanded before the
+attributes on their methods. `#[stream]` and `#[try_stream]` detect async
+fn rewritten by `#[async_trait]` and convert it back, so they can be used
+inside `#[async_trait]` traits and impls together with `boxed` or
+`boxed_local`. This requires async-trait 0.1.48 or later; the functions
+rewritten by older versions are rejected with an error:
+
+```
+#![feature(coroutines)]
+
+use async_trait::async_trait;
+use futures_async_stream::stream;
+
+#[async_trait]
+trait Foo {
+    async fn future(&self) -> i32;
+
+    #[stream(boxed, item = i32)]
+    async fn stream(&self);
+}
+
+struct Bar;
+
+#[async_trait]
+impl Foo for Bar {
+    async fn future(&self) -> i32 {
+        1
+    }
+
+    #[stream(boxed, item = i32)]
+    async fn stream(&self) {
+        yield self.future().await;
+    }
+}
+```
+
+## `#[try_stream]`
+
+`?` operator can be used with the `#[try_stream]`. The `Item` of the
+returned stream is `Result` with `Ok` being the value yielded and `Err` the
+error type returned by `?` operator or `return Err(...)`.
+
+```
+#![feature(coroutines)]
+
+use futures::stream::Stream;
+use futures_async_stream::try_stream;
+
+#[try_stream(ok = i32, error = Box<dyn std::error::Error>)]
+async fn foo(stream: impl Stream<Item = String>) {
+    #[for_await]
+    for x in stream {
+        yield x.parse()?;
+    }
+}
+```
+
+`#[try_stream]` can be used wherever `#[stream]` can be used.
+
+To early exit from a `#[try_stream]` function or block, use `return Ok(())`.
+
+Returning an error ends the stream. To report an error and keep going, use
+`yield_err!(e)`, which yields `Err(e.into())` like `?` but does not end the
+stream. With the `continue_on_error` argument, `?` in the body of a loop does
+this and continues with the next iteration of the loop. `?` outside loops
+still ends the stream:
+
+```
+#![feature(coroutines)]
+
+use futures::stream::Stream;
+use futures_async_stream::try_stream;
+
+#[try_stream(ok = i32, error = std::num::ParseIntError, continue_on_error)]
+async fn parse(lines: impl Stream<Item = String>) {
+    #[for_await]
+    for line in lines {
+        // Invalid lines are reported as `Err` items.
+        yield line.parse()?;
+    }
+}
+```
+
+With the `context` argument, `?` records where the error came from: the
+error is converted into the `error` type, and then
+[`WithLocation::with_location`](https://docs.rs/futures-async-stream/latest/futures_async_stream/trait.WithLocation.html)
+is called with the name of the function and the file, line, and column of
+`?`. `WithLocation` is implemented for `Box<dyn Error>` (with and without
+`Send` and `Sync`), and can be implemented for custom error types. Without
+the `context` argument, `?` is not changed:
+
+```
+#![feature(coroutines)]
+
+use futures_async_stream::try_stream;
+
+#[try_stream(ok = i32, error = Box<dyn std::error::Error>, context)]
+async fn parse(lines: Vec<String>) {
+    for line in lines {
+        // The error is displayed as
+        // "invalid digit found in string (in `parse` at src/lib.rs:10:27)".
+        yield line.parse()?;
+    }
+}
+```
+
+With the `catch_panic` argument (requires the default `std` feature), a panic
+in the body of the stream is yielded as an error converted from
+[`PanicPayload`](https://docs.rs/futures-async-stream/latest/futures_async_stream/struct.PanicPayload.html)
+by `From`, and then the stream ends instead of propagating the panic to the
+consumer. `PanicPayload` implements `Error`, so `Box<dyn Error>` can be used
+as the error type as is:
+
+```
+#![feature(coroutines)]
+
+use futures_async_stream::try_stream;
+
+#[try_stream(ok = i32, error = Box<dyn std::error::Error>, catch_panic)]
+async fn parse(lines: Vec<String>) {
+    for line in lines {
+        // A panic here is yielded as an error: "stream panicked: ..."
+        yield line.parse().unwrap();
+    }
+}
+```
+
+With the `checkpoint = <type>` argument, `checkpoint!(<cursor>)` in the body
+commits a checkpoint, and the returned stream implements
+[`CheckpointStream`](https://docs.rs/futures-async-stream/latest/futures_async_stream/trait.CheckpointStream.html),
+whose `checkpoint` method returns the last committed checkpoint.
+[`retry`](https://docs.rs/futures-async-stream/latest/futures_async_stream/fn.retry.html)
+uses it to restart the stream from where it failed instead of starting over:
+
+```
+#![feature(coroutines)]
+
+use futures::stream::Stream;
+use futures_async_stream::{retry, try_stream};
+
+#[try_stream(ok = String, error = std::io::Error, checkpoint = usize)]
+async fn fetch(start: Option<usize>) {
+    for page in start.unwrap_or(0)..10 {
+        yield fetch_page(page).await?;
+        checkpoint!(page + 1);
+    }
+}
+
+// Restarts `fetch` with the last checkpoint up to 3 times in a row.
+fn fetch_with_retry() -> impl Stream<Item = std::io::Result<String>> {
+    retry(fetch, |_: &std::io::Error, attempts| attempts < 3)
+}
+
+async fn fetch_page(page: usize) -> std::io::Result<String> {
+    Ok(page.to_string())
+}
+```
+
+The item type is not limited to `Result`. With the `output = <type>` argument
+instead of `ok` and `error`, `?` works with any type that implements
+[`TryOutput`](https://docs.rs/futures-async-stream/latest/futures_async_stream/trait.TryOutput.html),
+such as `Option<T>` and `ControlFlow<B, T>`. `yield` takes the output type of
+`?` (`T`), and the body returns `Option<()>` or `ControlFlow<B>`. With
+`Option`, `?` on `None` ends the stream without yielding an item. With
+`ControlFlow`, `Break` is yielded as the last item:
+
+```
+#![feature(coroutines)]
+
+use std::ops::ControlFlow;
+
+use futures_async_stream::try_stream;
+
+// The items are `i32`, and the stream ends at the first `None`.
+#[try_stream(output = Option<i32>)]
+async fn prefix(items: Vec<Option<i32>>) {
+    for x in items {
+        yield x?;
+    }
+}
+
+// The items are `ControlFlow<String, i32>`.
+#[try_stream(output = ControlFlow<String, i32>)]
+async fn until_empty(lines: Vec<String>) {
+    for line in lines {
+        if line.is_empty() {
+            return ControlFlow::Break("empty line".to_owned());
+        }
+        yield line.len() as i32;
+    }
+}
+```
+
+<!--
+## List of features that may be added in the future as an extension of this feature:
+
+- `async_sink` (https://github.com/rust-lang-nursery/futures-rs/pull/1548#issuecomment-486205382)
+- Support `.await` in macro (https://github.com/rust-lang-nursery/futures-rs/pull/1548#discussion_r285341883)
+- Parallel version of `for_await` (https://github.com/rustasync/runtime/pull/25)
+-->
+
+## How to write the equivalent code without this API?
+
+### `#[for_await]`
+
+You can write this by combining `while let` loop, `.await`, `pin!` macro,
+and `StreamExt::next()` method:
+
+```
+use std::pin::pin;
+
+use futures::stream::{Stream, StreamExt};
+
+async fn collect(stream: impl Stream<Item = i32>) -> Vec<i32> {
+    let mut vec = vec![];
+    let mut stream = pin!(stream);
+    while let Some(value) = stream.next().await {
+        vec.push(value);
+    }
+    vec
+}
+```
+
+### `#[stream]`
+
+You can write this by manually implementing the combinator:
+
+```
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures::stream::Stream;
+use pin_project::pin_project;
+
+fn foo<S>(stream: S) -> impl Stream<Item = i32>
+where
+    S: Stream<Item = String>,
+{
+    Foo { stream }
+}
+
+#[pin_project]
+struct Foo<S> {
+    #[pin]
+    stream: S,
+}
+
+impl<S> Stream for Foo<S>
+where
+    S: Stream<Item = String>,
+{
+    type Item = i32;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(x) = ready!(self.project().stream.poll_next(cx)) {
+            Poll::Ready(Some(x.parse().unwrap()))
         } else {
             Poll::Ready(None)
         }
@@ -285,260 +1130,2442 @@ where
     clippy::std_instead_of_core,
     clippy::missing_inline_in_public_items,
 )]
-#![feature(coroutine_trait)]
+#![feature(coroutine_trait, try_trait_v2)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(any(test, feature = "std"))]
+extern crate std;
+
+#[cfg(test)]
+mod tests;
+
+#[cfg(test)]
+#[path = "gen/tests/assert_impl.rs"]
+mod assert_impl;
+#[cfg(test)]
+#[path = "gen/tests/track_size.rs"]
+mod track_size;
+
+#[doc(inline)]
+pub use futures_async_stream_macro::for_await;
+#[doc(inline)]
+pub use futures_async_stream_macro::stream;
+#[doc(inline)]
+pub use futures_async_stream_macro::stream_block;
+#[doc(inline)]
+pub use futures_async_stream_macro::stream_trait;
+#[doc(inline)]
+pub use futures_async_stream_macro::try_stream;
+#[doc(inline)]
+pub use futures_async_stream_macro::try_stream_block;
+
+#[cfg(feature = "std")]
+pub use crate::try_stream::PanicPayload;
+pub use crate::{
+    checkpoint::{CheckpointStream, retry},
+    context::{Location, WithLocation},
+    must_consume::MustConsume,
+    try_stream::TryOutput,
+};
+#[cfg(feature = "alloc")]
+pub use crate::{
+    scope::{LocalScope, Scope},
+    stop::{StopHandle, StopSignal},
+};
+
+mod future {
+    use core::{
+        future::Future,
+        mem,
+        ops::{Coroutine, CoroutineState},
+        pin::Pin,
+        ptr::NonNull,
+        task::{Context, Poll},
+    };
+
+    use futures_core::future::FusedFuture;
+    use pin_project::pin_project;
+
+    // Based on https://github.com/rust-lang/rust/blob/1.66.0/library/core/src/future/mod.rs.
+    // TODO: can we avoid GenFuture? https://github.com/rust-lang/rust/commit/9f36f988ad873f5d34cd9c08e4903c597ffc9532
+
+    /// This type is needed because:
+    ///
+    /// a) Coroutines cannot implement `for<'a, 'b> Coroutine<&'a mut Context<'b>>`, so we need to pass
+    ///    a raw pointer (see <https://github.com/rust-lang/rust/issues/68923>).
+    /// b) Raw pointers and `NonNull` aren't `Send` or `Sync`, so that would make every single future
+    ///    non-Send/Sync as well, and we don't want that.
+    ///
+    /// It also simplifies the lowering of `.await`.
+    ///
+    /// The second field holds the pointers to the state of the stream that is
+    /// resuming the coroutine, which are only needed by some streams (see
+    /// [`WithSizeHint`] and [`WithCheckpoint`]). It is `()` otherwise, so
+    /// `ResumeTy` is a single pointer.
+    #[doc(hidden)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct ResumeTy<E = ()>(pub(crate) NonNull<Context<'static>>, pub(crate) E);
+
+    /// The return type of `Stream::size_hint`.
+    pub(crate) type SizeHint = (usize, Option<usize>);
+
+    /// Points to the size hint of the stream that is resuming the coroutine.
+    /// This is used by the lowering of `size_hint!`.
+    #[doc(hidden)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct WithSizeHint(pub(crate) NonNull<SizeHint>);
+
+    /// Points to the checkpoint (`Option<C>`) of the stream that is resuming
+    /// the coroutine, along with `E` of the stream without a checkpoint. This
+    /// is used by the lowering of `checkpoint!`.
+    #[doc(hidden)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct WithCheckpoint<E = ()>(pub(crate) E, pub(crate) NonNull<()>);
+
+    // SAFETY: the caller of the `get_context` function that dereferences a
+    // pointer must guarantee that no data races will occur.
+    // Note: Since https://github.com/rust-lang/rust/pull/95985, `Context` is
+    // `!Send` and `!Sync`.
+    unsafe impl<E> Send for ResumeTy<E> {}
+    // SAFETY: see `Send` impl
+    unsafe impl<E> Sync for ResumeTy<E> {}
+
+    // Needed to work around old nightly bug (fixed in nightly-2024-05-24 by https://github.com/rust-lang/rust/pull/125392)
+    impl<E> core::panic::UnwindSafe for ResumeTy<E> {}
+    impl<E> core::panic::RefUnwindSafe for ResumeTy<E> {}
+
+    /// The storage of the size hint of a stream.
+    ///
+    /// Streams without `size_hint` and `size_hint!` use `()`, so they do not
+    /// store the size hint and report `(0, None)`.
+    pub(crate) trait SizeHintStorage {
+        /// The second field of `ResumeTy` passed to the coroutine.
+        type Ext: Copy;
+
+        fn ext(&mut self) -> Self::Ext;
+
+        /// Returns the size hint of the stream that has not terminated.
+        fn get(&self) -> SizeHint;
+
+        /// Updates the size hint after an item is yielded, as the size hint
+        /// is the number of the remaining items.
+        fn consume(&mut self);
+    }
+
+    impl SizeHintStorage for () {
+        type Ext = ();
+
+        #[inline]
+        fn ext(&mut self) {}
+
+        #[inline]
+        fn get(&self) -> SizeHint {
+            (0, None)
+        }
+
+        #[inline]
+        fn consume(&mut self) {}
+    }
+
+    impl SizeHintStorage for SizeHint {
+        type Ext = WithSizeHint;
+
+        #[inline]
+        fn ext(&mut self) -> WithSizeHint {
+            WithSizeHint(NonNull::from(self))
+        }
+
+        #[inline]
+        fn get(&self) -> SizeHint {
+            *self
+        }
+
+        #[inline]
+        fn consume(&mut self) {
+            self.0 = self.0.saturating_sub(1);
+            self.1 = self.1.map(|upper| upper.saturating_sub(1));
+        }
+    }
+
+    /// Wrap a coroutine in a future.
+    ///
+    /// This function returns a `GenFuture` underneath, but hides it in `impl Trait` to give
+    /// better error messages (`impl Future` rather than `GenFuture<[closure.....]>`).
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_coroutine<G>(g: G) -> impl FusedFuture<Output = G::Return>
+    where
+        G: Coroutine<ResumeTy, Yield = ()>,
+    {
+        GenFuture(Some(g))
+    }
+
+    #[pin_project]
+    pub(crate) struct GenFuture<G>(#[pin] Option<G>);
+
+    impl<G> Future for GenFuture<G>
+    where
+        G: Coroutine<ResumeTy, Yield = ()>,
+    {
+        type Output = G::Return;
+
+        #[inline]
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            match resume(self.project().0, cx, ()) {
+                Some(CoroutineState::Complete(x)) => Poll::Ready(x),
+                // Like `futures::future::Fuse`, a terminated future never completes again.
+                Some(CoroutineState::Yielded(())) | None => Poll::Pending,
+            }
+        }
+    }
+
+    impl<G> FusedFuture for GenFuture<G>
+    where
+        G: Coroutine<ResumeTy, Yield = ()>,
+    {
+        #[inline]
+        fn is_terminated(&self) -> bool {
+            self.0.is_none()
+        }
+    }
+
+    /// Resumes the coroutine, and drops it when it completes or panics.
+    ///
+    /// Returns `None` if the coroutine has already been dropped. This makes
+    /// futures and streams fused, and defines the behavior of polling them
+    /// after a panic: a future or stream that panicked is terminated, as if it
+    /// had completed.
+    #[inline]
+    pub(crate) fn resume<G, E>(
+        g: Pin<&mut Option<G>>,
+        cx: &mut Context<'_>,
+        ext: E,
+    ) -> Option<CoroutineState<G::Yield, G::Return>>
+    where
+        G: Coroutine<ResumeTy<E>>,
+    {
+        struct DropOnUnwind<'a, G>(Pin<&'a mut Option<G>>);
+
+        impl<G> Drop for DropOnUnwind<'_, G> {
+            fn drop(&mut self) {
+                self.0.set(None);
+            }
+        }
+
+        let mut guard = DropOnUnwind(g);
+        let coroutine = guard.0.as_mut().as_pin_mut()?;
+        // Resume the coroutine, turning the `&mut Context` into a `NonNull` raw pointer. The
+        // `.await` lowering will safely cast that back to a `&mut Context`.
+        let state = coroutine.resume(ResumeTy(NonNull::from(cx).cast::<Context<'static>>(), ext));
+        match state {
+            // Drop the completed coroutine.
+            CoroutineState::Complete(_) => drop(guard),
+            CoroutineState::Yielded(_) => mem::forget(guard),
+        }
+        Some(state)
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    #[must_use]
+    pub unsafe fn get_context<'a, 'b, E: Copy>(cx: ResumeTy<E>) -> &'a mut Context<'b> {
+        // SAFETY: the caller must guarantee that `cx.0` is a valid pointer
+        // that fulfills all the requirements for a mutable reference.
+        unsafe { &mut *cx.0.as_ptr().cast::<Context<'b>>() }
+    }
+
+    /// Requires `f` to be a poll function that accepts `&mut Context<'_>` of
+    /// any lifetime, so the context cannot escape from it.
+    #[doc(hidden)]
+    #[inline]
+    pub fn poll_fn<F, T>(f: F) -> F
+    where
+        F: FnMut(&mut Context<'_>) -> Poll<T>,
+    {
+        f
+    }
+
+    /// Calls `f` with the context. `f` must accept `&mut Context<'_>` of any
+    /// lifetime, so the context cannot escape from it.
+    #[doc(hidden)]
+    #[inline]
+    pub fn with_context<F, R>(f: F, cx: &mut Context<'_>) -> R
+    where
+        F: FnOnce(&mut Context<'_>) -> R,
+    {
+        f(cx)
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub unsafe fn set_size_hint<E: HasSizeHint>(
+        cx: ResumeTy<E>,
+        lower: usize,
+        upper: Option<usize>,
+    ) {
+        // SAFETY: the caller must guarantee that `cx` is the argument of the
+        // current resumption, so the size hint is a valid pointer that
+        // fulfills all the requirements for a mutable reference.
+        unsafe { *cx.1.size_hint().as_ptr() = (lower, upper) }
+    }
+
+    /// The second field of `ResumeTy` that points to the size hint.
+    #[doc(hidden)]
+    pub trait HasSizeHint: Copy {
+        #[doc(hidden)]
+        fn size_hint(self) -> NonNull<SizeHint>;
+    }
+
+    impl HasSizeHint for WithSizeHint {
+        #[inline]
+        fn size_hint(self) -> NonNull<SizeHint> {
+            self.0
+        }
+    }
+
+    impl<E: HasSizeHint> HasSizeHint for WithCheckpoint<E> {
+        #[inline]
+        fn size_hint(self) -> NonNull<SizeHint> {
+            self.0.size_hint()
+        }
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    pub unsafe fn set_checkpoint<C, E: Copy>(cx: ResumeTy<WithCheckpoint<E>>, checkpoint: C) {
+        // SAFETY: the caller must guarantee that `cx` is the argument of the
+        // current resumption of a coroutine that is resumed by a stream with
+        // the checkpoint of type `C`, so `cx.1.1` is a valid pointer to
+        // `Option<C>` that fulfills all the requirements for a mutable
+        // reference.
+        unsafe { *cx.1.1.cast::<Option<C>>().as_ptr() = Some(checkpoint) }
+    }
+}
+
+mod stream {
+    use core::{
+        future::Future,
+        ops::{Coroutine, CoroutineState},
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use futures_core::stream::{FusedStream, Stream};
+    use pin_project::pin_project;
+
+    use crate::future::{ResumeTy, SizeHint, SizeHintStorage, WithSizeHint, resume};
+
+    /// Wrap a coroutine in a stream.
+    ///
+    /// This function returns a `GenStream` underneath, but hides it in `impl Trait` to give
+    /// better error messages (`impl Stream` rather than `GenStream<[closure.....]>`).
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_coroutine<G, T>(g: G) -> impl FusedStream<Item = T>
+    where
+        G: Coroutine<ResumeTy, Yield = Poll<T>, Return = ()>,
+    {
+        GenStream(Some(g), ())
+    }
+
+    /// Wrap a movable coroutine in a stream that implements `Clone`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_clone_coroutine<G, T>(g: G) -> impl FusedStream<Item = T> + Clone
+    where
+        G: Coroutine<ResumeTy, Yield = Poll<T>, Return = ()> + Clone,
+    {
+        GenStream(Some(g), ())
+    }
+
+    /// Wrap a coroutine in a stream that stores its size hint, which can be
+    /// updated by `size_hint!`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_coroutine_with_size_hint<G, T>(
+        size_hint: SizeHint,
+        g: G,
+    ) -> impl FusedStream<Item = T>
+    where
+        G: Coroutine<ResumeTy<WithSizeHint>, Yield = Poll<T>, Return = ()>,
+    {
+        GenStream(Some(g), size_hint)
+    }
+
+    /// Wrap a movable coroutine in a stream that stores its size hint and
+    /// implements `Clone`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_clone_coroutine_with_size_hint<G, T>(
+        size_hint: SizeHint,
+        g: G,
+    ) -> impl FusedStream<Item = T> + Clone
+    where
+        G: Coroutine<ResumeTy<WithSizeHint>, Yield = Poll<T>, Return = ()> + Clone,
+    {
+        GenStream(Some(g), size_hint)
+    }
+
+    // `H` is `SizeHint` if the stream stores its size hint, and `()` otherwise.
+    #[derive(Clone)]
+    #[pin_project]
+    pub(crate) struct GenStream<G, H = ()>(#[pin] Option<G>, H);
+
+    impl<G, H, T> Stream for GenStream<G, H>
+    where
+        G: Coroutine<ResumeTy<H::Ext>, Yield = Poll<T>, Return = ()>,
+        H: SizeHintStorage,
+    {
+        type Item = T;
+
+        #[inline]
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let this = self.project();
+            match resume(this.0, cx, this.1.ext()) {
+                Some(CoroutineState::Yielded(x)) => x.map(|x| {
+                    this.1.consume();
+                    Some(x)
+                }),
+                Some(CoroutineState::Complete(())) | None => Poll::Ready(None),
+            }
+        }
+
+        #[inline]
+        fn size_hint(&self) -> SizeHint {
+            if self.0.is_some() { self.1.get() } else { (0, Some(0)) }
+        }
+    }
+
+    impl<G, H, T> FusedStream for GenStream<G, H>
+    where
+        G: Coroutine<ResumeTy<H::Ext>, Yield = Poll<T>, Return = ()>,
+        H: SizeHintStorage,
+    {
+        #[inline]
+        fn is_terminated(&self) -> bool {
+            self.0.is_none()
+        }
+    }
+
+    /// Counts the consecutive ready polls of futures and streams in the body of
+    /// `#[stream(budget = <count>)]` and `#[try_stream(budget = <count>)]`.
+    #[doc(hidden)]
+    #[derive(Debug)]
+    pub struct Budget {
+        remaining: usize,
+        limit: usize,
+    }
+
+    impl Budget {
+        #[doc(hidden)]
+        #[inline]
+        #[must_use]
+        pub fn new(limit: usize) -> Self {
+            assert!(limit != 0, "`budget` must be greater than zero");
+            Self { remaining: limit, limit }
+        }
+
+        /// Records a ready poll. Returns `true` if the budget is exhausted, in
+        /// which case the stream must yield `Poll::Pending` after waking the task.
+        #[doc(hidden)]
+        #[inline]
+        pub fn ready(&mut self) -> bool {
+            self.remaining = self.remaining.saturating_sub(1);
+            if self.remaining == 0 {
+                self.reset();
+                true
+            } else {
+                false
+            }
+        }
+
+        /// Resets the budget. This is called every time the stream returns
+        /// control to the executor.
+        #[doc(hidden)]
+        #[inline]
+        pub fn reset(&mut self) {
+            self.remaining = self.limit;
+        }
+    }
+
+    // This is equivalent to the `futures::stream::StreamExt::next` method.
+    // But we want to make this crate dependency as small as possible, so we define our `next` function.
+    #[doc(hidden)]
+    #[inline]
+    pub fn next<S>(stream: &mut S) -> impl Future<Output = Option<S::Item>> + '_
+    where
+        S: Stream + Unpin,
+    {
+        Next(stream)
+    }
+
+    pub(crate) struct Next<'a, S>(&'a mut S);
+
+    impl<S> Future for Next<'_, S>
+    where
+        S: Stream + Unpin,
+    {
+        type Output = Option<S::Item>;
+
+        #[inline]
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            Pin::new(&mut *self.0).poll_next(cx)
+        }
+    }
+}
+
+mod try_stream {
+    use core::{
+        convert::Infallible,
+        marker::PhantomData,
+        ops::{ControlFlow, Coroutine, CoroutineState, Try},
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use futures_core::stream::{FusedStream, Stream};
+    use pin_project::pin_project;
+
+    use crate::future::{ResumeTy, SizeHint, SizeHintStorage, WithSizeHint, resume};
+
+    /// Wrap a coroutine in a stream.
+    ///
+    /// This function returns a `GenStream` underneath, but hides it in `impl Trait` to give
+    /// better error messages (`impl Stream` rather than `GenStream<[closure.....]>`).
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_coroutine<G, T, E>(g: G) -> impl FusedStream<Item = Result<T, E>>
+    where
+        G: Coroutine<ResumeTy, Yield = Poll<Result<T, E>>, Return = Result<(), E>>,
+    {
+        GenTryStream(Some(g), ())
+    }
+
+    /// Wrap a movable coroutine in a stream that implements `Clone`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_clone_coroutine<G, T, E>(g: G) -> impl FusedStream<Item = Result<T, E>> + Clone
+    where
+        G: Coroutine<ResumeTy, Yield = Poll<Result<T, E>>, Return = Result<(), E>> + Clone,
+    {
+        GenTryStream(Some(g), ())
+    }
+
+    /// Wrap a coroutine in a stream that stores its size hint, which can be
+    /// updated by `size_hint!`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_coroutine_with_size_hint<G, T, E>(
+        size_hint: SizeHint,
+        g: G,
+    ) -> impl FusedStream<Item = Result<T, E>>
+    where
+        G: Coroutine<ResumeTy<WithSizeHint>, Yield = Poll<Result<T, E>>, Return = Result<(), E>>,
+    {
+        GenTryStream(Some(g), size_hint)
+    }
+
+    /// Wrap a movable coroutine in a stream that stores its size hint and
+    /// implements `Clone`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_clone_coroutine_with_size_hint<G, T, E>(
+        size_hint: SizeHint,
+        g: G,
+    ) -> impl FusedStream<Item = Result<T, E>> + Clone
+    where
+        G: Coroutine<ResumeTy<WithSizeHint>, Yield = Poll<Result<T, E>>, Return = Result<(), E>>
+            + Clone,
+    {
+        GenTryStream(Some(g), size_hint)
+    }
+
+    // `H` is `SizeHint` if the stream stores its size hint, and `()` otherwise.
+    #[derive(Clone)]
+    #[pin_project]
+    pub(crate) struct GenTryStream<G, H = ()>(#[pin] Option<G>, H);
+
+    impl<G, H, T, E> Stream for GenTryStream<G, H>
+    where
+        G: Coroutine<ResumeTy<H::Ext>, Yield = Poll<Result<T, E>>, Return = Result<(), E>>,
+        H: SizeHintStorage,
+    {
+        type Item = Result<T, E>;
+
+        #[inline]
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let this = self.project();
+            match resume(this.0, cx, this.1.ext()) {
+                // `yield_err!` yields `Err` without completing the coroutine.
+                Some(CoroutineState::Yielded(x)) => x.map(|x| {
+                    this.1.consume();
+                    Some(x)
+                }),
+                Some(CoroutineState::Complete(Err(e))) => Poll::Ready(Some(Err(e))),
+                Some(CoroutineState::Complete(Ok(()))) | None => Poll::Ready(None),
+            }
+        }
+
+        #[inline]
+        fn size_hint(&self) -> SizeHint {
+            if self.0.is_some() { self.1.get() } else { (0, Some(0)) }
+        }
+    }
+
+    impl<G, H, T, E> FusedStream for GenTryStream<G, H>
+    where
+        G: Coroutine<ResumeTy<H::Ext>, Yield = Poll<Result<T, E>>, Return = Result<(), E>>,
+        H: SizeHintStorage,
+    {
+        #[inline]
+        fn is_terminated(&self) -> bool {
+            self.0.is_none()
+        }
+    }
+
+    /// A [`Try`] type that can be used as the `output` argument of
+    /// [`#[try_stream]`](crate::try_stream).
+    ///
+    /// `yield <value>` in the body of the stream yields `into_item(<value>)`.
+    /// The body returns `Return`, and if `?` or `return` exits the body with a
+    /// residual, `last_item(<residual>)` is yielded before the stream ends.
+    ///
+    /// This is implemented for [`Result`], [`Option`], and [`ControlFlow`],
+    /// and can be implemented for custom `Try` types.
+    pub trait TryOutput: Try {
+        /// The item type of the stream.
+        type Item;
+
+        /// The return type of the body of the stream.
+        ///
+        /// This is usually `Self` with `()` as the output type.
+        type Return: Try<Output = (), Residual = Self::Residual>;
+
+        /// Converts a yielded value into an item.
+        fn into_item(output: Self::Output) -> Self::Item;
+
+        /// Converts the residual that ended the stream into the last item.
+        fn last_item(residual: Self::Residual) -> Option<Self::Item>;
+    }
+
+    /// The stream yields `Ok` items, and the error that ended the stream as
+    /// the last item, like `#[try_stream(ok = T, error = E)]`.
+    impl<T, E> TryOutput for Result<T, E> {
+        type Item = Self;
+        type Return = Result<(), E>;
+
+        #[inline]
+        fn into_item(output: T) -> Self {
+            Ok(output)
+        }
+
+        #[inline]
+        fn last_item(residual: Result<Infallible, E>) -> Option<Self> {
+            match residual {
+                Err(e) => Some(Err(e)),
+            }
+        }
+    }
+
+    /// The stream yields values as is, and `None` ends the stream without
+    /// yielding an item.
+    impl<T> TryOutput for Option<T> {
+        type Item = T;
+        type Return = Option<()>;
+
+        #[inline]
+        fn into_item(output: T) -> T {
+            output
+        }
+
+        #[inline]
+        fn last_item(_: Option<Infallible>) -> Option<T> {
+            None
+        }
+    }
+
+    /// The stream yields `Continue` items, and the `Break` that ended the
+    /// stream as the last item.
+    impl<B, C> TryOutput for ControlFlow<B, C> {
+        type Item = Self;
+        type Return = ControlFlow<B>;
+
+        #[inline]
+        fn into_item(output: C) -> Self {
+            ControlFlow::Continue(output)
+        }
+
+        #[inline]
+        fn last_item(residual: ControlFlow<B, Infallible>) -> Option<Self> {
+            match residual {
+                ControlFlow::Break(b) => Some(ControlFlow::Break(b)),
+            }
+        }
+    }
+
+    /// Returns the value returned when the body of the stream completes.
+    #[doc(hidden)]
+    #[inline]
+    #[must_use]
+    pub fn complete<O: TryOutput>() -> O::Return {
+        O::Return::from_output(())
+    }
+
+    /// Wrap a coroutine that returns `O::Return` in a stream.
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_output_coroutine<G, O>(g: G) -> impl FusedStream<Item = O::Item>
+    where
+        G: Coroutine<ResumeTy, Yield = Poll<O::Output>, Return = O::Return>,
+        O: TryOutput,
+    {
+        GenTryOutputStream::<G, O>(Some(g), (), PhantomData)
+    }
+
+    /// Wrap a movable coroutine that returns `O::Return` in a stream that
+    /// implements `Clone`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_clone_output_coroutine<G, O>(g: G) -> impl FusedStream<Item = O::Item> + Clone
+    where
+        G: Coroutine<ResumeTy, Yield = Poll<O::Output>, Return = O::Return> + Clone,
+        O: TryOutput,
+    {
+        GenTryOutputStream::<G, O>(Some(g), (), PhantomData)
+    }
+
+    /// Wrap a coroutine that returns `O::Return` in a stream that stores its
+    /// size hint, which can be updated by `size_hint!`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_output_coroutine_with_size_hint<G, O>(
+        size_hint: SizeHint,
+        g: G,
+    ) -> impl FusedStream<Item = O::Item>
+    where
+        G: Coroutine<ResumeTy<WithSizeHint>, Yield = Poll<O::Output>, Return = O::Return>,
+        O: TryOutput,
+    {
+        GenTryOutputStream::<G, O, SizeHint>(Some(g), size_hint, PhantomData)
+    }
+
+    /// Wrap a movable coroutine that returns `O::Return` in a stream that
+    /// stores its size hint and implements `Clone`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_clone_output_coroutine_with_size_hint<G, O>(
+        size_hint: SizeHint,
+        g: G,
+    ) -> impl FusedStream<Item = O::Item> + Clone
+    where
+        G: Coroutine<ResumeTy<WithSizeHint>, Yield = Poll<O::Output>, Return = O::Return> + Clone,
+        O: TryOutput,
+    {
+        GenTryOutputStream::<G, O, SizeHint>(Some(g), size_hint, PhantomData)
+    }
+
+    // `H` is `SizeHint` if the stream stores its size hint, and `()` otherwise.
+    #[pin_project]
+    pub(crate) struct GenTryOutputStream<G, O, H = ()>(#[pin] Option<G>, H, PhantomData<fn() -> O>);
+
+    // Do not require `O: Clone`.
+    impl<G: Clone, O, H: Clone> Clone for GenTryOutputStream<G, O, H> {
+        #[inline]
+        fn clone(&self) -> Self {
+            Self(self.0.clone(), self.1.clone(), PhantomData)
+        }
+    }
+
+    impl<G, O, H> Stream for GenTryOutputStream<G, O, H>
+    where
+        G: Coroutine<ResumeTy<H::Ext>, Yield = Poll<O::Output>, Return = O::Return>,
+        O: TryOutput,
+        H: SizeHintStorage,
+    {
+        type Item = O::Item;
+
+        #[inline]
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let this = self.project();
+            match resume(this.0, cx, this.1.ext()) {
+                Some(CoroutineState::Yielded(x)) => x.map(|x| {
+                    this.1.consume();
+                    Some(O::into_item(x))
+                }),
+                Some(CoroutineState::Complete(ret)) => match ret.branch() {
+                    ControlFlow::Continue(()) => Poll::Ready(None),
+                    ControlFlow::Break(residual) => Poll::Ready(O::last_item(residual)),
+                },
+                None => Poll::Ready(None),
+            }
+        }
+
+        #[inline]
+        fn size_hint(&self) -> SizeHint {
+            if self.0.is_some() { self.1.get() } else { (0, Some(0)) }
+        }
+    }
+
+    impl<G, O, H> FusedStream for GenTryOutputStream<G, O, H>
+    where
+        G: Coroutine<ResumeTy<H::Ext>, Yield = Poll<O::Output>, Return = O::Return>,
+        O: TryOutput,
+        H: SizeHintStorage,
+    {
+        #[inline]
+        fn is_terminated(&self) -> bool {
+            self.0.is_none()
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub use self::catch_panic::{PanicPayload, catch_panic, catch_panic_clone};
+    #[cfg(feature = "std")]
+    mod catch_panic {
+        use alloc::{boxed::Box, string::String};
+        use core::{
+            any::Any,
+            fmt,
+            panic::AssertUnwindSafe,
+            pin::Pin,
+            task::{Context, Poll},
+        };
+        use std::panic;
+
+        use futures_core::stream::{FusedStream, Stream};
+        use pin_project::pin_project;
+
+        use crate::future::SizeHint;
+
+        /// The payload of a panic in the body of a
+        /// [`#[try_stream]`](crate::try_stream) function with the `catch_panic`
+        /// argument.
+        ///
+        /// The panic is yielded as an error converted from this type by
+        /// [`From`]. This implements [`Error`](std::error::Error), so
+        /// `Box<dyn Error>` and `Box<dyn Error + Send>` can be used as the
+        /// error type as is.
+        pub struct PanicPayload(Box<dyn Any + Send>);
+
+        impl PanicPayload {
+            /// Returns the panic message if the payload is a string, as with
+            /// `panic!("..")`.
+            #[inline]
+            #[must_use]
+            pub fn message(&self) -> Option<&str> {
+                self.0
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| self.0.downcast_ref::<String>().map(String::as_str))
+            }
+
+            /// Returns the payload passed to `panic!`, which can be passed to
+            /// [`resume_unwind`](std::panic::resume_unwind).
+            #[inline]
+            #[must_use]
+            pub fn into_inner(self) -> Box<dyn Any + Send> {
+                self.0
+            }
+        }
+
+        impl fmt::Debug for PanicPayload {
+            #[inline]
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("PanicPayload").field("message", &self.message()).finish()
+            }
+        }
+
+        impl fmt::Display for PanicPayload {
+            #[inline]
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self.message() {
+                    Some(message) => write!(f, "stream panicked: {message}"),
+                    None => f.write_str("stream panicked"),
+                }
+            }
+        }
+
+        impl core::error::Error for PanicPayload {}
+
+        /// Wraps a stream in a stream that yields a panic in `poll_next` as an
+        /// error, and then ends.
+        ///
+        /// This function returns a `CatchPanic` underneath, but hides it in `impl Trait`
+        /// like `from_coroutine`.
+        #[doc(hidden)]
+        #[inline]
+        pub fn catch_panic<S, T, E>(stream: S) -> impl FusedStream<Item = Result<T, E>>
+        where
+            S: FusedStream<Item = Result<T, E>>,
+            E: From<PanicPayload>,
+        {
+            CatchPanic(Some(stream))
+        }
+
+        /// Wraps a stream that implements `Clone` like `catch_panic`.
+        #[doc(hidden)]
+        #[inline]
+        pub fn catch_panic_clone<S, T, E>(
+            stream: S,
+        ) -> impl FusedStream<Item = Result<T, E>> + Clone
+        where
+            S: FusedStream<Item = Result<T, E>> + Clone,
+            E: From<PanicPayload>,
+        {
+            CatchPanic(Some(stream))
+        }
+
+        #[derive(Clone)]
+        #[pin_project]
+        pub(crate) struct CatchPanic<S>(#[pin] Option<S>);
+
+        impl<S, T, E> Stream for CatchPanic<S>
+        where
+            S: FusedStream<Item = Result<T, E>>,
+            E: From<PanicPayload>,
+        {
+            type Item = Result<T, E>;
+
+            #[inline]
+            fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+                let mut this = self.project();
+                let Some(stream) = this.0.as_mut().as_pin_mut() else {
+                    return Poll::Ready(None);
+                };
+                match panic::catch_unwind(AssertUnwindSafe(|| stream.poll_next(cx))) {
+                    Ok(poll) => poll,
+                    Err(payload) => {
+                        // The stream may be in an inconsistent state, so drop it
+                        // instead of polling it again.
+                        this.0.set(None);
+                        Poll::Ready(Some(Err(E::from(PanicPayload(payload)))))
+                    }
+                }
+            }
+
+            #[inline]
+            fn size_hint(&self) -> SizeHint {
+                self.0.as_ref().map_or((0, Some(0)), Stream::size_hint)
+            }
+        }
+
+        impl<S, T, E> FusedStream for CatchPanic<S>
+        where
+            S: FusedStream<Item = Result<T, E>>,
+            E: From<PanicPayload>,
+        {
+            #[inline]
+            fn is_terminated(&self) -> bool {
+                self.0.as_ref().is_none_or(FusedStream::is_terminated)
+            }
+        }
+    }
+}
+
+mod size {
+    use core::mem;
+
+    /// Checks that the size of the stream does not exceed `N` bytes.
+    ///
+    /// This is checked after monomorphization, so `cargo check` does not report it.
+    #[doc(hidden)]
+    #[inline]
+    pub fn assert_size<S, const N: usize>(stream: S) -> S {
+        const {
+            assert!(mem::size_of::<S>() <= N, "the size of the stream exceeds `max_size`");
+        }
+        stream
+    }
+
+    #[cfg(feature = "alloc")]
+    pub use self::auto_box::auto_box;
+    #[cfg(feature = "alloc")]
+    pub(crate) mod auto_box {
+        use alloc::boxed::Box;
+        use core::{
+            marker::PhantomData,
+            mem::{self, MaybeUninit},
+            pin::Pin,
+            ptr,
+            task::{Context, Poll},
+        };
+
+        use futures_core::stream::{FusedStream, Stream};
+
+        /// Stores the stream inline if it fits in `N` bytes, and on the heap otherwise.
+        ///
+        /// This function returns an `AutoBoxed` underneath, but hides it in `impl Trait`
+        /// like `from_coroutine`.
+        #[doc(hidden)]
+        #[inline]
+        pub fn auto_box<S, const N: usize>(stream: S) -> impl FusedStream<Item = S::Item>
+        where
+            S: FusedStream,
+        {
+            AutoBoxed::<S, N>::new(stream)
+        }
+
+        #[derive(Clone, Copy)]
+        #[repr(C, align(16))]
+        struct Inline<const N: usize>([MaybeUninit<u8>; N]);
+
+        #[repr(C)]
+        union Storage<const N: usize> {
+            inline: Inline<N>,
+            boxed: *mut (),
+        }
+
+        pub(crate) struct AutoBoxed<S, const N: usize> {
+            storage: Storage<N>,
+            // Inherit auto traits (except for `Send` and `Sync`, see below) from `S`.
+            _marker: PhantomData<S>,
+        }
+
+        // SAFETY: `AutoBoxed` owns `S`, whether it is stored inline or on the heap.
+        unsafe impl<S: Send, const N: usize> Send for AutoBoxed<S, N> {}
+        // SAFETY: `AutoBoxed` owns `S`, whether it is stored inline or on the heap.
+        unsafe impl<S: Sync, const N: usize> Sync for AutoBoxed<S, N> {}
+
+        impl<S, const N: usize> AutoBoxed<S, N> {
+            const INLINE: bool =
+                mem::size_of::<S>() <= N && mem::align_of::<S>() <= mem::align_of::<Inline<N>>();
+
+            fn new(stream: S) -> Self {
+                let storage = if Self::INLINE {
+                    let mut storage = Storage { inline: Inline([MaybeUninit::uninit(); N]) };
+                    // SAFETY: The size and alignment of `S` fit in `storage.inline`.
+                    // `S` is not pinned yet, so it can be moved along with `storage`.
+                    unsafe { ptr::addr_of_mut!(storage.inline).cast::<S>().write(stream) }
+                    storage
+                } else {
+                    Storage { boxed: Box::into_raw(Box::new(stream)).cast::<()>() }
+                };
+                Self { storage, _marker: PhantomData }
+            }
+
+            fn as_ptr(&mut self) -> *mut S {
+                if Self::INLINE {
+                    ptr::addr_of_mut!(self.storage.inline).cast::<S>()
+                } else {
+                    // SAFETY: `storage.boxed` is initialized by `new` if `INLINE` is false.
+                    unsafe { self.storage.boxed.cast::<S>() }
+                }
+            }
+
+            fn as_ref(&self) -> &S {
+                let ptr = if Self::INLINE {
+                    ptr::addr_of!(self.storage.inline).cast::<S>()
+                } else {
+                    // SAFETY: `storage.boxed` is initialized by `new` if `INLINE` is false.
+                    unsafe { self.storage.boxed.cast::<S>().cast_const() }
+                };
+                // SAFETY: `ptr` points to `S` initialized by `new`.
+                unsafe { &*ptr }
+            }
+        }
+
+        impl<S, const N: usize> Drop for AutoBoxed<S, N> {
+            fn drop(&mut self) {
+                let ptr = self.as_ptr();
+                if Self::INLINE {
+                    // SAFETY: `ptr` points to `S` initialized by `new`, and it is never
+                    // used after this.
+                    unsafe { ptr::drop_in_place(ptr) }
+                } else {
+                    // SAFETY: `ptr` was created by `Box::into_raw` in `new`, and it is
+                    // never used after this.
+                    drop(unsafe { Box::from_raw(ptr) });
+                }
+            }
+        }
+
+        impl<S: Stream, const N: usize> Stream for AutoBoxed<S, N> {
+            type Item = S::Item;
+
+            #[inline]
+            fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+                // SAFETY: `S` is never moved out of `self`: the inline `S` is pinned as
+                // `self` is pinned, and the boxed `S` is never moved.
+                unsafe { Pin::new_unchecked(&mut *self.get_unchecked_mut().as_ptr()) }.poll_next(cx)
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.as_ref().size_hint()
+            }
+        }
+
+        impl<S: FusedStream, const N: usize> FusedStream for AutoBoxed<S, N> {
+            #[inline]
+            fn is_terminated(&self) -> bool {
+                self.as_ref().is_terminated()
+            }
+        }
+    }
+}
+
+mod prefetch {
+    use core::{
+        pin::Pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use futures_core::stream::{FusedStream, Stream};
+    use pin_project::pin_project;
+
+    use crate::future::SizeHint;
+
+    /// Wraps a stream in a stream that polls it for up to `N` items ahead of
+    /// the consumer.
+    ///
+    /// This function returns a `Prefetch` underneath, but hides it in `impl Trait`
+    /// like `from_coroutine`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn prefetch<S, const N: usize>(stream: S) -> impl FusedStream<Item = S::Item>
+    where
+        S: FusedStream,
+    {
+        Prefetch::<S, N> { stream, buf: Buffer::new() }
+    }
+
+    /// Like `prefetch`, but also polls the stream until it returns
+    /// `Poll::Pending` or the buffer is full before returning it.
+    ///
+    /// There is no task yet, so the stream is polled with a no-op waker. No
+    /// wakeup is lost: if the stream returned `Poll::Pending`, the buffer is
+    /// not full, and the first `poll_next` polls the stream again with the
+    /// consumer's waker. The stream must be `Unpin` because it is moved after
+    /// being polled.
+    #[doc(hidden)]
+    #[inline]
+    pub fn prefetch_eager<S, const N: usize>(stream: S) -> impl FusedStream<Item = S::Item>
+    where
+        S: FusedStream + Unpin,
+    {
+        let mut this = Prefetch::<S, N> { stream, buf: Buffer::new() };
+        Pin::new(&mut this).project().fill(&mut Context::from_waker(Waker::noop()));
+        this
+    }
+
+    #[pin_project(project = PrefetchProj)]
+    pub(crate) struct Prefetch<S: Stream, const N: usize> {
+        #[pin]
+        stream: S,
+        buf: Buffer<S::Item, N>,
+    }
+
+    impl<S: FusedStream, const N: usize> PrefetchProj<'_, S, N> {
+        /// Polls the stream until it returns `Poll::Pending`, it is terminated,
+        /// or the buffer is full.
+        fn fill(&mut self, cx: &mut Context<'_>) {
+            while self.buf.len < N && !self.stream.is_terminated() {
+                match self.stream.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(item)) => self.buf.push(item),
+                    Poll::Ready(None) | Poll::Pending => break,
+                }
+            }
+        }
+    }
+
+    impl<S: FusedStream, const N: usize> Stream for Prefetch<S, N> {
+        type Item = S::Item;
+
+        #[inline]
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let mut this = self.project();
+            if this.buf.len == 0 {
+                if this.stream.is_terminated() {
+                    return Poll::Ready(None);
+                }
+                let item = this.stream.as_mut().poll_next(cx);
+                if let Poll::Ready(Some(_)) = item {
+                    this.fill(cx);
+                }
+                return item;
+            }
+            // Refill the buffer before returning the buffered item. The stream
+            // only runs during `poll_next`, not while the consumer processes
+            // the item. This also polls the stream with the real waker after
+            // `prefetch_eager`: popping an item always leaves room to fill.
+            let item = this.buf.pop();
+            this.fill(cx);
+            Poll::Ready(item)
+        }
+
+        #[inline]
+        fn size_hint(&self) -> SizeHint {
+            let (lower, upper) = self.stream.size_hint();
+            let len = self.buf.len;
+            (lower.saturating_add(len), upper.and_then(|upper| upper.checked_add(len)))
+        }
+    }
+
+    impl<S: FusedStream, const N: usize> FusedStream for Prefetch<S, N> {
+        #[inline]
+        fn is_terminated(&self) -> bool {
+            self.buf.len == 0 && self.stream.is_terminated()
+        }
+    }
+
+    /// A ring buffer of up to `N` items stored inline.
+    struct Buffer<T, const N: usize> {
+        items: [Option<T>; N],
+        head: usize,
+        len: usize,
+    }
+
+    impl<T, const N: usize> Buffer<T, N> {
+        fn new() -> Self {
+            Self { items: [const { None }; N], head: 0, len: 0 }
+        }
+
+        fn push(&mut self, item: T) {
+            debug_assert!(self.len < N);
+            self.items[(self.head + self.len) % N] = Some(item);
+            self.len += 1;
+        }
+
+        fn pop(&mut self) -> Option<T> {
+            if self.len == 0 {
+                return None;
+            }
+            let item = self.items[self.head].take();
+            self.head = (self.head + 1) % N;
+            self.len -= 1;
+            item
+        }
+    }
+}
+
+mod checkpoint {
+    use core::{
+        ops::{Coroutine, CoroutineState},
+        pin::Pin,
+        ptr::NonNull,
+        task::{Context, Poll},
+    };
+
+    use futures_core::{
+        ready,
+        stream::{FusedStream, Stream},
+    };
+    use pin_project::pin_project;
+
+    use crate::future::{
+        ResumeTy, SizeHint, SizeHintStorage, WithCheckpoint, WithSizeHint, resume,
+    };
+
+    /// A stream that records checkpoints with `checkpoint!`.
+    ///
+    /// This is implemented by the streams returned by
+    /// [`#[try_stream]`](crate::try_stream) functions with the `checkpoint`
+    /// argument. See [`retry`] for restarting such a stream from its last
+    /// checkpoint.
+    pub trait CheckpointStream: Stream {
+        /// The type of the checkpoint.
+        type Checkpoint;
+
+        /// Returns the last checkpoint committed with `checkpoint!`, or `None`
+        /// if the stream has not committed a checkpoint yet.
+        fn checkpoint(&self) -> Option<&Self::Checkpoint>;
+    }
+
+    impl<S: ?Sized + CheckpointStream + Unpin> CheckpointStream for &mut S {
+        type Checkpoint = S::Checkpoint;
+
+        #[inline]
+        fn checkpoint(&self) -> Option<&Self::Checkpoint> {
+            (**self).checkpoint()
+        }
+    }
+
+    impl<P> CheckpointStream for Pin<P>
+    where
+        P: core::ops::DerefMut<Target: CheckpointStream> + Unpin,
+    {
+        type Checkpoint = <P::Target as CheckpointStream>::Checkpoint;
+
+        #[inline]
+        fn checkpoint(&self) -> Option<&Self::Checkpoint> {
+            (**self).checkpoint()
+        }
+    }
+
+    /// Wrap a coroutine that commits checkpoints of type `C` in a stream.
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_coroutine<G, T, E, C>(
+        g: G,
+    ) -> impl FusedStream<Item = Result<T, E>> + CheckpointStream<Checkpoint = C>
+    where
+        G: Coroutine<ResumeTy<WithCheckpoint>, Yield = Poll<Result<T, E>>, Return = Result<(), E>>,
+    {
+        GenCheckpointStream(Some(g), (), None)
+    }
+
+    /// Wrap a movable coroutine that commits checkpoints of type `C` in a
+    /// stream that implements `Clone`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_clone_coroutine<G, T, E, C>(
+        g: G,
+    ) -> impl FusedStream<Item = Result<T, E>> + CheckpointStream<Checkpoint = C> + Clone
+    where
+        G: Coroutine<ResumeTy<WithCheckpoint>, Yield = Poll<Result<T, E>>, Return = Result<(), E>>
+            + Clone,
+        C: Clone,
+    {
+        GenCheckpointStream(Some(g), (), None)
+    }
+
+    /// Wrap a coroutine that commits checkpoints of type `C` in a stream that
+    /// stores its size hint, which can be updated by `size_hint!`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_coroutine_with_size_hint<G, T, E, C>(
+        size_hint: SizeHint,
+        g: G,
+    ) -> impl FusedStream<Item = Result<T, E>> + CheckpointStream<Checkpoint = C>
+    where
+        G: Coroutine<
+                ResumeTy<WithCheckpoint<WithSizeHint>>,
+                Yield = Poll<Result<T, E>>,
+                Return = Result<(), E>,
+            >,
+    {
+        GenCheckpointStream(Some(g), size_hint, None)
+    }
+
+    /// Wrap a movable coroutine that commits checkpoints of type `C` in a
+    /// stream that stores its size hint and implements `Clone`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn from_clone_coroutine_with_size_hint<G, T, E, C>(
+        size_hint: SizeHint,
+        g: G,
+    ) -> impl FusedStream<Item = Result<T, E>> + CheckpointStream<Checkpoint = C> + Clone
+    where
+        G: Coroutine<
+                ResumeTy<WithCheckpoint<WithSizeHint>>,
+                Yield = Poll<Result<T, E>>,
+                Return = Result<(), E>,
+            > + Clone,
+        C: Clone,
+    {
+        GenCheckpointStream(Some(g), size_hint, None)
+    }
+
+    // `GenTryStream` with a checkpoint.
+    #[derive(Clone)]
+    #[pin_project]
+    pub(crate) struct GenCheckpointStream<G, H, C>(#[pin] Option<G>, H, Option<C>);
+
+    impl<G, H, T, E, C> Stream for GenCheckpointStream<G, H, C>
+    where
+        G: Coroutine<
+                ResumeTy<WithCheckpoint<H::Ext>>,
+                Yield = Poll<Result<T, E>>,
+                Return = Result<(), E>,
+            >,
+        H: SizeHintStorage,
+    {
+        type Item = Result<T, E>;
+
+        #[inline]
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let this = self.project();
+            let ext = WithCheckpoint(this.1.ext(), NonNull::from(this.2).cast::<()>());
+            match resume(this.0, cx, ext) {
+                Some(CoroutineState::Yielded(x)) => x.map(|x| {
+                    this.1.consume();
+                    Some(x)
+                }),
+                Some(CoroutineState::Complete(Err(e))) => Poll::Ready(Some(Err(e))),
+                Some(CoroutineState::Complete(Ok(()))) | None => Poll::Ready(None),
+            }
+        }
+
+        #[inline]
+        fn size_hint(&self) -> SizeHint {
+            if self.0.is_some() { self.1.get() } else { (0, Some(0)) }
+        }
+    }
+
+    impl<G, H, T, E, C> FusedStream for GenCheckpointStream<G, H, C>
+    where
+        G: Coroutine<
+                ResumeTy<WithCheckpoint<H::Ext>>,
+                Yield = Poll<Result<T, E>>,
+                Return = Result<(), E>,
+            >,
+        H: SizeHintStorage,
+    {
+        #[inline]
+        fn is_terminated(&self) -> bool {
+            self.0.is_none()
+        }
+    }
+
+    impl<G, H, T, E, C> CheckpointStream for GenCheckpointStream<G, H, C>
+    where
+        G: Coroutine<
+                ResumeTy<WithCheckpoint<H::Ext>>,
+                Yield = Poll<Result<T, E>>,
+                Return = Result<(), E>,
+            >,
+        H: SizeHintStorage,
+    {
+        type Checkpoint = C;
+
+        #[inline]
+        fn checkpoint(&self) -> Option<&C> {
+            self.2.as_ref()
+        }
+    }
+
+    /// Creates a stream that restarts the stream returned by `f` from its last
+    /// checkpoint when it yields an error.
+    ///
+    /// `f` is called with `None` to create the first stream, and with the last
+    /// checkpoint committed by any of the previous streams to restart it.
+    ///
+    /// When the stream yields an error, `policy` is called with the error and
+    /// the number of restarts since the last `Ok` item. If it returns `true`,
+    /// the error is discarded and the stream is restarted. Otherwise, the
+    /// error is yielded and the stream continues as is, so it usually ends.
+    #[inline]
+    pub fn retry<F, S, P, T, E>(f: F, policy: P) -> impl FusedStream<Item = Result<T, E>>
+    where
+        F: FnMut(Option<S::Checkpoint>) -> S,
+        S: CheckpointStream<Item = Result<T, E>>,
+        S::Checkpoint: Clone,
+        P: FnMut(&E, usize) -> bool,
+    {
+        Retry { f, policy, stream: None, checkpoint: None, attempts: 0, done: false }
+    }
+
+    #[pin_project]
+    pub(crate) struct Retry<F, S: CheckpointStream, P> {
+        f: F,
+        policy: P,
+        #[pin]
+        stream: Option<S>,
+        checkpoint: Option<S::Checkpoint>,
+        attempts: usize,
+        done: bool,
+    }
+
+    impl<F, S, P, T, E> Stream for Retry<F, S, P>
+    where
+        F: FnMut(Option<S::Checkpoint>) -> S,
+        S: CheckpointStream<Item = Result<T, E>>,
+        S::Checkpoint: Clone,
+        P: FnMut(&E, usize) -> bool,
+    {
+        type Item = Result<T, E>;
+
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let mut this = self.project();
+            loop {
+                if *this.done {
+                    return Poll::Ready(None);
+                }
+                if this.stream.is_none() {
+                    this.stream.set(Some((this.f)(this.checkpoint.clone())));
+                }
+                let mut stream = this.stream.as_mut().as_pin_mut().unwrap();
+                let item = ready!(stream.as_mut().poll_next(cx));
+                // Keep the last checkpoint, so that restarting a stream that
+                // fails before committing a checkpoint does not start over.
+                if let Some(checkpoint) = stream.checkpoint() {
+                    *this.checkpoint = Some(checkpoint.clone());
+                }
+                match item {
+                    Some(Ok(x)) => {
+                        *this.attempts = 0;
+                        return Poll::Ready(Some(Ok(x)));
+                    }
+                    Some(Err(e)) if (this.policy)(&e, *this.attempts) => {
+                        *this.attempts += 1;
+                        this.stream.set(None);
+                    }
+                    Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                    None => {
+                        *this.done = true;
+                        this.stream.set(None);
+                    }
+                }
+            }
+        }
+    }
+
+    impl<F, S, P, T, E> FusedStream for Retry<F, S, P>
+    where
+        F: FnMut(Option<S::Checkpoint>) -> S,
+        S: CheckpointStream<Item = Result<T, E>>,
+        S::Checkpoint: Clone,
+        P: FnMut(&E, usize) -> bool,
+    {
+        #[inline]
+        fn is_terminated(&self) -> bool {
+            self.done
+        }
+    }
+}
+
+mod must_consume {
+    use core::{
+        ops::DerefMut,
+        panic::Location,
+        pin::Pin,
+        sync::atomic::{AtomicBool, Ordering},
+        task::{Context, Poll},
+    };
+
+    use futures_core::stream::{FusedStream, Stream};
+    use pin_project::{pin_project, pinned_drop};
+
+    use crate::future::SizeHint;
+
+    /// A stream that must be consumed until it ends.
+    ///
+    /// This is implemented by the streams returned by
+    /// [`#[stream]`](crate::stream) and [`#[try_stream]`](crate::try_stream)
+    /// functions with the `must_consume` argument. In debug builds, dropping
+    /// such a stream before it ends panics, unless [`cancel`](Self::cancel)
+    /// has been called on it.
+    ///
+    /// The check requires the `std` feature; without it, dropping such a
+    /// stream early does nothing.
+    pub trait MustConsume: Stream {
+        /// Allows the stream to be dropped before it ends.
+        fn cancel(&self);
+    }
+
+    impl<S: ?Sized + MustConsume + Unpin> MustConsume for &mut S {
+        #[inline]
+        fn cancel(&self) {
+            (**self).cancel();
+        }
+    }
+
+    impl<P> MustConsume for Pin<P>
+    where
+        P: DerefMut<Target: MustConsume> + Unpin,
+    {
+        #[inline]
+        fn cancel(&self) {
+            (**self).cancel();
+        }
+    }
+
+    /// Wraps a stream in a stream that panics if it is dropped before it ends.
+    ///
+    /// The location of the caller is reported as the location where the
+    /// stream was created.
+    ///
+    /// This function returns a `Checked` underneath, but hides it in `impl Trait`
+    /// like `from_coroutine`.
+    #[doc(hidden)]
+    #[inline]
+    #[track_caller]
+    pub fn must_consume<S: FusedStream>(
+        stream: S,
+        function: &'static str,
+    ) -> impl MustConsume<Item = S::Item> + FusedStream {
+        Checked::new(stream, function)
+    }
+
+    /// Wraps a stream that implements `Clone` like `must_consume`.
+    #[doc(hidden)]
+    #[inline]
+    #[track_caller]
+    pub fn must_consume_clone<S: FusedStream + Clone>(
+        stream: S,
+        function: &'static str,
+    ) -> impl MustConsume<Item = S::Item> + FusedStream + Clone {
+        Checked::new(stream, function)
+    }
+
+    /// Wraps a stream in a stream that implements `MustConsume` without
+    /// checking it. This is used in release builds.
+    #[doc(hidden)]
+    #[inline]
+    pub fn must_consume_unchecked<S: FusedStream>(
+        stream: S,
+    ) -> impl MustConsume<Item = S::Item> + FusedStream {
+        Unchecked(stream)
+    }
+
+    /// Wraps a stream that implements `Clone` like `must_consume_unchecked`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn must_consume_unchecked_clone<S: FusedStream + Clone>(
+        stream: S,
+    ) -> impl MustConsume<Item = S::Item> + FusedStream + Clone {
+        Unchecked(stream)
+    }
+
+    #[pin_project(PinnedDrop)]
+    pub(crate) struct Checked<S> {
+        #[pin]
+        stream: S,
+        function: &'static str,
+        location: &'static Location<'static>,
+        // Whether the stream has ended or has been cancelled.
+        done: AtomicBool,
+    }
+
+    impl<S> Checked<S> {
+        #[track_caller]
+        fn new(stream: S, function: &'static str) -> Self {
+            Self { stream, function, location: Location::caller(), done: AtomicBool::new(false) }
+        }
+    }
+
+    // A clone must also be consumed, even if the original stream has ended.
+    impl<S: Clone> Clone for Checked<S> {
+        fn clone(&self) -> Self {
+            Self {
+                stream: self.stream.clone(),
+                function: self.function,
+                location: self.location,
+                done: AtomicBool::new(false),
+            }
+        }
+    }
+
+    impl<S: Stream> Stream for Checked<S> {
+        type Item = S::Item;
+
+        #[inline]
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let this = self.project();
+            let res = this.stream.poll_next(cx);
+            if let Poll::Ready(None) = res {
+                this.done.store(true, Ordering::Relaxed);
+            }
+            res
+        }
+
+        #[inline]
+        fn size_hint(&self) -> SizeHint {
+            self.stream.size_hint()
+        }
+    }
+
+    impl<S: FusedStream> FusedStream for Checked<S> {
+        #[inline]
+        fn is_terminated(&self) -> bool {
+            self.stream.is_terminated()
+        }
+    }
+
+    impl<S: Stream> MustConsume for Checked<S> {
+        #[inline]
+        fn cancel(&self) {
+            self.done.store(true, Ordering::Relaxed);
+        }
+    }
+
+    #[pinned_drop]
+    impl<S> PinnedDrop for Checked<S> {
+        fn drop(self: Pin<&mut Self>) {
+            // Without std, there is no way to know whether the thread is
+            // already panicking, and panicking again would abort the process,
+            // so the check is skipped.
+            #[cfg(feature = "std")]
+            assert!(
+                std::thread::panicking() || self.done.load(Ordering::Relaxed),
+                "stream returned by `{}` at {} was dropped before it ended; \
+                 consume it until it ends, or call `MustConsume::cancel` on it",
+                self.function,
+                self.location,
+            );
+        }
+    }
+
+    #[derive(Clone)]
+    #[pin_project]
+    #[repr(transparent)]
+    pub(crate) struct Unchecked<S>(#[pin] S);
+
+    impl<S: Stream> Stream for Unchecked<S> {
+        type Item = S::Item;
+
+        #[inline]
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            self.project().0.poll_next(cx)
+        }
+
+        #[inline]
+        fn size_hint(&self) -> SizeHint {
+            self.0.size_hint()
+        }
+    }
+
+    impl<S: FusedStream> FusedStream for Unchecked<S> {
+        #[inline]
+        fn is_terminated(&self) -> bool {
+            self.0.is_terminated()
+        }
+    }
+
+    impl<S: Stream> MustConsume for Unchecked<S> {
+        #[inline]
+        fn cancel(&self) {}
+    }
+}
+
+#[cfg(feature = "alloc")]
+mod stop {
+    use alloc::sync::Arc;
+    use core::{
+        pin::Pin,
+        sync::atomic::{AtomicBool, Ordering},
+        task::{Context, Poll},
+    };
+
+    use futures_core::{
+        stream::{FusedStream, Stream},
+        // This is the same type as `futures::task::AtomicWaker`.
+        task::__internal::AtomicWaker,
+    };
+    use pin_project::pin_project;
+
+    use crate::{checkpoint::CheckpointStream, future::SizeHint};
+
+    /// A handle to request a [`#[stream]`](crate::stream) or
+    /// [`#[try_stream]`](crate::try_stream) function with the `stoppable`
+    /// argument to stop.
+    ///
+    /// This is returned alongside the stream.
+    #[derive(Debug, Clone, Default)]
+    pub struct StopHandle(Arc<Inner>);
+
+    #[derive(Debug, Default)]
+    struct Inner {
+        stop: AtomicBool,
+        // The waker of the task that polled the stream last.
+        waker: AtomicWaker,
+    }
+
+    impl StopHandle {
+        /// Requests the stream to stop.
+        ///
+        /// The next `yield_or_stop!` in the body of the stream evaluates to
+        /// [`StopSignal::Stop`], so the body can run cleanup before the stream
+        /// ends. The stream must be polled until it ends for the cleanup to
+        /// run.
+        ///
+        /// This wakes the task that polled the stream last, so a consumer
+        /// that is waiting for the next item polls the stream again.
+        #[inline]
+        pub fn request_stop(&self) {
+            self.0.stop.store(true, Ordering::Release);
+            self.0.waker.wake();
+        }
+
+        /// Returns `true` if [`request_stop`](Self::request_stop) has been
+        /// called.
+        #[inline]
+        #[must_use]
+        pub fn is_stop_requested(&self) -> bool {
+            self.0.stop.load(Ordering::Acquire)
+        }
+
+        #[doc(hidden)]
+        #[inline]
+        pub fn signal(&self) -> StopSignal {
+            if self.is_stop_requested() { StopSignal::Stop } else { StopSignal::Continue }
+        }
+    }
+
+    /// Wraps the stream of a function with the `stoppable` argument, so that
+    /// [`StopHandle::request_stop`] wakes the task that polled it last.
+    #[doc(hidden)]
+    #[inline]
+    pub fn stoppable<S: Stream>(handle: StopHandle, stream: S) -> Stoppable<S> {
+        Stoppable { stream, handle }
+    }
+
+    #[doc(hidden)]
+    #[derive(Debug, Clone)]
+    #[pin_project]
+    pub struct Stoppable<S> {
+        #[pin]
+        stream: S,
+        handle: StopHandle,
+    }
+
+    impl<S: Stream> Stream for Stoppable<S> {
+        type Item = S::Item;
+
+        #[inline]
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let this = self.project();
+            this.handle.0.waker.register(cx.waker());
+            this.stream.poll_next(cx)
+        }
+
+        #[inline]
+        fn size_hint(&self) -> SizeHint {
+            self.stream.size_hint()
+        }
+    }
+
+    impl<S: FusedStream> FusedStream for Stoppable<S> {
+        #[inline]
+        fn is_terminated(&self) -> bool {
+            self.stream.is_terminated()
+        }
+    }
+
+    impl<S: CheckpointStream> CheckpointStream for Stoppable<S> {
+        type Checkpoint = S::Checkpoint;
+
+        #[inline]
+        fn checkpoint(&self) -> Option<&Self::Checkpoint> {
+            self.stream.checkpoint()
+        }
+    }
+
+    /// The value of `yield_or_stop!` in the body of a stream.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[must_use]
+    #[non_exhaustive]
+    pub enum StopSignal {
+        /// The item was yielded, and the stream may continue.
+        Continue,
+        /// The stream was requested to stop with
+        /// [`StopHandle::request_stop`]. If the request was made before
+        /// `yield_or_stop!` was evaluated, the item was not yielded.
+        Stop,
+    }
+
+    impl StopSignal {
+        /// Returns `true` if this is [`StopSignal::Stop`].
+        #[inline]
+        #[must_use]
+        pub fn is_stop(self) -> bool {
+            self == Self::Stop
+        }
+
+        /// Returns `true` if this is [`StopSignal::Continue`].
+        #[inline]
+        #[must_use]
+        pub fn is_continue(self) -> bool {
+            self == Self::Continue
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+mod scope {
+    use alloc::{boxed::Box, vec::Vec};
+    use core::{
+        fmt,
+        pin::Pin,
+        task::{Context, Poll},
+    };
 
-#[cfg(test)]
-extern crate std;
+    use futures_core::stream::Stream;
 
-#[cfg(test)]
-mod tests;
+    use crate::future::SizeHint;
+
+    macro_rules! scope {
+        ($(#[$attr:meta])* $name:ident $(, $send:ident)?) => {
+            $(#[$attr])*
+            pub struct $name<'a, T> {
+                children: Vec<Pin<Box<dyn Stream<Item = T> $(+ $send)? + 'a>>>,
+                // The index of the child to poll first. This is moved past the
+                // child that yielded the last item, so the children are polled
+                // in round-robin order and a child that is always ready does
+                // not starve the others.
+                start: usize,
+            }
 
-#[cfg(test)]
-#[path = "gen/tests/assert_impl.rs"]
-mod assert_impl;
-#[cfg(test)]
-#[path = "gen/tests/track_size.rs"]
-mod track_size;
+            impl<'a, T> $name<'a, T> {
+                /// Spawns a child stream. Its items are yielded by the
+                /// enclosing stream.
+                ///
+                /// The child stream is boxed, so each call allocates.
+                ///
+                /// In `scope!`, async blocks passed to this method can
+                /// `yield` like the body of [`#[stream]`](crate::stream).
+                #[inline]
+                pub fn spawn<S: Stream<Item = T> $(+ $send)? + 'a>(&mut self, stream: S) {
+                    self.children.push(Box::pin(stream));
+                }
+            }
 
-#[doc(inline)]
-pub use futures_async_stream_macro::for_await;
-#[doc(inline)]
-pub use futures_async_stream_macro::stream;
-#[doc(inline)]
-pub use futures_async_stream_macro::stream_block;
-#[doc(inline)]
-pub use futures_async_stream_macro::try_stream;
-#[doc(inline)]
-pub use futures_async_stream_macro::try_stream_block;
+            impl<T> Default for $name<'_, T> {
+                #[inline]
+                fn default() -> Self {
+                    Self { children: Vec::new(), start: 0 }
+                }
+            }
 
-mod future {
+            impl<T> fmt::Debug for $name<'_, T> {
+                #[inline]
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.debug_struct(stringify!($name))
+                        .field("children", &self.children.len())
+                        .finish_non_exhaustive()
+                }
+            }
+
+            impl<T> Stream for $name<'_, T> {
+                type Item = T;
+
+                #[inline]
+                fn poll_next(
+                    mut self: Pin<&mut Self>,
+                    cx: &mut Context<'_>,
+                ) -> Poll<Option<Self::Item>> {
+                    let this = &mut *self;
+                    let mut index = this.start;
+                    for _ in 0..this.children.len() {
+                        if index >= this.children.len() {
+                            index = 0;
+                        }
+                        match this.children[index].as_mut().poll_next(cx) {
+                            Poll::Ready(Some(item)) => {
+                                // Rotate the start, so that the next poll begins
+                                // with the child after this one.
+                                this.start = index + 1;
+                                return Poll::Ready(Some(item));
+                            }
+                            // The next child is moved to `index`.
+                            Poll::Ready(None) => drop(this.children.remove(index)),
+                            Poll::Pending => index += 1,
+                        }
+                    }
+                    if this.children.is_empty() { Poll::Ready(None) } else { Poll::Pending }
+                }
+
+                #[inline]
+                fn size_hint(&self) -> SizeHint {
+                    self.children.iter().fold((0, Some(0)), |(lower, upper), child| {
+                        let (l, u) = child.size_hint();
+                        (lower.saturating_add(l), upper.zip(u).and_then(|(a, b)| a.checked_add(b)))
+                    })
+                }
+            }
+        };
+    }
+
+    scope! {
+        /// The scope of `scope!` in the body of [`#[stream]`](crate::stream).
+        ///
+        /// The items yielded by the child streams are yielded by the enclosing
+        /// stream in the order in which they are ready. The children are
+        /// polled in round-robin order, so a child that is always ready does
+        /// not starve the others. The scope completes once every child stream
+        /// ends.
+        ///
+        /// Each child stream is boxed when it is spawned, which is one
+        /// allocation per child.
+        ///
+        /// The child streams must implement `Send`. See also [`LocalScope`].
+        Scope, Send
+    }
+
+    scope! {
+        /// The scope of `scope_local!` in the body of [`#[stream]`](crate::stream).
+        ///
+        /// This is the same as [`Scope`], except that the child streams do not
+        /// have to implement `Send`.
+        LocalScope
+    }
+}
+
+#[cfg(feature = "std")]
+mod outputs {
+    use alloc::{boxed::Box, collections::VecDeque, sync::Arc, task::Wake, vec::Vec};
     use core::{
-        future::Future,
+        fmt, mem,
         ops::{Coroutine, CoroutineState},
         pin::Pin,
-        ptr::NonNull,
-        task::{Context, Poll},
+        task::{Context, Poll, Waker},
     };
+    use std::sync::{Mutex, MutexGuard, PoisonError};
 
-    use pin_project::pin_project;
+    use futures_core::stream::{FusedStream, Stream};
 
-    // Based on https://github.com/rust-lang/rust/blob/1.66.0/library/core/src/future/mod.rs.
-    // TODO: can we avoid GenFuture? https://github.com/rust-lang/rust/commit/9f36f988ad873f5d34cd9c08e4903c597ffc9532
+    use crate::future::{ResumeTy, resume};
 
-    /// This type is needed because:
-    ///
-    /// a) Coroutines cannot implement `for<'a, 'b> Coroutine<&'a mut Context<'b>>`, so we need to pass
-    ///    a raw pointer (see <https://github.com/rust-lang/rust/issues/68923>).
-    /// b) Raw pointers and `NonNull` aren't `Send` or `Sync`, so that would make every single future
-    ///    non-Send/Sync as well, and we don't want that.
-    ///
-    /// It also simplifies the lowering of `.await`.
+    /// The items yielded by the coroutine of a function with the `outputs`
+    /// argument: a tuple of `Option`s, one for each output, of which exactly
+    /// one is `Some`.
     #[doc(hidden)]
-    #[derive(Debug, Clone, Copy)]
-    pub struct ResumeTy(pub(crate) NonNull<Context<'static>>);
+    pub trait Channels: Sized {
+        /// A tuple of `VecDeque`s, one for each output.
+        type Buffers: Default;
+        /// The number of outputs.
+        const COUNT: usize;
+
+        /// Pushes the item to the buffer of its output and returns the index
+        /// of the output.
+        ///
+        /// If the output has been dropped, the item is returned instead, so
+        /// that it can be dropped outside the lock.
+        fn push(self, buffers: &mut Self::Buffers, closed: &[bool]) -> Result<usize, Self>;
+    }
 
-    // SAFETY: the caller of the `get_context` function that dereferences a
-    // pointer must guarantee that no data races will occur.
-    // Note: Since https://github.com/rust-lang/rust/pull/95985, `Context` is
-    // `!Send` and `!Sync`.
-    unsafe impl Send for ResumeTy {}
-    // SAFETY: see `Send` impl
-    unsafe impl Sync for ResumeTy {}
+    /// The `N`th output of `Channels`.
+    #[doc(hidden)]
+    pub trait Channel<const N: usize>: Channels {
+        type Item;
 
-    // Needed to work around old nightly bug (fixed in nightly-2024-05-24 by https://github.com/rust-lang/rust/pull/125392)
-    impl core::panic::UnwindSafe for ResumeTy {}
-    impl core::panic::RefUnwindSafe for ResumeTy {}
+        fn pop(buffers: &mut Self::Buffers) -> Option<Self::Item>;
 
-    /// Wrap a coroutine in a future.
-    ///
-    /// This function returns a `GenFuture` underneath, but hides it in `impl Trait` to give
-    /// better error messages (`impl Future` rather than `GenFuture<[closure.....]>`).
+        fn is_empty(buffers: &Self::Buffers) -> bool;
+
+        fn take(buffers: &mut Self::Buffers) -> VecDeque<Self::Item>;
+    }
+
+    macro_rules! channels {
+        ($($ty:ident $index:tt),+) => {
+            impl<$($ty),+> Channels for ($(Option<$ty>,)+) {
+                type Buffers = ($(VecDeque<$ty>,)+);
+                const COUNT: usize = [$($index),+].len();
+
+                #[inline]
+                fn push(self, buffers: &mut Self::Buffers, closed: &[bool]) -> Result<usize, Self> {
+                    $(
+                        if closed[$index] && self.$index.is_some() {
+                            return Err(self);
+                        }
+                        if let Some(item) = self.$index {
+                            buffers.$index.push_back(item);
+                            return Ok($index);
+                        }
+                    )+
+                    unreachable!()
+                }
+            }
+
+            channels!(@channel [$($ty),+] $($ty $index),+);
+        };
+        (@channel [$($all:ident),+]) => {};
+        (@channel [$($all:ident),+] $ty:ident $index:tt $(, $rest:ident $rest_index:tt)*) => {
+            impl<$($all),+> Channel<$index> for ($(Option<$all>,)+) {
+                type Item = $ty;
+
+                #[inline]
+                fn pop(buffers: &mut Self::Buffers) -> Option<Self::Item> {
+                    buffers.$index.pop_front()
+                }
+
+                #[inline]
+                fn is_empty(buffers: &Self::Buffers) -> bool {
+                    buffers.$index.is_empty()
+                }
+
+                #[inline]
+                fn take(buffers: &mut Self::Buffers) -> VecDeque<Self::Item> {
+                    mem::take(&mut buffers.$index)
+                }
+            }
+
+            channels!(@channel [$($all),+] $($rest $rest_index),*);
+        };
+    }
+
+    channels!(A 0);
+    channels!(A 0, B 1);
+    channels!(A 0, B 1, C 2);
+    channels!(A 0, B 1, C 2, D 3);
+    channels!(A 0, B 1, C 2, D 3, E 4);
+    channels!(A 0, B 1, C 2, D 3, E 4, F 5);
+    channels!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+    channels!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+    /// Wrap a coroutine in `Outputs`, from which the output streams are
+    /// created.
     #[doc(hidden)]
     #[inline]
-    pub fn from_coroutine<G>(g: G) -> impl Future<Output = G::Return>
+    pub fn from_coroutine<G, I>(g: G) -> Outputs<G, I>
     where
-        G: Coroutine<ResumeTy, Yield = ()>,
+        G: Coroutine<ResumeTy, Yield = Poll<I>, Return = ()>,
+        I: Channels,
     {
-        GenFuture(g)
+        Outputs(Arc::new(Shared {
+            state: Mutex::new(State {
+                coroutine: Some(Box::pin(Some(g))),
+                contended: false,
+                buffers: I::Buffers::default(),
+                closed: (0..I::COUNT).map(|_| false).collect(),
+            }),
+            wakers: Arc::new(Wakers(Mutex::new((0..I::COUNT).map(|_| None).collect()))),
+        }))
     }
 
-    #[pin_project]
-    pub(crate) struct GenFuture<G>(#[pin] G);
+    #[doc(hidden)]
+    pub struct Outputs<G, I: Channels>(Arc<Shared<G, I>>);
 
-    impl<G> Future for GenFuture<G>
+    impl<G, I> Outputs<G, I>
     where
-        G: Coroutine<ResumeTy, Yield = ()>,
+        G: Coroutine<ResumeTy, Yield = Poll<I>, Return = ()>,
+        I: Channels,
     {
-        type Output = G::Return;
+        /// Returns the stream of the `N`th output.
+        #[doc(hidden)]
+        #[inline]
+        #[must_use]
+        pub fn output<const N: usize>(&self) -> impl FusedStream<Item = I::Item>
+        where
+            I: Channel<N>,
+        {
+            Output::<G, I, N>(Arc::clone(&self.0))
+        }
+    }
 
+    impl<G, I: Channels> fmt::Debug for Outputs<G, I> {
         #[inline]
-        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-            let this = self.project();
-            // Resume the coroutine, turning the `&mut Context` into a `NonNull` raw pointer. The
-            // `.await` lowering will safely cast that back to a `&mut Context`.
-            match this.0.resume(ResumeTy(NonNull::from(cx).cast::<Context<'static>>())) {
-                CoroutineState::Yielded(()) => Poll::Pending,
-                CoroutineState::Complete(x) => Poll::Ready(x),
-            }
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Outputs").finish_non_exhaustive()
         }
     }
 
-    #[doc(hidden)]
-    #[inline]
-    #[must_use]
-    pub unsafe fn get_context<'a, 'b>(cx: ResumeTy) -> &'a mut Context<'b> {
-        // SAFETY: the caller must guarantee that `cx.0` is a valid pointer
-        // that fulfills all the requirements for a mutable reference.
-        unsafe { &mut *cx.0.as_ptr().cast::<Context<'b>>() }
+    struct Shared<G, I: Channels> {
+        state: Mutex<State<G, I>>,
+        wakers: Arc<Wakers>,
     }
-}
 
-mod stream {
-    use core::{
-        future::Future,
-        ops::{Coroutine, CoroutineState},
-        pin::Pin,
-        ptr::NonNull,
-        task::{Context, Poll},
-    };
+    struct State<G, I: Channels> {
+        // `None` while an output is resuming the coroutine outside the lock.
+        coroutine: Option<Pin<Box<Option<G>>>>,
+        // Whether another output was polled while the coroutine was resumed.
+        contended: bool,
+        buffers: I::Buffers,
+        // Whether each output has been dropped.
+        closed: Vec<bool>,
+    }
 
-    use futures_core::stream::Stream;
-    use pin_project::pin_project;
+    impl<G, I: Channels> Shared<G, I> {
+        // No user code runs while the lock is held: the coroutine is resumed,
+        // and wakers and discarded items are dropped, after it is released.
+        // So the state is consistent even if the lock is poisoned.
+        fn lock(&self) -> MutexGuard<'_, State<G, I>> {
+            self.state.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
 
-    use crate::future::ResumeTy;
+    // Puts the coroutine back into the state if resuming it panics. `resume`
+    // has dropped it then, so the other outputs end after their buffered
+    // items.
+    struct Resuming<'a, G, I: Channels> {
+        shared: &'a Shared<G, I>,
+        coroutine: Option<Pin<Box<Option<G>>>>,
+    }
 
-    /// Wrap a coroutine in a stream.
+    impl<G, I: Channels> Drop for Resuming<'_, G, I> {
+        fn drop(&mut self) {
+            if let Some(coroutine) = self.coroutine.take() {
+                self.shared.lock().coroutine = Some(coroutine);
+                Wake::wake_by_ref(&self.shared.wakers);
+            }
+        }
+    }
+
+    /// The wakers of the outputs waiting for the coroutine.
     ///
-    /// This function returns a `GenStream` underneath, but hides it in `impl Trait` to give
-    /// better error messages (`impl Stream` rather than `GenStream<[closure.....]>`).
-    #[doc(hidden)]
-    #[inline]
-    pub fn from_coroutine<G, T>(g: G) -> impl Stream<Item = T>
-    where
-        G: Coroutine<ResumeTy, Yield = Poll<T>, Return = ()>,
-    {
-        GenStream(g)
+    /// The coroutine is resumed with a waker that wakes all of them, so that
+    /// an output is woken even if the coroutine was last resumed by another
+    /// output.
+    struct Wakers(Mutex<Vec<Option<Waker>>>);
+
+    impl Wakers {
+        // Wakers are cloned, woken, and dropped after the lock is released,
+        // so the list is consistent even if the lock is poisoned.
+        fn lock(&self) -> MutexGuard<'_, Vec<Option<Waker>>> {
+            self.0.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        fn register(&self, index: usize, waker: &Waker) {
+            if self.lock()[index].as_ref().is_some_and(|w| w.will_wake(waker)) {
+                return;
+            }
+            let waker = waker.clone();
+            let old = self.lock()[index].replace(waker);
+            drop(old);
+        }
+
+        fn wake_one(&self, index: usize) {
+            let waker = self.lock()[index].take();
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
     }
 
-    #[pin_project]
-    pub(crate) struct GenStream<G>(#[pin] G);
+    impl Wake for Wakers {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            let wakers: Vec<_> = self.lock().iter_mut().filter_map(Option::take).collect();
+            for waker in wakers {
+                waker.wake();
+            }
+        }
+    }
 
-    impl<G, T> Stream for GenStream<G>
+    struct Output<G, I: Channel<N>, const N: usize>(Arc<Shared<G, I>>);
+
+    impl<G, I, const N: usize> Stream for Output<G, I, N>
     where
-        G: Coroutine<ResumeTy, Yield = Poll<T>, Return = ()>,
+        G: Coroutine<ResumeTy, Yield = Poll<I>, Return = ()>,
+        I: Channel<N>,
     {
-        type Item = T;
+        type Item = I::Item;
 
-        #[inline]
         fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-            let this = self.project();
-            match this.0.resume(ResumeTy(NonNull::from(cx).cast::<Context<'static>>())) {
-                CoroutineState::Yielded(x) => x.map(Some),
-                CoroutineState::Complete(()) => Poll::Ready(None),
+            let shared = &*self.0;
+            loop {
+                // The waker is registered before the state is checked, so that
+                // the output resuming the coroutine wakes this output.
+                shared.wakers.register(N, cx.waker());
+                let mut state = shared.lock();
+                if let Some(item) = I::pop(&mut state.buffers) {
+                    return Poll::Ready(Some(item));
+                }
+                let Some(coroutine) = state.coroutine.take() else {
+                    // Another output is resuming the coroutine.
+                    state.contended = true;
+                    return Poll::Pending;
+                };
+                if coroutine.is_none() {
+                    state.coroutine = Some(coroutine);
+                    return Poll::Ready(None);
+                }
+                drop(state);
+
+                // The coroutine is resumed without holding the lock, so that
+                // the other outputs can be polled and dropped meanwhile.
+                let mut resuming = Resuming { shared, coroutine: Some(coroutine) };
+                let waker = Waker::from(Arc::clone(&shared.wakers));
+                let res = resume(
+                    resuming.coroutine.as_mut().unwrap().as_mut(),
+                    &mut Context::from_waker(&waker),
+                    (),
+                );
+                let mut guard = shared.lock();
+                let state = &mut *guard;
+                state.coroutine = resuming.coroutine.take();
+                let mut wake_all = mem::take(&mut state.contended);
+                let (mut woken, mut discarded) = (None, None);
+                let pending = match res {
+                    Some(CoroutineState::Yielded(Poll::Ready(item))) => {
+                        match item.push(&mut state.buffers, &state.closed) {
+                            Ok(index) => woken = Some(index).filter(|&index| index != N),
+                            Err(item) => discarded = Some(item),
+                        }
+                        false
+                    }
+                    Some(CoroutineState::Yielded(Poll::Pending)) => true,
+                    // The other outputs end after their buffered items.
+                    Some(CoroutineState::Complete(())) | None => {
+                        wake_all = true;
+                        false
+                    }
+                };
+                drop(guard);
+                drop(discarded);
+                if wake_all {
+                    Wake::wake_by_ref(&shared.wakers);
+                } else if let Some(index) = woken {
+                    shared.wakers.wake_one(index);
+                }
+                if pending {
+                    return Poll::Pending;
+                }
             }
         }
     }
 
-    // This is equivalent to the `futures::stream::StreamExt::next` method.
-    // But we want to make this crate dependency as small as possible, so we define our `next` function.
-    #[doc(hidden)]
-    #[inline]
-    pub fn next<S>(stream: &mut S) -> impl Future<Output = Option<S::Item>> + '_
+    impl<G, I, const N: usize> FusedStream for Output<G, I, N>
     where
-        S: Stream + Unpin,
+        G: Coroutine<ResumeTy, Yield = Poll<I>, Return = ()>,
+        I: Channel<N>,
     {
-        Next(stream)
+        fn is_terminated(&self) -> bool {
+            let state = self.0.lock();
+            state.coroutine.as_ref().is_some_and(|coroutine| coroutine.is_none())
+                && I::is_empty(&state.buffers)
+        }
     }
 
-    pub(crate) struct Next<'a, S>(&'a mut S);
+    impl<G, I: Channel<N>, const N: usize> Drop for Output<G, I, N> {
+        fn drop(&mut self) {
+            let mut state = self.0.lock();
+            // The items of this output are no longer buffered.
+            state.closed[N] = true;
+            let buffer = I::take(&mut state.buffers);
+            drop(state);
+            drop(buffer);
+        }
+    }
+}
 
-    impl<S> Future for Next<'_, S>
-    where
-        S: Stream + Unpin,
-    {
-        type Output = Option<S::Item>;
+mod context {
+    #[cfg(feature = "alloc")]
+    use alloc::boxed::Box;
+    use core::fmt;
+
+    /// The location of `?` that produced an error in a
+    /// [`#[try_stream]`](crate::try_stream) function with the `context`
+    /// argument.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Location {
+        function: &'static str,
+        file: &'static str,
+        line: u32,
+        column: u32,
+    }
 
+    impl Location {
+        #[doc(hidden)]
         #[inline]
-        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-            Pin::new(&mut *self.0).poll_next(cx)
+        #[must_use]
+        pub const fn new(
+            function: &'static str,
+            file: &'static str,
+            line: u32,
+            column: u32,
+        ) -> Self {
+            Self { function, file, line, column }
         }
-    }
-}
 
-mod try_stream {
-    use core::{
-        ops::{Coroutine, CoroutineState},
-        pin::Pin,
-        ptr::NonNull,
-        task::{Context, Poll},
-    };
+        /// Returns the name of the stream function.
+        #[inline]
+        #[must_use]
+        pub const fn function(&self) -> &'static str {
+            self.function
+        }
 
-    use futures_core::stream::{FusedStream, Stream};
-    use pin_project::pin_project;
+        /// Returns the name of the source file.
+        #[inline]
+        #[must_use]
+        pub const fn file(&self) -> &'static str {
+            self.file
+        }
 
-    use crate::future::ResumeTy;
+        /// Returns the line number of `?`.
+        #[inline]
+        #[must_use]
+        pub const fn line(&self) -> u32 {
+            self.line
+        }
 
-    /// Wrap a coroutine in a stream.
+        /// Returns the column number of `?`.
+        #[inline]
+        #[must_use]
+        pub const fn column(&self) -> u32 {
+            self.column
+        }
+    }
+
+    impl fmt::Display for Location {
+        #[inline]
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "in `{}` at {}:{}:{}", self.function, self.file, self.line, self.column)
+        }
+    }
+
+    /// An error type that can record the [`Location`] of `?` that produced it.
     ///
-    /// This function returns a `GenStream` underneath, but hides it in `impl Trait` to give
-    /// better error messages (`impl Stream` rather than `GenStream<[closure.....]>`).
+    /// In [`#[try_stream]`](crate::try_stream) functions with the `context`
+    /// argument, `?` converts the error into the `error` type and then calls
+    /// [`with_location`](Self::with_location) on it.
+    ///
+    /// This is implemented for `Box<dyn Error>` (with and without `Send` and
+    /// `Sync`), which wraps the error in an error that displays the location.
+    pub trait WithLocation {
+        /// Attaches `location` to the error.
+        #[must_use]
+        fn with_location(self, location: Location) -> Self;
+    }
+
+    /// Converts the error of `result` into `F`, and attaches `location` to it.
     #[doc(hidden)]
     #[inline]
-    pub fn from_coroutine<G, T, E>(g: G) -> impl FusedStream<Item = Result<T, E>>
+    pub fn with_location<T, E, F>(result: Result<T, E>, location: Location) -> Result<T, F>
     where
-        G: Coroutine<ResumeTy, Yield = Poll<T>, Return = Result<(), E>>,
+        F: From<E> + WithLocation,
     {
-        GenTryStream(Some(g))
+        result.map_err(|e| F::from(e).with_location(location))
     }
 
-    #[pin_project]
-    pub(crate) struct GenTryStream<G>(#[pin] Option<G>);
+    // Wraps the error in `Box<dyn Error>`, and displays it with the location.
+    #[cfg(feature = "alloc")]
+    struct Located<E: ?Sized> {
+        location: Location,
+        error: Box<E>,
+    }
 
-    impl<G, T, E> Stream for GenTryStream<G>
-    where
-        G: Coroutine<ResumeTy, Yield = Poll<T>, Return = Result<(), E>>,
-    {
-        type Item = Result<T, E>;
+    #[cfg(feature = "alloc")]
+    macro_rules! impl_with_location {
+        ($($ty:ty),*) => {$(
+            impl fmt::Debug for Located<$ty> {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.debug_struct("Located")
+                        .field("location", &self.location)
+                        .field("error", &self.error)
+                        .finish()
+                }
+            }
 
-        #[inline]
-        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-            let mut this = self.project();
-            if let Some(g) = this.0.as_mut().as_pin_mut() {
-                let res = match g.resume(ResumeTy(NonNull::from(cx).cast::<Context<'static>>())) {
-                    CoroutineState::Yielded(x) => x.map(|x| Some(Ok(x))),
-                    CoroutineState::Complete(Err(e)) => Poll::Ready(Some(Err(e))),
-                    CoroutineState::Complete(Ok(())) => Poll::Ready(None),
-                };
-                if let Poll::Ready(Some(Err(_)) | None) = &res {
-                    this.0.set(None);
+            impl fmt::Display for Located<$ty> {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    write!(f, "{} ({})", self.error, self.location)
                 }
-                res
-            } else {
-                Poll::Ready(None)
             }
-        }
-    }
 
-    impl<G, T, E> FusedStream for GenTryStream<G>
-    where
-        G: Coroutine<ResumeTy, Yield = Poll<T>, Return = Result<(), E>>,
-    {
-        #[inline]
-        fn is_terminated(&self) -> bool {
-            self.0.is_none()
-        }
+            impl core::error::Error for Located<$ty> {
+                fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+                    Some(&*self.error)
+                }
+            }
+
+            impl WithLocation for Box<$ty> {
+                #[inline]
+                fn with_location(self, location: Location) -> Self {
+                    Box::new(Located { location, error: self })
+                }
+            }
+        )*};
     }
+
+    #[cfg(feature = "alloc")]
+    impl_with_location!(
+        dyn core::error::Error,
+        dyn core::error::Error + Send,
+        dyn core::error::Error + Send + Sync
+    );
 }
 
 // Not public API.
 #[doc(hidden)]
 pub mod __private {
+    #[cfg(feature = "alloc")]
+    #[doc(hidden)]
+    pub use alloc::boxed::Box;
     #[doc(hidden)]
     pub use core::{
-        marker::Send,
+        clone::Clone,
+        column,
+        convert::From,
+        file, line,
+        marker::{Send, Sized},
         option::Option::{None, Some},
         pin::Pin,
-        result::Result::{self, Ok},
+        result::Result::{self, Err, Ok},
         task::Poll,
     };
 
@@ -548,7 +3575,10 @@ pub mod __private {
         pub use core::future::Future;
 
         #[doc(hidden)]
-        pub use crate::future::{ResumeTy, from_coroutine, get_context};
+        pub use crate::future::{
+            HasSizeHint, ResumeTy, WithCheckpoint, WithSizeHint, from_coroutine, get_context,
+            poll_fn, set_checkpoint, set_size_hint, with_context,
+        };
     }
 
     #[doc(hidden)]
@@ -557,12 +3587,60 @@ pub mod __private {
         pub use futures_core::stream::Stream;
 
         #[doc(hidden)]
-        pub use crate::stream::{from_coroutine, next};
+        pub use crate::stream::{
+            Budget, from_clone_coroutine, from_clone_coroutine_with_size_hint, from_coroutine,
+            from_coroutine_with_size_hint, next,
+        };
     }
 
     #[doc(hidden)]
     pub mod try_stream {
         #[doc(hidden)]
-        pub use crate::try_stream::from_coroutine;
+        pub use crate::try_stream::{
+            TryOutput, complete, from_clone_coroutine, from_clone_coroutine_with_size_hint,
+            from_clone_output_coroutine, from_clone_output_coroutine_with_size_hint,
+            from_coroutine, from_coroutine_with_size_hint, from_output_coroutine,
+            from_output_coroutine_with_size_hint,
+        };
+        #[cfg(feature = "std")]
+        #[doc(hidden)]
+        pub use crate::try_stream::{catch_panic, catch_panic_clone};
+    }
+
+    #[doc(hidden)]
+    pub mod checkpoint {
+        #[doc(hidden)]
+        pub use crate::checkpoint::{
+            CheckpointStream, from_clone_coroutine, from_clone_coroutine_with_size_hint,
+            from_coroutine, from_coroutine_with_size_hint,
+        };
+    }
+
+    #[doc(hidden)]
+    pub use crate::context::{Location, with_location};
+    #[doc(hidden)]
+    pub use crate::must_consume::{
+        MustConsume, must_consume, must_consume_clone, must_consume_unchecked,
+        must_consume_unchecked_clone,
+    };
+    #[doc(hidden)]
+    pub use crate::prefetch::{prefetch, prefetch_eager};
+    #[doc(hidden)]
+    pub use crate::size::assert_size;
+    #[cfg(feature = "alloc")]
+    #[doc(hidden)]
+    pub use crate::size::auto_box;
+    #[cfg(feature = "std")]
+    #[doc(hidden)]
+    pub mod outputs {
+        #[doc(hidden)]
+        pub use crate::outputs::{Channel, Channels, Outputs, from_coroutine};
     }
+
+    #[cfg(feature = "alloc")]
+    #[doc(hidden)]
+    pub use crate::{
+        scope::{LocalScope, Scope},
+        stop::{StopHandle, StopSignal, Stoppable, stoppable},
+    };
 }
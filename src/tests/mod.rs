@@ -4,6 +4,8 @@
 
 use core::marker::PhantomPinned;
 
+use futures_core::stream::FusedStream;
+
 use static_assertions::{assert_impl_all as assert_impl, assert_not_impl_all as assert_not_impl};
 
 use crate::*;
@@ -35,3 +37,10 @@ assert_impl!(try_stream::GenTryStream<()>: Sync);
 assert_not_impl!(try_stream::GenTryStream<*const ()>: Sync);
 assert_impl!(try_stream::GenTryStream<()>: Unpin);
 assert_not_impl!(try_stream::GenTryStream<PhantomPinned>: Unpin);
+
+type DynFusedStream = &'static mut (dyn FusedStream<Item = ()> + Unpin);
+
+#[cfg(feature = "alloc")]
+assert_impl!(size::auto_box::AutoBoxed<DynFusedStream, 8>: FusedStream);
+assert_impl!(must_consume::Checked<DynFusedStream>: FusedStream);
+assert_impl!(must_consume::Unchecked<DynFusedStream>: FusedStream);
@@ -0,0 +1,174 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines, proc_macro_hygiene, stmt_expr_attributes)]
+
+use std::{
+    pin::pin,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::Wake,
+};
+
+use futures::{
+    future,
+    stream::{self, Stream},
+    task::{Context, Poll, Waker},
+};
+use futures_async_stream::{stream, try_stream};
+
+#[derive(Default)]
+struct CountWaker(AtomicUsize);
+
+impl Wake for CountWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// Polls the stream to completion, and returns the results of `poll_next` and
+// the number of wakes.
+fn poll_all<S: Stream>(s: S) -> (Vec<Poll<Option<S::Item>>>, usize) {
+    let count = Arc::new(CountWaker::default());
+    let waker = Waker::from(count.clone());
+    let cx = &mut Context::from_waker(&waker);
+    let mut s = pin!(s);
+    let mut results = vec![];
+    loop {
+        let res = s.as_mut().poll_next(cx);
+        let done = matches!(res, Poll::Ready(None));
+        results.push(res);
+        if done {
+            return (results, count.0.load(Ordering::Relaxed));
+        }
+    }
+}
+
+#[stream(item = i32)]
+pub async fn yield_now() {
+    yield 1;
+    yield_now!();
+    yield 2;
+}
+
+#[stream(item = usize, budget = 2)]
+pub async fn sum(n: usize) {
+    let mut sum = 0;
+    #[for_await]
+    for i in stream::iter(0..n) {
+        sum += future::ready(i).await;
+    }
+    yield sum;
+}
+
+#[stream(item = usize, budget = 3)]
+pub async fn chunks(n: usize) {
+    #[for_await]
+    for i in stream::iter(0..n) {
+        // Yielding an item resets the budget.
+        if i % 2 == 1 {
+            yield i;
+        }
+    }
+}
+
+#[try_stream(ok = i32, error = i32, budget = 1)]
+pub async fn try_ready() {
+    yield future::ready(Ok::<_, i32>(1)).await?;
+    future::ready(Err(2)).await?;
+}
+
+#[try_stream(ok = i32, error = i32, budget = 2, continue_on_error)]
+pub async fn try_continue() {
+    for i in 1..=3 {
+        // Yielding an error also resets the budget.
+        yield future::ready(if i == 2 { Err(i) } else { Ok(i) }).await?;
+    }
+}
+
+#[test]
+fn test_yield_now() {
+    let (results, wakes) = poll_all(yield_now());
+    assert_eq!(
+        results,
+        [Poll::Ready(Some(1)), Poll::Pending, Poll::Ready(Some(2)), Poll::Ready(None)]
+    );
+    assert_eq!(wakes, 1);
+
+    let s = #[stream]
+    async {
+        yield_now!();
+        yield ();
+    };
+    let (results, wakes) = poll_all(s);
+    assert_eq!(results, [Poll::Pending, Poll::Ready(Some(())), Poll::Ready(None)]);
+    assert_eq!(wakes, 1);
+}
+
+#[test]
+fn test_budget() {
+    // 3 items from `stream::iter` and 3 `.await`s: 6 ready polls.
+    let (results, wakes) = poll_all(sum(3));
+    assert_eq!(
+        results,
+        [Poll::Pending, Poll::Pending, Poll::Pending, Poll::Ready(Some(3)), Poll::Ready(None)]
+    );
+    assert_eq!(wakes, 3);
+
+    let (results, wakes) = poll_all(chunks(8));
+    assert_eq!(
+        results,
+        [
+            Poll::Ready(Some(1)),
+            Poll::Ready(Some(3)),
+            Poll::Ready(Some(5)),
+            Poll::Ready(Some(7)),
+            Poll::Ready(None)
+        ]
+    );
+    assert_eq!(wakes, 0);
+
+    let (results, wakes) = poll_all(try_ready());
+    assert_eq!(
+        results,
+        [
+            Poll::Pending,
+            Poll::Ready(Some(Ok(1))),
+            Poll::Pending,
+            Poll::Ready(Some(Err(2))),
+            Poll::Ready(None)
+        ]
+    );
+    assert_eq!(wakes, 2);
+
+    let (results, wakes) = poll_all(try_continue());
+    assert_eq!(
+        results,
+        [
+            Poll::Ready(Some(Ok(1))),
+            Poll::Ready(Some(Err(2))),
+            Poll::Ready(Some(Ok(3))),
+            Poll::Ready(None)
+        ]
+    );
+    assert_eq!(wakes, 0);
+}
+
+const NO_BUDGET: usize = 0;
+
+#[stream(item = usize, budget = NO_BUDGET)]
+async fn no_budget() {
+    yield 0;
+}
+
+#[test]
+#[should_panic = "`budget` must be greater than zero"]
+fn zero_budget() {
+    poll_all(no_budget());
+}
@@ -0,0 +1,87 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate, clippy::missing_panics_doc)]
+#![feature(coroutines, coroutine_clone)]
+#![cfg(feature = "std")]
+
+use std::{error::Error, pin::pin};
+
+use futures::{
+    future::{self, Future},
+    stream::StreamExt as _,
+    task::{Context, Poll, noop_waker},
+};
+use futures_async_stream::{PanicPayload, try_stream};
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    Invalid(String),
+    Panicked(Option<String>),
+}
+
+impl From<PanicPayload> for ParseError {
+    fn from(payload: PanicPayload) -> Self {
+        Self::Panicked(payload.message().map(str::to_owned))
+    }
+}
+
+#[try_stream(ok = i32, error = ParseError, catch_panic, clone)]
+pub async fn parse(items: Vec<&'static str>) {
+    for x in items {
+        if x.is_empty() {
+            return Err(ParseError::Invalid(x.to_owned()));
+        }
+        yield future::ready(x.parse().unwrap()).await;
+    }
+}
+
+#[try_stream(ok = i32, error = Box<dyn Error>, catch_panic)]
+pub async fn boxed(n: i32) {
+    yield n;
+    std::panic::panic_any(n);
+}
+
+#[test]
+fn catch_panic() {
+    let s = parse(vec!["1", "a", "2"]);
+    let mut s2 = pin!(s.clone());
+    assert_eq!(run(s2.next()), Some(Ok(1)));
+    match run(s2.next()) {
+        Some(Err(ParseError::Panicked(Some(message)))) => {
+            assert!(message.contains("ParseIntError"), "{message}");
+        }
+        res => panic!("{res:?}"),
+    }
+    // The stream ends after the panic.
+    assert_eq!(run(s2.next()), None);
+    assert_eq!(run(s2.next()), None);
+    // The clone is not affected by the panic.
+    assert_eq!(run(s.take(1).collect::<Vec<_>>()), [Ok(1)]);
+
+    assert_eq!(
+        run(parse(vec!["1", ""]).collect::<Vec<_>>()),
+        [Ok(1), Err(ParseError::Invalid(String::new()))]
+    );
+}
+
+#[test]
+fn boxed_error() {
+    let results = run(boxed(1).collect::<Vec<_>>());
+    assert_eq!(results.len(), 2);
+    assert_eq!(*results[0].as_ref().unwrap(), 1);
+    let err = results[1].as_ref().unwrap_err();
+    assert_eq!(err.to_string(), "stream panicked");
+    let payload = err.downcast_ref::<PanicPayload>().unwrap();
+    assert_eq!(payload.message(), None);
+}
@@ -0,0 +1,87 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines)]
+
+use std::{cell::Cell, pin::pin};
+
+use futures::{
+    future::{self, Future},
+    stream::StreamExt as _,
+    task::{Context, Poll, noop_waker},
+};
+use futures_async_stream::{CheckpointStream as _, retry, try_stream};
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+// Fails once at each page in `fail`.
+#[try_stream(ok = usize, error = usize, checkpoint = usize)]
+pub async fn pages(fail: &[Cell<bool>], start: Option<usize>) {
+    for (page, fail) in fail.iter().enumerate().skip(start.unwrap_or(0)) {
+        if fail.replace(false) {
+            return Err(page);
+        }
+        yield future::ready(page).await;
+        checkpoint!(page + 1);
+    }
+}
+
+#[try_stream(ok = usize, error = usize, checkpoint = usize, boxed)]
+pub async fn boxed() {
+    checkpoint!(1);
+    yield 0;
+}
+
+#[test]
+fn checkpoint() {
+    let fail = [Cell::new(false), Cell::new(true), Cell::new(false)];
+    let mut s = pin!(pages(&fail, None));
+    assert_eq!(s.checkpoint(), None);
+    assert_eq!(run(s.next()), Some(Ok(0)));
+    // The checkpoint is committed after the consumer polls the stream again.
+    assert_eq!(s.checkpoint(), None);
+    assert_eq!(run(s.next()), Some(Err(1)));
+    assert_eq!(s.checkpoint(), Some(&1));
+    assert_eq!(run(s.next()), None);
+
+    // Resume from the checkpoint.
+    assert_eq!(run(pages(&fail, Some(1)).collect::<Vec<_>>()), [Ok(1), Ok(2)]);
+
+    let mut s = boxed();
+    assert_eq!(run(s.next()), Some(Ok(0)));
+    assert_eq!(s.checkpoint(), Some(&1));
+}
+
+#[test]
+fn test_retry() {
+    let fail = [Cell::new(true), Cell::new(true), Cell::new(false), Cell::new(true)];
+    let attempts = Cell::new(vec![]);
+    let starts = Cell::new(vec![]);
+    let s = retry(
+        |start| {
+            starts.set([starts.take(), vec![start]].concat());
+            pages(&fail, start)
+        },
+        |&e: &usize, n| {
+            attempts.set([attempts.take(), vec![(e, n)]].concat());
+            true
+        },
+    );
+    assert_eq!(run(s.collect::<Vec<_>>()), [Ok(0), Ok(1), Ok(2), Ok(3)]);
+    assert_eq!(attempts.take(), [(0, 0), (1, 0), (3, 0)]);
+    assert_eq!(starts.take(), [None, None, Some(1), Some(3)]);
+
+    // The error is yielded if the policy returns `false`.
+    let fail = [Cell::new(false), Cell::new(true), Cell::new(false)];
+    let s = retry(|start| pages(&fail, start), |_: &usize, _| false);
+    assert_eq!(run(s.collect::<Vec<_>>()), [Ok(0), Err(1)]);
+}
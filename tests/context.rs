@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines)]
+
+use std::pin::pin;
+
+use futures::{
+    future::{self, Future},
+    stream::StreamExt as _,
+    task::{Context, Poll, noop_waker},
+};
+use futures_async_stream::{Location, WithLocation, try_stream};
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    message: String,
+    location: Option<(&'static str, u32)>,
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Self { message: message.to_owned(), location: None }
+    }
+}
+
+impl WithLocation for Error {
+    fn with_location(mut self, location: Location) -> Self {
+        self.location = Some((location.function(), location.line()));
+        self
+    }
+}
+
+fn check(x: i32) -> Result<i32, &'static str> {
+    if x < 0 { Err("negative") } else { Ok(x) }
+}
+
+#[try_stream(ok = i32, error = Error, context)]
+pub async fn located(items: Vec<i32>) {
+    for x in items {
+        yield check(x)?;
+    }
+    future::ready(Err("end")).await?;
+}
+
+#[try_stream(ok = i32, error = Error, context, continue_on_error)]
+pub async fn located_continue(items: Vec<i32>) {
+    for x in items {
+        yield check(x)?;
+    }
+}
+
+#[try_stream(ok = i32, error = Box<dyn std::error::Error + Send + Sync>, context)]
+pub async fn boxed(s: &'static str) {
+    yield s.parse::<i32>()?;
+}
+
+#[try_stream(ok = i32, error = Error)]
+pub async fn no_context(x: i32) {
+    yield check(x)?;
+}
+
+#[test]
+fn context() {
+    assert_eq!(
+        run(located(vec![1, -2]).collect::<Vec<_>>()),
+        [Ok(1), Err(Error { message: "negative".to_owned(), location: Some(("located", 52)) })]
+    );
+    assert_eq!(
+        run(located(vec![]).collect::<Vec<_>>()),
+        [Err(Error { message: "end".to_owned(), location: Some(("located", 54)) })]
+    );
+    assert_eq!(
+        run(located_continue(vec![-1, 2]).collect::<Vec<_>>()),
+        [
+            Err(Error { message: "negative".to_owned(), location: Some(("located_continue", 60)) }),
+            Ok(2)
+        ]
+    );
+    assert_eq!(
+        run(no_context(-1).collect::<Vec<_>>()),
+        [Err(Error { message: "negative".to_owned(), location: None })]
+    );
+}
+
+#[test]
+fn boxed_error() {
+    let mut s = pin!(boxed("a"));
+    let err = run(s.next()).unwrap().unwrap_err();
+    let source = "a".parse::<i32>().unwrap_err();
+    assert_eq!(err.to_string(), format!("{source} (in `boxed` at tests/context.rs:66:27)"));
+    assert_eq!(err.source().unwrap().to_string(), source.to_string());
+}
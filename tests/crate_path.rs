@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::unused_async)]
+#![feature(coroutines, proc_macro_hygiene, stmt_expr_attributes)]
+
+use std::pin::pin;
+
+use futures::{
+    future::Future,
+    task::{Context, Poll, noop_waker},
+};
+
+// A facade that re-exports the macros, like an internal crate does.
+pub mod facade {
+    pub use ::futures_async_stream::*;
+}
+
+use self::facade::{for_await, stream, stream_block, stream_trait, try_stream, try_stream_block};
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+#[stream(crate = crate::facade, item = i32)]
+async fn stream(x: i32) {
+    for i in 1..=x {
+        yield i;
+    }
+}
+
+#[stream(item = i32, boxed, crate = crate::facade)]
+async fn boxed() {
+    #[for_await]
+    for i in stream(2) {
+        yield async { i * 2 }.await;
+    }
+    let s = stream_block! {
+        yield 5;
+    };
+    #[for_await]
+    for i in s {
+        yield i;
+    }
+}
+
+#[try_stream(crate = crate::facade, ok = i32, error = i32)]
+async fn try_stream() {
+    let s = {
+        #[try_stream(crate = crate::facade)]
+        async move {
+            #[for_await]
+            for i in stream(2) {
+                yield i;
+            }
+            return Err(3);
+        }
+    };
+    #[for_await]
+    for i in s {
+        yield i?;
+    }
+}
+
+#[stream_trait(crate = crate::facade)]
+pub trait Trait {
+    #[stream(item = i32)]
+    async fn stream(&self);
+}
+
+#[stream_trait(crate = crate::facade)]
+impl Trait for i32 {
+    #[stream(item = i32)]
+    async fn stream(&self) {
+        yield *self;
+    }
+}
+
+#[test]
+fn test() {
+    run(async {
+        let mut v = vec![];
+        #[for_await(crate = crate::facade)]
+        for x in boxed() {
+            v.push(x);
+        }
+        assert_eq!(v, [2, 4, 5]);
+
+        let mut v = vec![];
+        #[for_await(crate = crate::facade)]
+        for x in try_stream() {
+            v.push(x);
+        }
+        assert_eq!(v, [Ok(1), Ok(2), Err(3)]);
+
+        let s = stream_block! {
+            crate = crate::facade;
+            yield 1;
+        };
+        let mut v = vec![];
+        #[for_await(crate = crate::facade)]
+        for x in s {
+            v.push(x);
+        }
+        assert_eq!(v, [1]);
+
+        let s = try_stream_block! {
+            crate = crate::facade;
+            yield 1;
+            return Err(2);
+        };
+        let mut v = vec![];
+        #[for_await(crate = crate::facade)]
+        for x in s {
+            v.push(x);
+        }
+        assert_eq!(v, [Ok(1), Err(2)]);
+
+        let s: &dyn DynTrait = &1;
+        let mut v = vec![];
+        #[for_await(crate = crate::facade)]
+        for x in s.stream() {
+            v.push(x);
+        }
+        assert_eq!(v, [1]);
+    });
+}
@@ -6,10 +6,18 @@
     renamed_and_removed_lints,
     elided_named_lifetimes,
     mismatched_lifetime_syntaxes,
-    clippy::needless_pass_by_value
+    clippy::must_use_candidate,
+    clippy::needless_pass_by_value,
+    clippy::no_effect_underscore_binding
 )]
 #![feature(coroutines)]
 
+use std::{
+    fmt::{Debug, Formatter},
+    pin::Pin,
+};
+
+use futures::stream::Stream;
 use futures_async_stream::stream;
 
 pub struct Ref<'a, T>(&'a T);
@@ -65,3 +73,109 @@ where
 {
     yield;
 }
+
+#[stream(item = ())]
+pub async fn impl_trait(x: impl Debug + '_) {
+    let _x = x;
+    yield;
+}
+
+#[stream(item = ())]
+pub async fn dyn_trait(x: &dyn Debug, y: &(dyn Debug + Sync), z: Box<dyn Debug + '_>) {
+    let _x = (x, y, z);
+    yield;
+}
+
+#[stream(item = ())]
+pub async fn default_object_lifetime(x: &mut Box<dyn Debug>) {
+    let _x = x;
+    yield;
+}
+
+#[stream(item = (), boxed_local)]
+pub async fn boxed_multi_ref(x: impl Debug + '_, y: &dyn Debug, z: Ref<'_, i32>) {
+    let _x = (x, y, z);
+    yield;
+}
+
+#[stream(item = ())]
+pub async fn paths(x: Formatter<'_>, y: &mut Formatter<'_>) {
+    let _x = (x, y);
+    yield;
+}
+
+#[allow(elided_lifetimes_in_paths)]
+#[stream(item = ())]
+pub async fn hidden_lifetimes(x: Formatter, y: &mut Formatter, z: Ref<i32>) {
+    let _x = (x, y, z);
+    yield;
+}
+
+#[stream(item = u8)]
+pub async fn higher_ranked(f: fn(&u8) -> &u8, g: Box<dyn Fn(&u8) -> &u8>) {
+    yield *g(f(&0));
+}
+
+pub fn higher_ranked_is_static() -> Pin<Box<dyn Stream<Item = u8>>> {
+    Box::pin(higher_ranked(|x| x, Box::new(|x| x)))
+}
+
+#[stream(item = &'a str, captures = ['a])]
+pub async fn precise_capture<'a>(x: &'a str, _y: &str) {
+    yield x;
+}
+
+#[stream(item = T, captures = ['a, T])]
+pub async fn precise_capture_generic<'a, T: Clone>(x: &'a T, _y: &str) {
+    yield x.clone();
+}
+
+#[stream(item = usize, captures = [])]
+pub async fn precise_capture_none(x: &str) {
+    let _ = x;
+    yield 0;
+}
+
+#[stream(item = &'a str, boxed, captures = ['a])]
+pub async fn precise_capture_boxed<'a>(x: &'a str, _y: &str) {
+    yield x;
+}
+
+// The returned streams must not be tied to the lifetimes that are not listed in `captures`.
+pub fn precise_capture_outlives_other_args() {
+    let x = String::from("x");
+    let _s = {
+        let y = String::from("y");
+        precise_capture(&x, &y)
+    };
+    let _s = {
+        let y = String::from("y");
+        precise_capture_generic(&x, &y)
+    };
+    let _s = {
+        let x = String::from("x");
+        precise_capture_none(&x)
+    };
+    let _s = {
+        let y = String::from("y");
+        precise_capture_boxed(&x, &y)
+    };
+}
+
+pub struct Captured<'a, T>(&'a T);
+
+impl<'a, T: Clone> Captured<'a, T> {
+    // The generic parameters of the enclosing impl can also be listed.
+    #[stream(item = T, captures = ['a, T])]
+    pub async fn precise_capture_impl(self, _y: &str) {
+        yield self.0.clone();
+    }
+}
+
+pub fn precise_capture_impl_outlives_other_args() {
+    let x = 0;
+    let _s = {
+        let y = String::from("y");
+        Captured(&x).precise_capture_impl(&y)
+    };
+}
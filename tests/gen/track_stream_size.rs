@@ -0,0 +1,37 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// This file is @generated by futures-async-stream-internal-codegen
+// (gen_track_stream_size function at tools/codegen/src/main.rs).
+// It is not intended for manual editing.
+
+#![cfg_attr(rustfmt, rustfmt::skip)]
+#![allow(clippy::std_instead_of_alloc, clippy::std_instead_of_core)]
+use std::{fmt::Write as _, path::Path, string::String};
+fn write_size<T>(out: &mut String, name: &str, _: &T) {
+    let _ = writeln!(out, "{}: {}", name, std::mem::size_of::< T > ());
+}
+/// Test the size of the streams returned by stream functions. This is not intended to
+/// keep a specific size and is intended to be used only as a help in optimization.
+/// Use `-Z print-type-sizes` to see the size of each suspension point.
+///
+/// Ignore non-64-bit targets due to usize/ptr size, ignore Miri/cargo-careful as we set
+/// -Z randomize-layout for them, and ignore old rustc as the layout of coroutines
+/// may differ between compiler versions.
+#[rustversion::attr(
+    nightly,
+    cfg_attr(any(not(target_pointer_width = "64"), miri, careful), ignore)
+)]
+#[rustversion::attr(not(nightly), ignore)]
+#[test]
+fn track_stream_size() {
+    let mut out = String::new();
+    write_size(&mut out, "for_await_in_stream_fn", &super::for_await_in_stream_fn());
+    write_size(&mut out, "for_await_in_try_stream_fn", &super::for_await_in_try_stream_fn());
+    write_size(&mut out, "stream_in_stream_fn", &super::stream_in_stream_fn());
+    write_size(&mut out, "stream_in_try_stream_fn", &super::stream_in_try_stream_fn());
+    write_size(&mut out, "try_stream_in_stream_fn", &super::try_stream_in_stream_fn());
+    write_size(&mut out, "try_stream_in_try_stream_fn", &super::try_stream_in_try_stream_fn());
+    test_helper::git::assert_diff(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/gen/track_stream_size.txt"),
+        out,
+    );
+}
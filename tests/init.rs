@@ -0,0 +1,89 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines)]
+
+use std::pin::pin;
+
+use futures::{
+    future::{self, Future},
+    stream::StreamExt as _,
+    task::{Context, Poll, noop_waker},
+};
+use futures_async_stream::{stream, try_stream};
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidStep(i32);
+
+#[stream(item = i32, init_error = InvalidStep)]
+pub async fn range(n: i32, step: i32) {
+    init! {
+        if step <= 0 {
+            return Err(InvalidStep(step));
+        }
+        let end = n * step;
+    }
+    let mut i = 0;
+    while i < end {
+        yield i;
+        i += step;
+    }
+}
+
+#[try_stream(ok = i32, error = String, init_error = InvalidStep, boxed)]
+pub async fn try_range<'a>(name: &'a str, ref step: i32) {
+    init! {
+        if *step <= 0 {
+            return Err(InvalidStep(*step));
+        }
+    }
+    yield *step;
+    return Err(name.to_owned());
+}
+
+#[stream(item = i32, init_error = InvalidStep, async_init)]
+pub async fn async_range(n: i32, step: i32) {
+    init! {
+        let step = future::ready(step).await;
+        if step <= 0 {
+            return Err(InvalidStep(step));
+        }
+    }
+    for i in 0..n {
+        yield future::ready(i * step).await;
+    }
+}
+
+#[stream(item = i32, init_error = InvalidStep)]
+pub async fn no_init() {
+    yield 1;
+}
+
+#[test]
+fn init() {
+    assert_eq!(run(range(3, 2).unwrap().collect::<Vec<_>>()), [0, 2, 4]);
+    assert_eq!(range(3, 0).err(), Some(InvalidStep(0)));
+
+    assert_eq!(run(try_range("a", 1).unwrap().collect::<Vec<_>>()), [Ok(1), Err("a".to_owned())]);
+    assert_eq!(try_range("a", -1).err(), Some(InvalidStep(-1)));
+
+    assert_eq!(run(no_init().unwrap().collect::<Vec<_>>()), [1]);
+}
+
+#[test]
+fn async_init() {
+    let s = run(async_range(3, 2)).unwrap();
+    assert_eq!(run(s.collect::<Vec<_>>()), [0, 2, 4]);
+    assert_eq!(run(async_range(3, 0)).err(), Some(InvalidStep(0)));
+}
@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines)]
+
+use std::pin::pin;
+
+use futures::{
+    future::{self, Future},
+    stream::StreamExt as _,
+    task::{Context, Poll, noop_waker},
+};
+use futures_async_stream::stream;
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Progress(usize);
+
+#[stream(item = #[derive(Debug, PartialEq)] enum Event {
+    Started(String),
+    Progress(Progress),
+    Done(()),
+})]
+pub async fn events(n: usize) {
+    yield "job".to_owned();
+    for i in 0..n {
+        yield future::ready(Progress(i)).await;
+    }
+    // The enum can also be yielded as is.
+    yield Event::Done(());
+}
+
+#[stream(item = #[derive(Debug, PartialEq)] enum Token<'a> { Word(&'a str), Number(u32) })]
+pub async fn tokens<'a>(s: &'a str) {
+    for token in s.split_whitespace() {
+        match token.parse::<u32>() {
+            Ok(n) => yield n,
+            Err(_) => yield token,
+        }
+    }
+}
+
+#[test]
+fn item_enum() {
+    assert_eq!(
+        run(events(2).collect::<Vec<_>>()),
+        [
+            Event::Started("job".to_owned()),
+            Event::Progress(Progress(0)),
+            Event::Progress(Progress(1)),
+            Event::Done(())
+        ]
+    );
+    assert_eq!(
+        run(tokens("a 1 b").collect::<Vec<_>>()),
+        [Token::Word("a"), Token::Number(1), Token::Word("b")]
+    );
+    assert_eq!(Event::from(Progress(1)), Event::Progress(Progress(1)));
+}
+
+pub mod visibility {
+    use futures_async_stream::stream;
+
+    #[stream(item = enum Item { Int(i32) })]
+    pub async fn public() {
+        yield 1;
+    }
+
+    #[stream(item = enum PrivateItem { Int(i32) })]
+    async fn private() {
+        yield 1;
+    }
+
+    pub fn call_private() -> impl futures::stream::Stream<Item = i32> {
+        futures::stream::StreamExt::map(private(), |PrivateItem::Int(x)| x)
+    }
+}
+
+#[test]
+fn visibility() {
+    // The enum has the same visibility as the function.
+    let items = run(visibility::public().collect::<Vec<_>>());
+    assert!(matches!(items[..], [visibility::Item::Int(1)]));
+    assert_eq!(run(visibility::call_private().collect::<Vec<_>>()), [1]);
+}
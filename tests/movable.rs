@@ -0,0 +1,106 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines, coroutine_clone)]
+
+use std::pin::pin;
+
+use futures::{
+    future::{self, Future},
+    stream::{self, StreamExt as _},
+    task::{Context, Poll, noop_waker},
+};
+use futures_async_stream::{stream, try_stream};
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+fn assert_unpin<T: Unpin>(_: &T) {}
+
+#[stream(item = i32, movable)]
+pub async fn range(n: i32) {
+    for i in 0..n {
+        yield future::ready(i).await;
+    }
+}
+
+#[stream(item = i32, movable)]
+pub async fn double(n: i32) {
+    #[for_await]
+    for i in range(n) {
+        yield i * 2;
+    }
+    // Futures that are not `Unpin` must be pinned on the heap.
+    yield Box::pin(async { future::ready(-1).await }).await;
+    #[for_await]
+    for i in stream::iter(vec![10, 20]) {
+        yield i;
+    }
+}
+
+#[try_stream(ok = i32, error = i32, movable)]
+pub async fn try_range(n: i32) {
+    for i in 0..n {
+        yield i;
+    }
+    future::ready(Err(n)).await?;
+}
+
+#[stream(item = String, clone)]
+pub async fn words(words: Vec<&'static str>) {
+    let mut buf = String::new();
+    for word in words {
+        buf.push_str(word);
+        yield buf.clone();
+    }
+}
+
+#[try_stream(ok = usize, error = (), clone)]
+pub async fn try_lengths(words: Vec<&'static str>) {
+    for word in words {
+        yield future::ready(word.len()).await;
+    }
+}
+
+#[test]
+fn movable() {
+    assert_unpin(&range(3));
+    assert_unpin(&double(3));
+    assert_unpin(&try_range(3));
+
+    assert_eq!(run(range(3).collect::<Vec<_>>()), vec![0, 1, 2]);
+    assert_eq!(run(double(3).collect::<Vec<_>>()), vec![0, 2, 4, -1, 10, 20]);
+    assert_eq!(run(try_range(2).collect::<Vec<_>>()), vec![Ok(0), Ok(1), Err(2)]);
+
+    // `Unpin` streams can be polled without pinning.
+    let mut s = range(3);
+    assert_eq!(run(s.next()), Some(0));
+    let mut s = Some(s);
+    assert_eq!(run(s.take().unwrap().collect::<Vec<_>>()), vec![1, 2]);
+}
+
+#[test]
+fn clone() {
+    let mut s = words(vec!["a", "b", "c"]);
+    assert_unpin(&s);
+    assert_eq!(run(s.next()), Some("a".to_owned()));
+
+    // Fork the stream in the middle of iteration.
+    let fork = s.clone();
+    assert_eq!(run(s.collect::<Vec<_>>()), vec!["ab".to_owned(), "abc".to_owned()]);
+    assert_eq!(run(fork.collect::<Vec<_>>()), vec!["ab".to_owned(), "abc".to_owned()]);
+
+    let mut s = try_lengths(vec!["a", "bb", "ccc"]);
+    assert_eq!(run(s.next()), Some(Ok(1)));
+    let fork = s.clone();
+    assert_eq!(run(s.collect::<Vec<_>>()), vec![Ok(2), Ok(3)]);
+    assert_eq!(run(fork.collect::<Vec<_>>()), vec![Ok(2), Ok(3)]);
+}
@@ -0,0 +1,107 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate, clippy::missing_panics_doc)]
+#![feature(coroutines, coroutine_clone)]
+
+use std::{panic, pin::pin};
+
+use futures::{
+    future::{self, Future},
+    stream::StreamExt as _,
+    task::{Context, Poll, noop_waker},
+};
+use futures_async_stream::{MustConsume as _, stream, try_stream};
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+#[stream(item = i32, must_consume, clone)]
+pub async fn numbers(n: i32) {
+    for i in 0..n {
+        yield i;
+    }
+}
+
+#[try_stream(ok = i32, error = String, must_consume, boxed)]
+pub async fn parse(items: Vec<&'static str>) {
+    for x in items {
+        yield future::ready(x).await.parse::<i32>().map_err(|e| e.to_string())?;
+    }
+}
+
+fn panic_message(f: impl FnOnce() + panic::UnwindSafe) -> String {
+    let payload = panic::catch_unwind(f).unwrap_err();
+    payload.downcast::<String>().map(|s| *s).unwrap()
+}
+
+#[test]
+fn consumed() {
+    assert_eq!(run(numbers(3).collect::<Vec<_>>()), [0, 1, 2]);
+    assert_eq!(run(parse(vec!["1", "2"]).collect::<Vec<_>>()), [Ok(1), Ok(2)]);
+    // `?` ends the stream.
+    assert_eq!(run(parse(vec!["a", "2"]).count()), 1);
+    // A stream that ends without yielding anything.
+    let s = numbers(0);
+    assert_eq!(run(s.collect::<Vec<_>>()), []);
+}
+
+#[test]
+fn cancel() {
+    let s = numbers(3);
+    s.cancel();
+    drop(s);
+
+    let mut s = parse(vec!["1", "2"]);
+    assert_eq!(run(s.next()), Some(Ok(1)));
+    s.cancel();
+    drop(s);
+
+    let mut s = pin!(numbers(3));
+    assert_eq!(run(s.next()), Some(0));
+    s.cancel();
+}
+
+#[test]
+#[cfg_attr(
+    any(not(debug_assertions), not(feature = "std")),
+    ignore = "the check is removed in release builds and without std"
+)]
+fn not_consumed() {
+    let line = line!() + 2;
+    let message = panic_message(|| {
+        let mut s = pin!(numbers(3));
+        assert_eq!(run(s.next()), Some(0));
+    });
+    assert_eq!(
+        message,
+        format!(
+            "stream returned by `numbers` at tests/must_consume.rs:{line}:26 was dropped before \
+             it ended; consume it until it ends, or call `MustConsume::cancel` on it"
+        )
+    );
+
+    let message = panic_message(|| drop(parse(vec!["1"])));
+    assert!(message.starts_with("stream returned by `parse` at tests/must_consume.rs:"));
+}
+
+#[test]
+#[cfg_attr(
+    any(not(debug_assertions), not(feature = "std")),
+    ignore = "the check is removed in release builds and without std"
+)]
+fn clone() {
+    let s = numbers(2);
+    let s2 = s.clone();
+    assert_eq!(run(s.collect::<Vec<_>>()), [0, 1]);
+    // The clone must also be consumed.
+    let message = panic_message(|| drop(s2));
+    assert!(message.contains("`numbers`"), "{message}");
+}
@@ -6,3 +6,35 @@
 use futures_async_stream::{stream, try_stream};
 
 include!("../include/basic.rs");
+
+// `boxed` and `boxed_local` work without `Box` in scope.
+pub trait Trait {
+    #[stream(boxed, item = i32)]
+    async fn stream(&self);
+
+    #[try_stream(boxed_local, ok = i32, error = ())]
+    async fn try_stream(&mut self);
+}
+
+impl Trait for i32 {
+    #[stream(boxed, item = i32)]
+    async fn stream(&self) {
+        yield *self;
+    }
+
+    #[try_stream(boxed_local, ok = i32, error = ())]
+    async fn try_stream(&mut self) {
+        *self += 1;
+        yield *self;
+    }
+}
+
+// Cooperative scheduling and prefetching work without `std`.
+#[stream(item = i32, budget = 16, prefetch = 4)]
+pub async fn prefetch<S: futures_core::Stream<Item = i32>>(s: S) {
+    #[for_await]
+    for x in s {
+        yield x;
+        yield_now!();
+    }
+}
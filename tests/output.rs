@@ -0,0 +1,84 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines, coroutine_clone)]
+
+use std::{ops::ControlFlow, pin::pin};
+
+use futures::{
+    future::{self, Future},
+    stream::{self, StreamExt as _},
+    task::{Context, Poll, noop_waker},
+};
+use futures_async_stream::try_stream;
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+#[try_stream(output = Option<i32>)]
+pub async fn checked_sum(items: Vec<Option<i32>>) {
+    let mut sum = 0_i32;
+    for x in items {
+        // `?` on `None` ends the stream without yielding an item.
+        sum = sum.checked_add(x?)?;
+        yield sum;
+    }
+}
+
+#[try_stream(output = ControlFlow<String, i32>)]
+pub async fn until_negative(items: Vec<i32>) {
+    #[for_await]
+    for x in stream::iter(items) {
+        if x < 0 {
+            // `Break` is yielded as the last item.
+            return ControlFlow::Break(format!("negative: {x}"));
+        }
+        yield future::ready(x).await;
+    }
+}
+
+#[try_stream(output = Result<i32, String>, clone)]
+pub async fn parse(items: Vec<&'static str>) {
+    for x in items {
+        yield x.parse::<i32>().map_err(|e| e.to_string())?;
+    }
+}
+
+#[test]
+fn option() {
+    assert_eq!(run(checked_sum(vec![Some(1), Some(2)]).collect::<Vec<_>>()), [1, 3]);
+    assert_eq!(run(checked_sum(vec![Some(1), None, Some(2)]).collect::<Vec<_>>()), [1]);
+    assert_eq!(run(checked_sum(vec![Some(i32::MAX), Some(1)]).collect::<Vec<_>>()), [i32::MAX]);
+
+    let mut s = pin!(checked_sum(vec![None]));
+    assert_eq!(run(s.next()), None);
+    assert_eq!(run(s.next()), None);
+}
+
+#[test]
+fn control_flow() {
+    assert_eq!(
+        run(until_negative(vec![1, 2]).collect::<Vec<_>>()),
+        [ControlFlow::Continue(1), ControlFlow::Continue(2)]
+    );
+    assert_eq!(
+        run(until_negative(vec![1, -2, 3]).collect::<Vec<_>>()),
+        [ControlFlow::Continue(1), ControlFlow::Break("negative: -2".to_owned())]
+    );
+}
+
+#[test]
+fn result() {
+    let s = parse(vec!["1", "a", "2"]);
+    let err = "a".parse::<i32>().unwrap_err().to_string();
+    assert_eq!(run(s.clone().collect::<Vec<_>>()), [Ok(1), Err(err.clone())]);
+    assert_eq!(run(s.collect::<Vec<_>>()), [Ok(1), Err(err)]);
+}
@@ -0,0 +1,171 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines)]
+#![cfg(feature = "std")]
+
+use std::{
+    panic::{AssertUnwindSafe, catch_unwind},
+    pin::{Pin, pin},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::Wake,
+};
+
+use futures::{
+    future::{self, Future},
+    stream::{Stream, StreamExt as _},
+    task::{Context, Poll, Waker, noop_waker},
+};
+use futures_async_stream::stream;
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+fn assert_send<T: Send>(_: &T) {}
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Progress(usize),
+    Skipped(String),
+}
+
+#[stream(outputs(rows = Vec<i32>, events = Event))]
+pub async fn parse_csv(lines: Vec<&'static str>) {
+    for (i, line) in lines.into_iter().enumerate() {
+        match line.split(',').map(str::parse).collect() {
+            Ok(row) => yield_to!(rows: row),
+            Err(_) => yield_to!(events: Event::Skipped(line.to_owned())),
+        }
+        yield_to!(events: future::ready(Event::Progress(i + 1)).await);
+    }
+}
+
+#[stream(outputs(words = &'a str, lengths = usize))]
+pub async fn split<'a>(s: &'a str) {
+    for word in s.split_whitespace() {
+        yield_to!(words: word);
+        yield_to!(lengths: word.len());
+    }
+}
+
+#[test]
+fn outputs() {
+    let ParseCsvOutputs { rows, events } = parse_csv(vec!["1,2", "a", "3"]);
+    assert_send(&rows);
+    assert_send(&events);
+    // The events yielded while the rows are consumed are buffered.
+    assert_eq!(run(rows.collect::<Vec<_>>()), [vec![1, 2], vec![3]]);
+    assert_eq!(
+        run(events.collect::<Vec<_>>()),
+        [
+            Event::Progress(1),
+            Event::Skipped("a".to_owned()),
+            Event::Progress(2),
+            Event::Progress(3)
+        ]
+    );
+
+    let SplitOutputs { words, lengths } = split("a bc def");
+    let (words, lengths) =
+        run(future::join(words.collect::<Vec<_>>(), lengths.collect::<Vec<_>>()));
+    assert_eq!(words, ["a", "bc", "def"]);
+    assert_eq!(lengths, [1, 2, 3]);
+}
+
+#[test]
+fn drop_output() {
+    let ParseCsvOutputs { rows, events } = parse_csv(vec!["1", "2"]);
+    // The other output is not affected.
+    drop(events);
+    assert_eq!(run(rows.collect::<Vec<_>>()), [vec![1], vec![2]]);
+}
+
+#[derive(Default)]
+struct Flag(AtomicBool);
+
+impl Wake for Flag {
+    fn wake(self: Arc<Self>) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+#[stream(outputs(data = i32, events = i32))]
+async fn gated(open: Arc<AtomicBool>) {
+    // Pending until the gate is opened, without registering the waker.
+    future::poll_fn(|_| if open.load(Ordering::SeqCst) { Poll::Ready(()) } else { Poll::Pending })
+        .await;
+    yield_to!(events: 1);
+    yield_to!(data: 2);
+}
+
+fn poll_next<S: Stream + Unpin>(s: &mut S, waker: &Arc<Flag>) -> Poll<Option<S::Item>> {
+    let waker = Waker::from(Arc::clone(waker));
+    s.poll_next_unpin(&mut Context::from_waker(&waker))
+}
+
+#[test]
+fn wake() {
+    let open = Arc::new(AtomicBool::new(false));
+    let GatedOutputs { data, events } = gated(Arc::clone(&open));
+    let (mut data, mut events) = (Box::pin(data), Box::pin(events));
+    let (data_waker, events_waker) = (Arc::new(Flag::default()), Arc::new(Flag::default()));
+
+    assert_eq!(poll_next(&mut events, &events_waker), Poll::Pending);
+    open.store(true, Ordering::SeqCst);
+    // Polling `data` resumes the coroutine, which yields an item to `events`.
+    assert_eq!(poll_next(&mut data, &data_waker), Poll::Ready(Some(2)));
+    assert!(events_waker.0.load(Ordering::SeqCst));
+    assert_eq!(poll_next(&mut events, &events_waker), Poll::Ready(Some(1)));
+    assert_eq!(poll_next(&mut events, &events_waker), Poll::Ready(None));
+    assert_eq!(poll_next(&mut data, &data_waker), Poll::Ready(None));
+}
+
+type Slot = Arc<Mutex<Option<Pin<Box<dyn Stream<Item = i32> + Send>>>>>;
+
+#[stream(outputs(a = i32, b = i32))]
+async fn reentrant(slot: Slot) {
+    // Polls the other output while the coroutine is resumed.
+    let polled = future::poll_fn(|cx| {
+        Poll::Ready(slot.lock().unwrap().as_mut().unwrap().as_mut().poll_next(cx))
+    })
+    .await;
+    yield_to!(a: i32::from(polled.is_pending()));
+    yield_to!(b: 2);
+}
+
+#[test]
+fn reentrant_poll() {
+    let slot = Slot::default();
+    let ReentrantOutputs { a, b } = reentrant(Arc::clone(&slot));
+    *slot.lock().unwrap() = Some(Box::pin(b));
+    assert_eq!(run(a.collect::<Vec<_>>()), [1]);
+    let b = slot.lock().unwrap().take().unwrap();
+    assert_eq!(run(b.collect::<Vec<_>>()), [2]);
+}
+
+#[stream(outputs(a = i32, b = i32))]
+async fn panics() {
+    yield_to!(b: 1);
+    panic!("panic in the body");
+}
+
+#[test]
+fn panic() {
+    let PanicsOutputs { a, b } = panics();
+    let mut a = Box::pin(a);
+    assert!(catch_unwind(AssertUnwindSafe(|| run(a.next()))).is_err());
+    // The other outputs end after their buffered items.
+    assert_eq!(run(b.collect::<Vec<_>>()), [1]);
+    assert_eq!(run(a.next()), None);
+}
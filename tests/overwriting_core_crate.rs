@@ -1,5 +1,6 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
+#![allow(clippy::must_use_candidate)]
 #![feature(coroutines)]
 
 // See https://github.com/rust-lang/pin-utils/pull/26#discussion_r344491597
@@ -12,6 +13,85 @@ extern crate futures_async_stream as core;
 // Dummy module to check that the expansion refers to the crate.
 mod futures_async_stream {}
 
+// Dummy items to check that the expansion does not refer to the items in the scope.
+#[allow(dead_code)]
+struct Box;
+#[allow(dead_code)]
+struct Pin;
+#[allow(dead_code)]
+struct Poll;
+
 use ::futures_async_stream::{stream, try_stream};
 
 include!("include/basic.rs");
+
+#[stream(boxed, item = ())]
+pub async fn boxed() {
+    yield;
+}
+
+#[try_stream(boxed_local, ok = (), error = ())]
+pub async fn boxed_local() {
+    yield;
+}
+
+#[no_implicit_prelude]
+pub mod no_implicit_prelude {
+    use ::futures_async_stream::{stream, stream_trait, try_stream};
+
+    #[stream(item = ())]
+    async fn stream() {}
+
+    #[stream(item = ())]
+    pub async fn for_await_in_stream_fn() {
+        #[for_await]
+        for () in stream() {
+            yield;
+            async {}.await;
+        }
+    }
+
+    #[try_stream(ok = (), error = ())]
+    pub async fn stream_in_try_stream_fn() {
+        let _ = {
+            #[stream]
+            async move {
+                yield;
+                async {}.await;
+            }
+        };
+        yield;
+    }
+
+    #[stream(boxed, item = ())]
+    pub async fn boxed() {
+        yield;
+    }
+
+    #[try_stream(boxed_local, ok = (), error = ())]
+    pub async fn boxed_local() {
+        yield;
+    }
+
+    #[stream_trait]
+    pub trait Trait {
+        #[stream(item = ())]
+        async fn method(&self);
+
+        #[try_stream(boxed_local, ok = (), error = ())]
+        async fn no_receiver();
+    }
+
+    #[stream_trait]
+    impl Trait for () {
+        #[stream(item = ())]
+        async fn method(&self) {
+            yield;
+        }
+
+        #[try_stream(boxed_local, ok = (), error = ())]
+        async fn no_receiver() {
+            yield;
+        }
+    }
+}
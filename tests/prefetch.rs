@@ -0,0 +1,174 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines)]
+
+use std::{
+    cell::RefCell,
+    pin::pin,
+    rc::Rc,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::Wake,
+};
+
+use futures::{
+    future::{self, Future},
+    stream::{Stream, StreamExt as _},
+    task::{Context, Poll, Waker, noop_waker},
+};
+use futures_async_stream::{stream, try_stream};
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+#[derive(Default)]
+struct CountWaker(AtomicUsize);
+
+impl Wake for CountWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+type Log = Rc<RefCell<Vec<String>>>;
+
+// Whether the gate is open, and the waker of the task waiting on it.
+type Gate = Rc<RefCell<(bool, Option<Waker>)>>;
+
+#[stream(item = i32, prefetch = 2)]
+pub async fn produce(log: Log, n: i32) {
+    for i in 0..n {
+        log.borrow_mut().push(format!("produce {i}"));
+        yield i;
+    }
+}
+
+#[stream(item = i32, prefetch = 2, movable, eager)]
+pub async fn produce_eager(log: Log, n: i32) {
+    for i in 0..n {
+        log.borrow_mut().push(format!("produce {i}"));
+        yield i;
+    }
+}
+
+#[stream(item = i32, prefetch = 2, movable, eager)]
+pub async fn gated(gate: Gate) {
+    yield 0;
+    future::poll_fn(move |cx| {
+        let mut gate = gate.borrow_mut();
+        if gate.0 {
+            return Poll::Ready(());
+        }
+        gate.1 = Some(cx.waker().clone());
+        Poll::Pending
+    })
+    .await;
+    yield 1;
+}
+
+#[stream(item = i32, prefetch = 4, size_hint = (3, Some(3)))]
+pub async fn pending() {
+    yield 1;
+    // Prefetching stops when the stream returns `Poll::Pending`.
+    yield_now!();
+    yield 2;
+    yield 3;
+}
+
+#[try_stream(ok = i32, error = i32, prefetch = 2)]
+pub async fn try_produce(n: i32) {
+    for i in 0..n {
+        yield i;
+    }
+    return Err(n);
+}
+
+fn consume<S: Stream<Item = i32>>(log: &Log, s: S) {
+    let mut s = pin!(s);
+    while let Some(i) = run(s.next()) {
+        log.borrow_mut().push(format!("consume {i}"));
+    }
+}
+
+#[test]
+fn prefetch() {
+    let log = Log::default();
+    let s = produce(log.clone(), 4);
+    assert!(log.borrow().is_empty());
+    consume(&log, s);
+    assert_eq!(
+        *log.borrow(),
+        [
+            "produce 0",
+            "produce 1",
+            "produce 2",
+            "consume 0",
+            "produce 3",
+            "consume 1",
+            "consume 2",
+            "consume 3",
+        ]
+    );
+
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut s = pin!(pending());
+    assert_eq!(s.size_hint(), (3, Some(3)));
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(Some(1)));
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(Some(2)));
+    assert_eq!(s.size_hint(), (1, Some(1)));
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(Some(3)));
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(None));
+    assert_eq!(s.size_hint(), (0, Some(0)));
+
+    assert_eq!(run(try_produce(3).collect::<Vec<_>>()), [Ok(0), Ok(1), Ok(2), Err(3)]);
+}
+
+#[test]
+fn eager() {
+    let log = Log::default();
+    let s = produce_eager(log.clone(), 3);
+    assert_eq!(*log.borrow(), ["produce 0", "produce 1"]);
+    consume(&log, s);
+    assert_eq!(
+        *log.borrow(),
+        ["produce 0", "produce 1", "produce 2", "consume 0", "consume 1", "consume 2"]
+    );
+}
+
+#[test]
+fn eager_wakeup() {
+    let gate = Gate::default();
+    // Runs until it waits on the gate with a no-op waker.
+    let mut s = pin!(gated(gate.clone()));
+    assert!(gate.borrow().1.is_some());
+
+    let waker = Arc::new(CountWaker::default());
+    let w = Waker::from(waker.clone());
+    let cx = &mut Context::from_waker(&w);
+    // The first poll waits on the gate again with the consumer's waker.
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(Some(0)));
+    assert!(gate.borrow().1.as_ref().unwrap().will_wake(&w));
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Pending);
+
+    gate.borrow_mut().0 = true;
+    gate.borrow_mut().1.take().unwrap().wake();
+    assert_eq!(waker.0.load(Ordering::Relaxed), 1);
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(Some(1)));
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(None));
+}
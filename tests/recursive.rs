@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines, proc_macro_hygiene, stmt_expr_attributes)]
+
+use std::pin::pin;
+
+use futures::{
+    future::Future,
+    stream::Stream,
+    task::{Context, Poll, noop_waker},
+};
+use futures_async_stream::{for_await, stream, try_stream};
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+async fn collect<S: Stream>(stream: S) -> Vec<S::Item> {
+    let mut v = vec![];
+    #[for_await]
+    for x in stream {
+        v.push(x);
+    }
+    v
+}
+
+pub struct Tree {
+    value: i32,
+    children: Vec<Tree>,
+}
+
+#[stream(item = i32, recursive)]
+pub async fn walk(tree: &Tree) {
+    yield tree.value;
+    for child in &tree.children {
+        #[for_await]
+        for x in walk(child) {
+            yield x;
+        }
+    }
+}
+
+#[try_stream(ok = u32, error = u32, recursive)]
+pub async fn countdown(n: u32) {
+    if n == 0 {
+        return Err(0);
+    }
+    yield n;
+    #[for_await]
+    for x in countdown(n - 1) {
+        yield x?;
+    }
+}
+
+#[stream(item = i32, recursive, boxed_local)]
+pub async fn boxed(n: i32) {
+    if n > 0 {
+        #[for_await]
+        for x in boxed(n - 1) {
+            yield x;
+        }
+    }
+    yield n;
+}
+
+impl Tree {
+    #[stream(item = i32, recursive)]
+    pub async fn repeat(&self, n: usize) {
+        if n > 0 {
+            yield self.value;
+            #[for_await]
+            for x in self.repeat(n - 1) {
+                yield x;
+            }
+        }
+    }
+
+    #[stream(item = i32, recursive)]
+    pub async fn leaves(&self) {
+        if self.children.is_empty() {
+            yield self.value;
+        }
+        for child in &self.children {
+            #[for_await]
+            for x in Self::leaves(child) {
+                yield x;
+            }
+        }
+    }
+}
+
+fn tree() -> Tree {
+    let leaf = |value| Tree { value, children: vec![] };
+    Tree { value: 1, children: vec![Tree { value: 2, children: vec![leaf(3)] }, leaf(4)] }
+}
+
+#[test]
+fn test() {
+    fn assert_send<T: Send>(_: &T) {}
+
+    run(async {
+        let tree = tree();
+        let s = walk(&tree);
+        assert_send(&s);
+        assert_eq!(collect(s).await, [1, 2, 3, 4]);
+        assert_eq!(collect(countdown(2)).await, [Ok(2), Ok(1), Err(0)]);
+        assert_eq!(collect(boxed(2)).await, [0, 1, 2]);
+        assert_eq!(collect(tree.leaves()).await, [3, 4]);
+        assert_eq!(collect(tree.repeat(2)).await, [1, 1]);
+    });
+}
@@ -0,0 +1,49 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![feature(coroutines)]
+
+use async_trait::async_trait;
+use futures_async_stream::{stream, try_stream};
+
+#[async_trait]
+trait Foo {
+    async fn future(&self) -> i32;
+
+    #[stream(boxed, item = i32)]
+    async fn stream(&self, x: i32);
+
+    #[try_stream(boxed, ok = i32, error = String)]
+    async fn try_stream(&self, x: &str);
+
+    #[stream(boxed_local, item = i32)]
+    async fn default_method(&self) {
+        yield 1;
+    }
+}
+
+struct Bar;
+
+#[async_trait]
+impl Foo for Bar {
+    async fn future(&self) -> i32 {
+        1
+    }
+
+    #[stream(boxed, item = i32)]
+    async fn stream(&self, x: i32) {
+        yield x;
+        yield self.future().await;
+    }
+
+    #[try_stream(boxed, ok = i32, error = String)]
+    async fn try_stream(&self, x: &str) {
+        yield 1;
+        return Err(x.to_owned());
+    }
+}
+
+fn main() {
+    let _ = Bar.stream(1);
+    let _ = Bar.try_stream("");
+    let _ = Bar.default_method();
+}
@@ -0,0 +1,36 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![feature(coroutines, proc_macro_hygiene, stmt_expr_attributes)]
+#![deny(missing_docs)]
+
+//! Attributes below `#[stream]` are forwarded to the generated function.
+
+use futures_async_stream::{stream, try_stream};
+
+/// Doc comments are forwarded.
+#[stream(item = i32)]
+#[inline]
+#[cfg_attr(all(), must_use)]
+pub async fn forwarded(x: i32) {
+    yield x;
+}
+
+/// Doc comments are forwarded.
+#[try_stream(ok = i32, error = ())]
+#[cfg_attr(all(), inline)]
+#[must_use]
+pub async fn forwarded_try(x: i32) {
+    yield x;
+}
+
+fn main() {
+    let _ = forwarded(1);
+    let _ = forwarded_try(1);
+    let _ = {
+        #[stream]
+        #[allow(unused_mut)]
+        async move {
+            yield 1;
+        }
+    };
+}
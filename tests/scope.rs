@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines)]
+#![cfg(feature = "alloc")]
+
+use std::{pin::pin, rc::Rc};
+
+use futures::{
+    future::{self, Future, FutureExt as _},
+    stream::{self, Stream, StreamExt as _},
+    task::{Context, Poll, noop_waker},
+};
+use futures_async_stream::stream;
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+fn assert_send<T: Send>(_: &T) {}
+
+// A stream that is pending once before each item.
+fn pending_once<T>(items: Vec<T>) -> impl Stream<Item = T> {
+    stream::iter(items).then(|x| {
+        let mut pending = true;
+        future::poll_fn(move |cx| {
+            if pending {
+                pending = false;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .map(move |()| x)
+    })
+}
+
+#[stream(item = String)]
+pub async fn fan_in(names: Vec<&'static str>) {
+    yield "start".to_owned();
+    scope!(|s| {
+        for name in names {
+            s.spawn(async move {
+                #[for_await]
+                for i in pending_once(vec![1, 2]) {
+                    yield format!("{name}{i}");
+                }
+            });
+        }
+    });
+    yield "end".to_owned();
+}
+
+#[stream(item = i32)]
+pub async fn borrow(items: Vec<i32>) {
+    let items = &items;
+    scope!(|s: &mut futures_async_stream::Scope<'_, i32>| {
+        s.spawn(async {
+            for x in items {
+                yield *x;
+            }
+        });
+        // Streams that are not async blocks can also be spawned.
+        s.spawn(stream::iter(vec![10, 20]));
+    });
+}
+
+#[stream(item = i32)]
+pub async fn local(x: Rc<i32>) {
+    scope_local!(|s| {
+        s.spawn(async move {
+            yield *x;
+        });
+    });
+}
+
+#[stream(item = usize)]
+pub async fn round_robin(counts: Vec<usize>) {
+    scope!(|s| {
+        for (i, count) in counts.into_iter().enumerate() {
+            s.spawn(stream::repeat(i).take(count));
+        }
+    });
+}
+
+#[stream(item = i32)]
+pub async fn empty() {
+    scope!(|_s| {});
+    yield 1;
+}
+
+#[test]
+fn scope() {
+    let s = fan_in(vec!["a", "b"]);
+    assert_send(&s);
+    // The items of the children are interleaved as they are ready.
+    assert_eq!(run(s.collect::<Vec<_>>()), ["start", "a1", "b1", "a2", "b2", "end"]);
+
+    let mut v = run(borrow(vec![1, 2]).collect::<Vec<_>>());
+    v.sort_unstable();
+    assert_eq!(v, [1, 2, 10, 20]);
+
+    assert_eq!(run(local(Rc::new(1)).collect::<Vec<_>>()), [1]);
+    assert_eq!(run(empty().collect::<Vec<_>>()), [1]);
+}
+
+#[test]
+fn fairness() {
+    // Children that are always ready take turns.
+    assert_eq!(run(round_robin(vec![3, 3]).collect::<Vec<_>>()), [0, 1, 0, 1, 0, 1]);
+    // The order is kept when a child ends.
+    assert_eq!(run(round_robin(vec![2, 1, 2]).collect::<Vec<_>>()), [0, 1, 2, 0, 2]);
+}
+
+#[test]
+fn nested() {
+    #[stream(item = i32)]
+    async fn nested() {
+        scope!(|s| {
+            for i in 0..2 {
+                s.spawn(async move {
+                    scope!(|s| {
+                        s.spawn(async move {
+                            yield i * 10;
+                        });
+                        s.spawn(async move {
+                            yield i * 10 + 1;
+                        });
+                    });
+                });
+            }
+        });
+    }
+
+    let mut v = run(nested().collect::<Vec<_>>());
+    v.sort_unstable();
+    assert_eq!(v, [0, 1, 10, 11]);
+}
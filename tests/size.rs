@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines)]
+
+use std::{mem, pin::pin};
+
+use futures::{
+    future::Future,
+    stream::{Stream as _, StreamExt as _},
+    task::{Context, Poll, noop_waker},
+};
+use futures_async_stream::{stream, try_stream};
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+const BUF_SIZE: usize = 4096;
+
+#[stream(item = u8)]
+async fn large() {
+    let buf = [1; BUF_SIZE];
+    for b in buf {
+        yield b;
+    }
+}
+
+#[stream(item = i32, max_size = 64)]
+pub async fn small(n: i32) {
+    for i in 0..n {
+        yield i;
+    }
+}
+
+#[try_stream(ok = i32, error = (), max_size = 64)]
+pub async fn try_small(n: i32) {
+    for i in 0..n {
+        yield i;
+    }
+}
+
+#[stream(item = u8, max_size = BUF_SIZE * 2)]
+pub async fn large_with_max_size() {
+    let buf = [1; BUF_SIZE];
+    for b in buf {
+        yield b;
+    }
+}
+
+#[stream(item = u8, auto_box_over = 256)]
+pub async fn large_auto_boxed() {
+    let buf = [1; BUF_SIZE];
+    for b in buf {
+        yield b;
+    }
+}
+
+#[stream(item = i32, auto_box_over = 256)]
+pub async fn small_auto_boxed(n: i32) {
+    for i in 0..n {
+        yield i;
+    }
+}
+
+#[try_stream(ok = u8, error = (), auto_box_over = 256, max_size = 256 + 16)]
+pub async fn try_large_auto_boxed() {
+    let buf = [1; BUF_SIZE];
+    for b in buf {
+        yield b;
+    }
+}
+
+fn size_of_val<T>(_: &T) -> usize {
+    mem::size_of::<T>()
+}
+
+#[test]
+fn max_size() {
+    assert!(size_of_val(&small(3)) <= 64);
+    assert!(size_of_val(&try_small(3)) <= 64);
+    assert!(size_of_val(&large_with_max_size()) <= BUF_SIZE * 2);
+
+    assert_eq!(run(small(3).collect::<Vec<_>>()), vec![0, 1, 2]);
+    assert_eq!(run(try_small(3).collect::<Vec<_>>()), vec![Ok(0), Ok(1), Ok(2)]);
+    assert_eq!(run(large_with_max_size().count()), BUF_SIZE);
+}
+
+#[test]
+fn auto_box_over() {
+    assert!(size_of_val(&large()) > BUF_SIZE);
+    // The state of the stream is moved to the heap.
+    assert!(size_of_val(&large_auto_boxed()) <= 256 + 16);
+    assert!(size_of_val(&try_large_auto_boxed()) <= 256 + 16);
+    // The state of the stream is stored inline.
+    assert!(size_of_val(&small_auto_boxed(3)) <= 256 + 16);
+
+    assert_eq!(run(large_auto_boxed().count()), BUF_SIZE);
+    assert_eq!(run(try_large_auto_boxed().count()), BUF_SIZE);
+    assert_eq!(run(small_auto_boxed(3).collect::<Vec<_>>()), vec![0, 1, 2]);
+    assert_eq!(small_auto_boxed(3).size_hint(), (0, None));
+
+    // Dropping the stream in the middle drops the state of the stream.
+    let mut s = pin!(large_auto_boxed());
+    assert_eq!(run(s.next()), Some(1));
+    let mut s = pin!(small_auto_boxed(3));
+    assert_eq!(run(s.next()), Some(0));
+}
+
+#[test]
+fn auto_traits() {
+    fn assert_send<T: Send>(_: &T) {}
+    assert_send(&large_auto_boxed());
+    assert_send(&small_auto_boxed(0));
+}
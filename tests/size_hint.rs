@@ -0,0 +1,143 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines, coroutine_clone, proc_macro_hygiene, stmt_expr_attributes)]
+
+use std::pin::pin;
+
+use futures::{
+    future::{self, Future},
+    stream::{Stream, StreamExt as _},
+    task::{Context, Poll, noop_waker},
+};
+use futures_async_stream::{stream, try_stream};
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+#[stream(item = i32)]
+pub async fn no_hint() {
+    yield 1;
+}
+
+#[stream(item = usize, size_hint = (n, Some(n)))]
+pub async fn range(n: usize) {
+    for i in 0..n {
+        yield i;
+    }
+}
+
+// Emulates a paginated API that announces the total count in the first page.
+#[stream(item = usize)]
+pub async fn pages() {
+    let total = future::ready(3).await;
+    size_hint!(total, Some(total));
+    for i in 0..total {
+        yield i;
+    }
+}
+
+#[try_stream(ok = i32, error = i32, size_hint = (0, Some(3)))]
+pub async fn try_pages(fail: bool) {
+    yield 0;
+    if fail {
+        return Err(1);
+    }
+    let () = size_hint!(2, Some(2));
+    yield 1;
+    yield 2;
+}
+
+#[stream(item = usize, clone)]
+pub async fn clone_pages() {
+    size_hint!(2, Some(2));
+    yield 0;
+    yield 1;
+}
+
+#[try_stream(output = Option<usize>, size_hint = (2, Some(2)))]
+pub async fn output_range() {
+    yield 0;
+    yield 1;
+}
+
+#[try_stream(ok = usize, error = (), checkpoint = usize)]
+pub async fn checkpoint_pages() {
+    size_hint!(2, Some(2));
+    yield 0;
+    checkpoint!(1);
+    yield 1;
+}
+
+fn size_hints<S: Stream>(s: S) -> Vec<(usize, Option<usize>)> {
+    let mut s = pin!(s);
+    let mut hints = vec![s.size_hint()];
+    while run(s.next()).is_some() {
+        hints.push(s.size_hint());
+    }
+    hints.push(s.size_hint());
+    hints
+}
+
+#[test]
+fn static_size_hint() {
+    assert_eq!(size_hints(no_hint()), [(0, None), (0, None), (0, Some(0))]);
+    assert_eq!(size_hints(range(2)), [(2, Some(2)), (1, Some(1)), (0, Some(0)), (0, Some(0))]);
+    assert_eq!(run(range(3).collect::<Vec<_>>()), [0, 1, 2]);
+}
+
+#[test]
+fn dynamic_size_hint() {
+    assert_eq!(
+        size_hints(pages()),
+        [(0, None), (2, Some(2)), (1, Some(1)), (0, Some(0)), (0, Some(0))]
+    );
+    assert_eq!(
+        size_hints(try_pages(false)),
+        [(0, Some(3)), (0, Some(2)), (1, Some(1)), (0, Some(0)), (0, Some(0))]
+    );
+    assert_eq!(
+        size_hints(try_pages(true)),
+        [(0, Some(3)), (0, Some(2)), (0, Some(0)), (0, Some(0))]
+    );
+
+    let s = #[stream]
+    async {
+        size_hint!(2, None);
+        yield 1;
+        yield 2;
+    };
+    assert_eq!(size_hints(s), [(0, None), (1, None), (0, None), (0, Some(0))]);
+}
+
+#[test]
+fn size_hint_with_other_arguments() {
+    let hints = [(0, None), (1, Some(1)), (0, Some(0)), (0, Some(0))];
+    assert_eq!(size_hints(clone_pages()), hints);
+    assert_eq!(size_hints(clone_pages().clone()), hints);
+    assert_eq!(size_hints(checkpoint_pages()), hints);
+    assert_eq!(
+        size_hints(output_range()),
+        [(2, Some(2)), (1, Some(1)), (0, Some(0)), (0, Some(0))]
+    );
+}
+
+#[test]
+fn no_hint_is_not_stored() {
+    #[stream(item = i32)]
+    async fn hint() {
+        size_hint!(1, Some(1));
+        yield 1;
+    }
+
+    // Only the streams that use the size hint store it.
+    assert!(size_of_val(&no_hint()) < size_of_val(&hint()));
+}
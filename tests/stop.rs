@@ -0,0 +1,125 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines)]
+#![cfg(feature = "alloc")]
+
+use std::{
+    pin::pin,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::Wake,
+};
+
+use futures::{
+    future::{self, Future},
+    stream::{Stream as _, StreamExt as _},
+    task::{Context, Poll, Waker, noop_waker},
+};
+use futures_async_stream::{StopSignal, stream, try_stream};
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+#[derive(Default)]
+struct CountWaker(AtomicUsize);
+
+impl Wake for CountWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[stream(item = String, stoppable)]
+pub async fn frames() {
+    let mut i = 0;
+    loop {
+        if yield_or_stop!(format!("frame {i}")).is_stop() {
+            // The body can run async cleanup before the stream ends.
+            let frame = future::ready("goodbye".to_owned()).await;
+            yield frame;
+            return;
+        }
+        i += 1;
+    }
+}
+
+#[try_stream(ok = i32, error = i32, stoppable)]
+pub async fn count(n: i32) {
+    for i in 0..n {
+        if yield_or_stop!(i) == StopSignal::Stop {
+            return Err(i);
+        }
+    }
+}
+
+#[stream(item = i32, stoppable)]
+pub async fn idle() {
+    future::pending::<()>().await;
+}
+
+#[test]
+fn stop() {
+    let (s, handle) = frames();
+    let mut s = pin!(s);
+    assert_eq!(run(s.next()).unwrap(), "frame 0");
+    assert_eq!(run(s.next()).unwrap(), "frame 1");
+    assert!(!handle.is_stop_requested());
+    handle.request_stop();
+    assert!(handle.is_stop_requested());
+    assert_eq!(run(s.next()).unwrap(), "goodbye");
+    assert_eq!(run(s.next()), None);
+
+    let (s, handle) = count(3);
+    assert_eq!(run(s.collect::<Vec<_>>()), [Ok(0), Ok(1), Ok(2)]);
+    assert!(!handle.is_stop_requested());
+
+    let (s, handle) = count(3);
+    let mut s = pin!(s);
+    assert_eq!(run(s.next()), Some(Ok(0)));
+    handle.request_stop();
+    assert_eq!(run(s.next()), Some(Err(0)));
+    assert_eq!(run(s.next()), None);
+
+    // If the stop is requested before the first `yield_or_stop!`, the item is
+    // not yielded.
+    let (s, handle) = frames();
+    handle.request_stop();
+    assert_eq!(run(s.collect::<Vec<_>>()), ["goodbye"]);
+}
+
+#[test]
+fn request_stop_wakes() {
+    let count = Arc::new(CountWaker::default());
+    let waker = Waker::from(count.clone());
+    let cx = &mut Context::from_waker(&waker);
+
+    let (s, handle) = idle();
+    // The stream has not been polled yet, so there is no task to wake.
+    handle.request_stop();
+    let mut s = pin!(s);
+    assert!(s.as_mut().poll_next(cx).is_pending());
+    assert_eq!(count.0.load(Ordering::Relaxed), 0);
+    handle.request_stop();
+    assert_eq!(count.0.load(Ordering::Relaxed), 1);
+    // The waker is registered again only when the stream is polled again.
+    handle.request_stop();
+    assert_eq!(count.0.load(Ordering::Relaxed), 1);
+    assert!(s.as_mut().poll_next(cx).is_pending());
+    handle.request_stop();
+    assert_eq!(count.0.load(Ordering::Relaxed), 2);
+}
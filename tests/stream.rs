@@ -4,6 +4,7 @@
 #![feature(coroutines, proc_macro_hygiene, stmt_expr_attributes, gen_future)]
 
 use std::{
+    panic::{self, AssertUnwindSafe},
     pin::{Pin, pin},
     rc::Rc,
     sync::Arc,
@@ -11,7 +12,7 @@ use std::{
 
 use futures::{
     future::Future,
-    stream::Stream,
+    stream::{FusedStream as _, Stream},
     task::{Context, Poll, noop_waker},
 };
 use futures_async_stream::{for_await, stream, stream_block};
@@ -397,6 +398,56 @@ fn test_early_exit() {
     });
 }
 
+#[test]
+fn test_fused() {
+    #[stream(item = i32)]
+    async fn stream() {
+        yield 1;
+    }
+
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+
+    let mut s = pin!(stream());
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(Some(1)));
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(None));
+    // Polling a finished stream does not resume the completed coroutine.
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(None));
+
+    let mut s = pin!(
+        #[stream]
+        async {
+            yield 1;
+        }
+    );
+    assert!(!s.is_terminated());
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(Some(1)));
+    assert!(!s.is_terminated());
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(None));
+    assert!(s.is_terminated());
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(None));
+}
+
+#[test]
+fn test_panic() {
+    #[stream(item = i32)]
+    async fn stream() {
+        yield 1;
+        panic!("panic in stream");
+    }
+
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+
+    let mut s = pin!(stream());
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(Some(1)));
+    let res = panic::catch_unwind(AssertUnwindSafe(|| s.as_mut().poll_next(cx)));
+    assert!(res.is_err());
+    // A stream that panicked is terminated.
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(None));
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(None));
+}
+
 const _: fn() = || {
     fn assert_send<T: ?Sized + Send>() {}
     fn assert_sync<T: ?Sized + Sync>() {}
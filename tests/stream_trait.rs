@@ -0,0 +1,181 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines, proc_macro_hygiene, stmt_expr_attributes)]
+
+use std::pin::pin;
+
+use futures::{
+    future::Future,
+    stream::Stream,
+    task::{Context, Poll, noop_waker},
+};
+use futures_async_stream::for_await;
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+async fn collect<S: Stream>(stream: S) -> Vec<S::Item> {
+    let mut v = vec![];
+    #[for_await]
+    for x in stream {
+        v.push(x);
+    }
+    v
+}
+
+// The methods of the original trait and the companion trait have the same
+// names, so the traits are defined in a module and imported separately.
+pub mod traits {
+    use std::fmt::Debug;
+
+    use futures_async_stream::stream_trait;
+
+    #[stream_trait]
+    pub trait Values {
+        /// Returns the values.
+        #[stream(item = i32)]
+        async fn values(&self);
+
+        #[stream(item = &'a str)]
+        async fn words<'a>(&self, s: &'a str);
+
+        #[try_stream(ok = i32, error = String, boxed_local)]
+        async fn checked(&mut self, max: i32);
+
+        #[stream(item = i32)]
+        async fn default_method(&self, n: i32) {
+            for i in 0..n {
+                yield i;
+            }
+        }
+
+        #[stream(item = T)]
+        async fn generic<T: Debug + Send + 'static>(&self, x: T);
+
+        #[stream(item = i32)]
+        async fn no_receiver(n: i32);
+
+        fn name(&self) -> String;
+    }
+
+    pub struct Counter(pub i32);
+
+    #[stream_trait]
+    impl Values for Counter {
+        #[stream(item = i32)]
+        async fn values(&self) {
+            for i in 0..self.0 {
+                yield i;
+            }
+        }
+
+        #[stream(item = &'a str)]
+        async fn words<'a>(&self, s: &'a str) {
+            for word in s.split(' ') {
+                yield word;
+            }
+        }
+
+        #[try_stream(ok = i32, error = String, boxed_local)]
+        async fn checked(&mut self, max: i32) {
+            while self.0 < max {
+                self.0 += 1;
+                yield self.0;
+            }
+            return Err(format!("reached {max}"));
+        }
+
+        #[stream(item = T)]
+        async fn generic<T: Debug + Send + 'static>(&self, x: T) {
+            yield x;
+        }
+
+        #[stream(item = i32)]
+        async fn no_receiver(n: i32) {
+            yield n;
+        }
+
+        fn name(&self) -> String {
+            "counter".into()
+        }
+    }
+
+    #[stream_trait]
+    pub trait Generic<T: Send + 'static, const N: usize = 1> {
+        #[stream(item = [T; N])]
+        async fn arrays(&self);
+    }
+
+    #[stream_trait]
+    impl<T: Clone + Send + Sync + 'static, const N: usize> Generic<T, N> for Vec<T> {
+        #[stream(item = [T; N])]
+        async fn arrays(&self) {
+            for chunk in self.chunks_exact(N) {
+                yield chunk.to_vec().try_into().ok().unwrap();
+            }
+        }
+    }
+}
+
+#[test]
+fn static_dispatch() {
+    use self::traits::{Counter, Generic, Values as _};
+
+    run(async {
+        let mut counter = Counter(3);
+        assert_eq!(collect(counter.values()).await, vec![0, 1, 2]);
+        assert_eq!(collect(counter.words("a b")).await, vec!["a", "b"]);
+        assert_eq!(collect(counter.default_method(2)).await, vec![0, 1]);
+        assert_eq!(collect(counter.generic("x")).await, vec!["x"]);
+        assert_eq!(collect(Counter::no_receiver(4)).await, vec![4]);
+        assert_eq!(
+            collect(counter.checked(5)).await,
+            vec![Ok(4), Ok(5), Err("reached 5".to_owned())]
+        );
+        assert_eq!(counter.name(), "counter");
+
+        let v = vec![1, 2, 3, 4, 5];
+        assert_eq!(collect(Generic::<i32, 2>::arrays(&v)).await, vec![[1, 2], [3, 4]]);
+        assert_eq!(collect(Generic::<i32>::arrays(&v)).await.len(), 5);
+    });
+}
+
+#[test]
+fn dynamic_dispatch() {
+    use self::traits::{Counter, DynGeneric, DynValues};
+
+    fn assert_send<T: Send>(_: &T) {}
+
+    run(async {
+        let mut counter: Box<dyn DynValues> = Box::new(Counter(3));
+        let values = counter.values();
+        assert_send(&values);
+        assert_eq!(collect(values).await, vec![0, 1, 2]);
+        assert_eq!(collect(counter.words("a b")).await, vec!["a", "b"]);
+        assert_eq!(collect(counter.default_method(2)).await, vec![0, 1]);
+        assert_eq!(collect(counter.checked(4)).await, vec![Ok(4), Err("reached 4".to_owned())]);
+
+        let counters: Vec<Box<dyn DynValues>> = vec![Box::new(Counter(1)), Box::new(Counter(2))];
+        let mut all = vec![];
+        for counter in &counters {
+            all.extend(collect(counter.values()).await);
+        }
+        assert_eq!(all, vec![0, 0, 1]);
+
+        let v: &dyn DynGeneric<i32, 2> = &vec![1, 2, 3, 4];
+        assert_eq!(collect(v.arrays()).await, vec![[1, 2], [3, 4]]);
+
+        // Methods that cannot be called on trait objects are still available on concrete types.
+        assert_eq!(collect(DynValues::generic(&Counter(0), 1)).await, vec![1]);
+        assert_eq!(collect(<Counter as DynValues>::no_receiver(1)).await, vec![1]);
+    });
+}
@@ -0,0 +1,11 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines)]
+
+use futures_async_stream::{stream, try_stream};
+
+include!("include/basic.rs");
+
+#[path = "gen/track_stream_size.rs"]
+mod track_stream_size;
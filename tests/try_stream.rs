@@ -3,7 +3,10 @@
 #![allow(unreachable_pub, clippy::try_err, clippy::unused_async)]
 #![feature(coroutines, proc_macro_hygiene, stmt_expr_attributes)]
 
-use std::pin::pin;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    pin::pin,
+};
 
 use futures::{
     future::Future,
@@ -216,3 +219,22 @@ fn test_early_exit() {
         }
     });
 }
+
+#[test]
+fn test_panic() {
+    #[try_stream(ok = i32, error = i32)]
+    async fn stream() {
+        yield 1;
+        panic!("panic in stream");
+    }
+
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+
+    let mut s = pin!(stream());
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(Some(Ok(1))));
+    let res = panic::catch_unwind(AssertUnwindSafe(|| s.as_mut().poll_next(cx)));
+    assert!(res.is_err());
+    // A stream that panicked is terminated.
+    assert_eq!(s.as_mut().poll_next(cx), Poll::Ready(None));
+}
@@ -0,0 +1,17 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![feature(coroutines)]
+
+use futures_async_stream::stream;
+
+// Emulates the expansion of `#[async_trait]` before 0.1.48, which calls a
+// nested function instead of returning an async block.
+#[stream(item = i32)]
+fn stream<'async_trait>()
+-> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'async_trait>> {
+    //~^ ERROR failed to parse the function rewritten by #[async_trait]
+    async fn __stream() {}
+    Box::pin(__stream())
+}
+
+fn main() {}
@@ -0,0 +1,23 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![feature(coroutines)]
+
+use futures_async_stream::stream;
+
+#[stream(item = i32)]
+async fn with_context_escape() {
+    let _cx = with_context!(|cx| cx); //~ ERROR lifetime may not live long enough
+    yield 1;
+}
+
+#[stream(item = i32)]
+async fn poll_escape() {
+    let mut saved = None;
+    poll!(|cx| { //~ ERROR borrowed data escapes outside of closure
+        saved = Some(cx);
+        std::task::Poll::Ready(())
+    });
+    yield 1;
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![feature(coroutines)]
+
+use futures_async_stream::{stream, try_stream};
+
+// Emulates an attribute macro that removed `async` before `#[stream]` was expanded.
+#[stream(item = i32)]
+fn stream() -> impl std::future::Future<Output = ()> { //~ ERROR async stream must be declared as async
+    async {}
+}
+
+#[try_stream(ok = i32, error = ())]
+fn try_stream() -> impl std::future::Future<Output = ()> { //~ ERROR async stream must be declared as async
+    async {}
+}
+
+fn main() {}
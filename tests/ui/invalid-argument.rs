@@ -69,6 +69,92 @@ mod stream {
 
     #[stream(item = i32, boxed_local, boxed)] //~ ERROR `boxed` and `boxed_local` cannot be used at the same time.
     async fn combine() {}
+
+    #[stream(item = i32, max_size)] //~ ERROR expected `max_size = <size>`
+    async fn expected_max_size_eq() {}
+
+    #[stream(item = i32, max_size = )] //~ ERROR expected `max_size = <size>`
+    async fn expected_max_size_value() {}
+
+    #[stream(item = i32, max_size = 8, max_size = 8)] //~ ERROR duplicate `max_size` argument
+    async fn duplicate_max_size() {}
+
+    #[stream(item = i32, auto_box_over = 8, auto_box_over = 8)] //~ ERROR duplicate `auto_box_over` argument
+    async fn duplicate_auto_box_over() {}
+
+    #[stream(item = i32, boxed, auto_box_over = 8)] //~ ERROR `auto_box_over` may not be used together
+    async fn combine_auto_box_over1() {}
+
+    #[stream(item = i32, auto_box_over = 8, boxed_local)] //~ ERROR `auto_box_over` may not be used together
+    async fn combine_auto_box_over2() {}
+
+    #[stream(item = i32, budget = 0)] //~ ERROR `budget` must be greater than zero
+    async fn zero_budget() {}
+
+    #[stream(item = i32, movable, eager)] //~ ERROR `eager` requires `prefetch = <count>`
+    async fn eager_without_prefetch() {}
+
+    #[stream(item = i32, prefetch = 2, eager)] //~ ERROR `eager` requires `movable`
+    async fn eager_without_movable() {}
+
+    #[stream(item = i32, prefetch = 2, clone)] //~ ERROR `clone` may not be used together with `prefetch`
+    async fn combine_prefetch_clone() {}
+
+    #[stream(item = i32, async_init)] //~ ERROR `async_init` requires `init_error = <type>`
+    async fn async_init_without_init_error() {}
+
+    #[stream(item = i32)]
+    async fn init_without_init_error() {
+        init! {} //~ ERROR `init!` requires `init_error = <type>` argument
+    }
+
+    #[stream(item = i32, init_error = ())]
+    async fn init_not_first() {
+        yield 1;
+        init! {} //~ ERROR `init!` must be the first statement
+    }
+
+    #[stream(item = i32, stoppable, stoppable)] //~ ERROR duplicate `stoppable` argument
+    async fn duplicate_stoppable() {}
+
+    #[stream(item = i32, stoppable, recursive)] //~ ERROR `stoppable` may not be used together with `recursive`
+    async fn combine_stoppable_recursive() {}
+
+    #[stream(item = i32)]
+    async fn yield_or_stop_without_stoppable() {
+        let _ = yield_or_stop!(1); //~ ERROR `yield_or_stop!` requires `stoppable` argument
+    }
+
+    #[stream(item = i32, must_consume, must_consume)] //~ ERROR duplicate `must_consume` argument
+    async fn duplicate_must_consume() {}
+
+    #[stream(item = i32, outputs(a = i32))] //~ ERROR `outputs` may not be used together with `item`
+    async fn outputs_with_item() {}
+
+    #[stream(outputs(a = i32, a = i32))] //~ ERROR duplicate output `a`
+    async fn duplicate_output() {}
+
+    #[stream(outputs())] //~ ERROR `outputs` requires at least one output
+    async fn empty_outputs() {}
+
+    #[stream(outputs(a = i32), boxed)] //~ ERROR `outputs` may not be used together with `boxed`
+    async fn outputs_with_boxed() {}
+
+    #[stream(item = enum A { B { x: i32 } })] //~ ERROR variants of `item` enum must have a single unnamed field, e.g., `B(T)`
+    async fn item_enum_named_field() {}
+
+    #[stream(item = enum A { B(i32) = 1 })] //~ ERROR variants of `item` enum may not have discriminants
+    async fn item_enum_discriminant() {}
+
+    #[stream(item = enum A { B(i32), C(i32) })] //~ ERROR duplicate type `i32` in `item` enum
+    async fn item_enum_duplicate_type() {}
+
+    struct S;
+
+    impl S {
+        #[stream(item = enum A { B(i32) })] //~ ERROR `item` enum is not supported on associated functions
+        async fn item_enum_method(&self) {}
+    }
 }
 
 mod try_stream {
@@ -133,6 +219,39 @@ mod try_stream {
 
     #[try_stream(ok = (), error = (), boxed_local, boxed)] //~ ERROR `boxed` and `boxed_local` cannot be used at the same time.
     async fn combine() {}
+
+    #[try_stream(output = Option<()>, output = Option<()>)] //~ ERROR duplicate `output` argument
+    async fn duplicate_output() {}
+
+    #[try_stream(output = Option<()>, ok = ())] //~ ERROR `output` may not be used together with `ok` or `error`
+    async fn output_with_ok() {}
+
+    #[try_stream(output = Option<()>, continue_on_error)] //~ ERROR `continue_on_error` may not be used together with `output`
+    async fn output_with_continue_on_error() {}
+
+    #[try_stream(ok = (), error = (), context, context)] //~ ERROR duplicate `context` argument
+    async fn duplicate_context() {}
+
+    #[try_stream(output = Option<()>, context)] //~ ERROR `context` may not be used together with `output`
+    async fn output_with_context() {}
+
+    #[try_stream(ok = (), error = (), catch_panic, catch_panic)] //~ ERROR duplicate `catch_panic` argument
+    async fn duplicate_catch_panic() {}
+
+    #[try_stream(output = Option<()>, catch_panic)] //~ ERROR `catch_panic` may not be used together with `output`
+    async fn output_with_catch_panic() {}
+
+    #[try_stream(output = Option<()>, checkpoint = ())] //~ ERROR `checkpoint` may not be used together with `output`
+    async fn output_with_checkpoint() {}
+
+    #[try_stream(ok = (), error = (), checkpoint = (), catch_panic)] //~ ERROR `checkpoint` may not be used together with `catch_panic`
+    async fn checkpoint_with_catch_panic() {}
+
+    #[try_stream(ok = (), error = (), checkpoint = (), prefetch = 1)] //~ ERROR `checkpoint` may not be used together with `prefetch`
+    async fn checkpoint_with_prefetch() {}
+
+    #[try_stream(ok = (), error = (), checkpoint = (), must_consume)] //~ ERROR `checkpoint` may not be used together with `must_consume`
+    async fn checkpoint_with_must_consume() {}
 }
 
 fn main() {}
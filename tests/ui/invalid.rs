@@ -107,6 +107,82 @@ mod attribute {
     }
 }
 
+mod yield_err {
+    use futures_async_stream::{stream, try_stream};
+
+    #[stream(item = i32)]
+    async fn in_stream() {
+        yield_err!(1); //~ ERROR `yield_err!` may only be used in #[try_stream]
+    }
+
+    #[try_stream(output = Option<i32>)]
+    async fn with_output() {
+        yield_err!(1); //~ ERROR `yield_err!` may not be used together with the `output` argument
+    }
+}
+
+mod checkpoint {
+    use futures_async_stream::try_stream;
+
+    #[try_stream(ok = i32, error = i32)]
+    async fn without_argument() {
+        checkpoint!(1); //~ ERROR `checkpoint!` requires `checkpoint = <type>` argument
+    }
+}
+
+mod scope {
+    use futures_async_stream::{stream, try_stream};
+
+    #[try_stream(ok = i32, error = i32)]
+    async fn in_try_stream() {
+        scope!(|s| {}); //~ ERROR `scope!` may only be used in #[stream]
+    }
+
+    #[stream(item = i32)]
+    async fn two_arguments() {
+        scope!(|s, t| {}); //~ ERROR expected a closure with one argument
+    }
+
+    #[stream(item = i32)]
+    async fn not_ident() {
+        scope_local!(|(s, t): (i32, i32)| {}); //~ ERROR expected an identifier pattern
+    }
+}
+
+mod outputs {
+    use futures_async_stream::stream;
+
+    #[stream(outputs(a = i32))]
+    async fn plain_yield() {
+        yield 1; //~ ERROR `yield` may not be used together with the `outputs` argument
+    }
+
+    #[stream(item = i32)]
+    async fn without_argument() {
+        yield_to!(a: 1); //~ ERROR `yield_to!` requires `outputs(..)` argument
+    }
+
+    #[stream(outputs(a = i32))]
+    async fn unknown_output() {
+        yield_to!(b: 1); //~ ERROR unknown output `b`
+    }
+
+    #[stream(outputs(a = i32))]
+    async fn size_hint() {
+        size_hint!(1, Some(1)); //~ ERROR `size_hint!` may not be used together with the `outputs` argument
+        yield_to!(a: 1);
+    }
+
+    struct A;
+
+    impl A {
+        #[stream(outputs(a = i32))] //~ ERROR `outputs` argument is not supported on associated functions
+        async fn method(&self) {
+            yield_to!(a: 1);
+        }
+    }
+}
+
 mod item {
     use futures_async_stream::stream;
 
@@ -0,0 +1,26 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![feature(coroutines)]
+
+use futures_async_stream::stream;
+
+// rustc suggests "add `static`" at the attribute: the coroutine it refers to
+// is the one generated by `#[stream]`, so the fix is to remove `movable`.
+#[stream(item = i32, movable)]
+async fn borrow_across_yield() {
+    let v = vec![1, 2, 3];
+    for x in &v { //~ ERROR borrow may still be in use when coroutine yields
+        yield *x;
+    }
+}
+
+#[stream(item = i32, movable)]
+async fn await_not_unpin() {
+    async {}.await; //~ ERROR cannot be unpinned
+    yield 1;
+}
+
+#[stream(item = i32, boxed, clone)] //~ ERROR `clone` may not be used together
+async fn boxed_clone() {}
+
+fn main() {}
@@ -0,0 +1,102 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#![allow(clippy::must_use_candidate)]
+#![feature(coroutines)]
+
+use std::{num::ParseIntError, pin::pin};
+
+use futures::{
+    future::Future,
+    stream::{self, StreamExt as _},
+    task::{Context, Poll, noop_waker},
+};
+use futures_async_stream::try_stream;
+
+fn run<F: Future>(f: F) -> F::Output {
+    let w = noop_waker();
+    let cx = &mut Context::from_waker(&w);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(x) = f.as_mut().poll(cx) {
+            return x;
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Parse(String),
+    Negative(i32),
+}
+
+impl From<ParseIntError> for Error {
+    fn from(e: ParseIntError) -> Self {
+        Self::Parse(e.to_string())
+    }
+}
+
+#[try_stream(ok = i32, error = Error)]
+pub async fn non_negative(records: Vec<i32>) {
+    for x in records {
+        if x < 0 {
+            yield_err!(Error::Negative(x));
+        } else {
+            yield x;
+        }
+    }
+}
+
+#[try_stream(ok = i32, error = Error, continue_on_error)]
+pub async fn parse(records: Vec<&'static str>) {
+    #[for_await]
+    for record in stream::iter(records) {
+        // `?` in the body of loops yields the error and continues.
+        let x: i32 = record.parse()?;
+        if x < 0 {
+            Err(Error::Negative(x))?;
+        }
+        yield x;
+    }
+    // `?` outside loops terminates the stream.
+    "end".parse::<i32>()?;
+    yield 0;
+}
+
+#[try_stream(ok = i32, error = Error, continue_on_error)]
+pub async fn nested(records: Vec<Vec<&'static str>>) {
+    for records in records {
+        // `?` in closures returns from them.
+        let parse = |s: &str| -> Result<i32, Error> { Ok(s.parse::<i32>()?) };
+        let mut sum = 0;
+        for record in records {
+            sum += parse(record)?;
+        }
+        yield sum;
+    }
+}
+
+fn parse_error(s: &str) -> Error {
+    Error::Parse(s.parse::<i32>().unwrap_err().to_string())
+}
+
+#[test]
+fn yield_err() {
+    let mut s = pin!(non_negative(vec![1, -2, 3]));
+    assert_eq!(run(s.next()), Some(Ok(1)));
+    assert_eq!(run(s.next()), Some(Err(Error::Negative(-2))));
+    assert_eq!(run(s.next()), Some(Ok(3)));
+    assert_eq!(run(s.next()), None);
+    assert_eq!(run(s.next()), None);
+}
+
+#[test]
+fn continue_on_error() {
+    assert_eq!(
+        run(parse(vec!["1", "a", "-2", "3"]).collect::<Vec<_>>()),
+        [Ok(1), Err(parse_error("a")), Err(Error::Negative(-2)), Ok(3), Err(parse_error("end"))]
+    );
+    assert_eq!(
+        run(nested(vec![vec!["1", "2"], vec!["3", "b", "4"], vec![]]).collect::<Vec<_>>()),
+        [Ok(3), Err(parse_error("b")), Ok(7), Ok(0)]
+    );
+}
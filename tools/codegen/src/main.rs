@@ -2,8 +2,9 @@
 
 #![allow(clippy::needless_pass_by_value, clippy::wildcard_imports)]
 
-use std::path::Path;
+use std::{fs, path::Path};
 
+use quote::quote;
 use test_helper::{bin_name, codegen::file, function_name};
 
 fn workspace_root() -> &'static Path {
@@ -13,6 +14,7 @@ fn workspace_root() -> &'static Path {
 fn main() {
     gen_assert_impl();
     gen_track_size();
+    gen_track_stream_size();
 }
 
 fn gen_assert_impl() {
@@ -39,3 +41,61 @@ fn gen_track_size() {
     );
     file::write(function_name!(), bin_name!(), workspace_root, path, out);
 }
+
+// Generates a test that reports the size of the streams returned by the
+// public argument-less stream functions in tests/include/basic.rs.
+fn gen_track_stream_size() {
+    const INPUT: &str = "tests/include/basic.rs";
+    const OUTPUT: &str = "tests/gen/track_stream_size.rs";
+
+    let workspace_root = workspace_root();
+    let input = fs::read_to_string(workspace_root.join(INPUT)).unwrap();
+    let mut fns = vec![];
+    for item in syn::parse_file(&input).unwrap().items {
+        let syn::Item::Fn(item) = item else { continue };
+        let is_stream = item
+            .attrs
+            .iter()
+            .any(|attr| attr.path().is_ident("stream") || attr.path().is_ident("try_stream"));
+        if is_stream
+            && matches!(item.vis, syn::Visibility::Public(..))
+            && item.sig.inputs.is_empty()
+            && item.sig.generics.params.is_empty()
+        {
+            fns.push(item.sig.ident);
+        }
+    }
+    assert!(!fns.is_empty(), "no stream functions found in {INPUT}");
+
+    let names = fns.iter().map(ToString::to_string);
+    let txt = OUTPUT.replace(".rs", ".txt");
+    let out = quote! {
+        #![allow(clippy::std_instead_of_alloc, clippy::std_instead_of_core)]
+        use std::{fmt::Write as _, path::Path, string::String};
+        fn write_size<T>(out: &mut String, name: &str, _: &T) {
+            let _ = writeln!(out, "{}: {}", name, std::mem::size_of::<T>());
+        }
+        /// Test the size of the streams returned by stream functions. This is not intended to
+        /// keep a specific size and is intended to be used only as a help in optimization.
+        /// Use `-Z print-type-sizes` to see the size of each suspension point.
+        ///
+        /// Ignore non-64-bit targets due to usize/ptr size, ignore Miri/cargo-careful as we set
+        /// -Z randomize-layout for them, and ignore old rustc as the layout of coroutines
+        /// may differ between compiler versions.
+        #[rustversion::attr(
+            nightly,
+            cfg_attr(any(not(target_pointer_width = "64"), miri, careful), ignore)
+        )]
+        #[rustversion::attr(not(nightly), ignore)]
+        #[test]
+        fn track_stream_size() {
+            let mut out = String::new();
+            #(write_size(&mut out, #names, &super::#fns());)*
+            test_helper::git::assert_diff(
+                Path::new(env!("CARGO_MANIFEST_DIR")).join(#txt),
+                out,
+            );
+        }
+    };
+    file::write(function_name!(), bin_name!(), workspace_root, OUTPUT, out);
+}